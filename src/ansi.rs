@@ -0,0 +1,85 @@
+// Generates ANSI escape sequences that turn one screen into another, for
+// sending a `Grid` to a real terminal.
+
+use grid::{Grid, BLANK_TILE, DEFAULT_ATTR, tile_to_char};
+use palette::ANSI_TO_ATTR;
+
+// Unchanged cells shorter than this between two changes are rewritten rather
+// than skipped with a cursor movement, which takes more bytes.
+const MAX_GAP: u32 = 4;
+
+// Appends the SGR sequence that sets every part of `attr` from scratch.
+pub fn write_sgr(out: &mut Vec<u8>, attr: u8){
+    // swapping red and blue is its own inverse
+    let ansi = |colour: u8| ANSI_TO_ATTR[(colour & 0x07) as usize];
+    out.extend_from_slice(b"\x1b[0");
+    if attr & 0x08 != 0{
+        out.extend_from_slice(b";1");
+    }
+    if attr & 0x80 != 0{
+        out.extend_from_slice(b";5");
+    }
+    out.extend_from_slice(format!(";{};{}m", 30 + ansi(attr), 40 + ansi(attr >> 4)).as_bytes());
+}
+
+fn put_cell(out: &mut Vec<u8>, tile: u16, attr: u8, pen: &mut Option<u8>){
+    if *pen != Some(attr){
+        write_sgr(out, attr);
+        *pen = Some(attr);
+    }
+    let mut buf = [0u8; 4];
+    out.extend_from_slice(tile_to_char(tile).encode_utf8(&mut buf).as_bytes());
+}
+
+fn move_to(out: &mut Vec<u8>, x: u32, y: u32){
+    out.extend_from_slice(format!("\x1b[{};{}H", y + 1, x + 1).as_bytes());
+}
+
+// Appends to `out` what a terminal showing `old` needs to receive to show
+// `new`. With no `old`, or one of a different size, the screen is cleared
+// and drawn from scratch.
+pub fn write_diff(old: Option<&Grid>, new: &Grid, out: &mut Vec<u8>){
+    let old = match old{
+        Some(g) if g.width() == new.width() && g.height() == new.height() => Some(g),
+        _ => {
+            out.extend_from_slice(b"\x1b[0m\x1b[2J\x1b[H");
+            None
+        }
+    };
+
+    // where the remote cursor is after what has been written so far, and the
+    // colours it is writing in, where they are known
+    let mut cursor: Option<(u32, u32)> = if old.is_none() { Some((0, 0)) } else { None };
+    let mut pen: Option<u8>            = if old.is_none() { Some(DEFAULT_ATTR) } else { None };
+    let cell = |g: &Grid, x: u32, y: u32|
+        (g.get(x, y).unwrap_or(BLANK_TILE), g.get_attr(x, y).unwrap_or(DEFAULT_ATTR));
+    for y in 0..new.height(){
+        for x in 0..new.width(){
+            let (tile, attr) = cell(new, x, y);
+            let before = match old{
+                Some(g) => cell(g, x, y),
+                None    => (BLANK_TILE, DEFAULT_ATTR)
+            };
+            if (tile, attr) == before{
+                continue;
+            }
+            match cursor{
+                Some((cx, cy)) if cy == y && cx <= x && x - cx <= MAX_GAP => {
+                    for gap in cx..x{
+                        let (t, a) = cell(new, gap, y);
+                        put_cell(out, t, a, &mut pen);
+                    }
+                },
+                _ => move_to(out, x, y)
+            }
+            put_cell(out, tile, attr, &mut pen);
+            // past the right edge the terminal may or may not have wrapped
+            cursor = if x + 1 < new.width() { Some((x + 1, y)) } else { None };
+        }
+    }
+
+    let (x, y) = new.cursor();
+    if cursor != Some((x, y)){
+        move_to(out, x, y);
+    }
+}
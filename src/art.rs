@@ -0,0 +1,621 @@
+// Scene art files, loaded into a grid along with the hints their SAUCE record
+// gives for showing them.
+
+use std::cmp;
+use std::error;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use cp437;
+use grid::{Grid, TileMap, BLANK_TILE, DEFAULT_ATTR};
+use palette::Palette;
+use sauce;
+use sauce::Sauce;
+use terminal::{Charset, Terminal};
+
+pub const DEFAULT_WIDTH: u32 = 80;
+// BIN files without a SAUCE record are assumed to be this wide.
+pub const DEFAULT_BIN_WIDTH: u32 = 160;
+
+// Where an atlas for a SAUCE font name is looked for.
+const FONT_DIR: &'static str = "fonts";
+
+// The most cells a header or SAUCE record is believed about: 160 columns by
+// more than 26000 rows.
+const MAX_CELLS: u32 = 4 * 1024 * 1024;
+// And the most columns, well past the widest art made.
+const MAX_WIDTH: u32 = 1024;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Format{
+    Ansi,
+    Bin,     // raw character and attribute pairs
+    XBin,
+    PcBoard, // @X colour codes
+    Avatar,  // Avatar/0
+    Native   // screen.init: a byte per cell, the tile plus 0x20
+}
+
+#[derive(Debug)]
+pub enum ArtErr{
+    NotXBin,
+    Truncated,
+    TooWide(u32),      // the width the file claims
+    TooLarge(u32, u32) // and its height
+}
+
+impl fmt::Display for ArtErr{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result{
+        match *self{
+            ArtErr::NotXBin   => write!(f, "not an XBin file"),
+            ArtErr::Truncated => write!(f, "the file is cut short"),
+            ArtErr::TooWide(width) => write!(f, "{} columns is too wide; at most {} can be shown",
+                                             width, MAX_WIDTH),
+            ArtErr::TooLarge(width, height) => write!(f, "{}x{} is too large; at most {} cells can be shown",
+                                                      width, height, MAX_CELLS)
+        }
+    }
+}
+
+impl error::Error for ArtErr{}
+
+// A font embedded in the art, 8 pixels wide and `height` bytes per glyph.
+pub struct BitmapFont{
+    pub height: u16,
+    pub bitmap: Vec<u8>
+}
+
+pub struct Art{
+    pub grid:        Grid,
+    pub sauce:       Option<Sauce>,
+    pub palette:     Palette,
+    pub ice_colours: bool,
+    // When there is a font, tiles index it directly rather than going
+    // through a `TileMap`.
+    pub font:        Option<BitmapFont>
+}
+
+impl Format{
+    // Guesses from the contents, then the extension, then the SAUCE type.
+    pub fn detect(path: &Path, data: &[u8]) -> Format{
+        if data.starts_with(XBIN_ID){
+            return Format::XBin;
+        }
+        let extension = path.extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_lowercase())
+            .unwrap_or(String::new());
+        match &extension[..]{
+            "bin"  => return Format::Bin,
+            "xb"   => return Format::XBin,
+            "pcb"  => return Format::PcBoard,
+            "avt"  => return Format::Avatar,
+            "init" => return Format::Native,
+            _      => ()
+        }
+        match Sauce::parse(data){
+            Some((ref s, _)) if s.data_type == sauce::DATA_BINARY_TEXT => Format::Bin,
+            Some((ref s, _)) if s.data_type == sauce::DATA_XBIN        => Format::XBin,
+            _ => Format::Ansi
+        }
+    }
+}
+
+const XBIN_ID: &'static [u8] = b"XBIN\x1a";
+
+// XBin header flags
+const XBIN_PALETTE:  u8 = 0x01;
+const XBIN_FONT:     u8 = 0x02;
+const XBIN_COMPRESS: u8 = 0x04;
+const XBIN_NONBLINK: u8 = 0x08;
+const XBIN_512:      u8 = 0x10;
+
+// The atlas to draw art in `font` with: `fonts/<font>.json` if there is one,
+// otherwise `default`. The name comes from the file, so one that could lead
+// out of `FONT_DIR` is ignored.
+pub fn atlas_for_font(font: Option<&str>, default: &Path) -> PathBuf{
+    let safe = |name: &str| !name.is_empty() && !name.contains("..") &&
+                            !name.contains(|c: char| c == '/' || c == '\\' || c == ':' || c.is_control());
+    if let Some(font) = font.filter(|&f| safe(f)){
+        let path = PathBuf::from(FONT_DIR).join(format!("{}.json", font));
+        if path.is_file(){
+            return path;
+        }
+    }
+    default.to_path_buf()
+}
+
+impl Art{
+    pub fn load(format: Format, data: &[u8], tiles: &TileMap) -> Result<Art, ArtErr>{
+        match format{
+            Format::Ansi    => Art::from_ansi(data, tiles),
+            Format::Bin     => Art::from_bin(data, tiles),
+            Format::XBin    => Art::from_xbin(data, tiles),
+            Format::PcBoard => Art::from_pcboard(data, tiles),
+            Format::Avatar  => Art::from_avatar(data, tiles),
+            Format::Native  => Ok(Art::from_native(data))
+        }
+    }
+
+    // Blank art to draw on, in the default colours.
+    pub fn blank(width: u32, height: u32) -> Art{
+        Art{
+            grid:        Grid::new(width, height),
+            sauce:       None,
+            palette:     Palette::vga(),
+            ice_colours: false,
+            font:        None
+        }
+    }
+
+    // The tile layout `main` has always read `screen.init` in, without
+    // colours. Rows are 80 cells long.
+    pub fn from_native(data: &[u8]) -> Art{
+        let height = cmp::max((data.len() as u32 + DEFAULT_WIDTH - 1) / DEFAULT_WIDTH, 1);
+        let mut grid = Grid::new(DEFAULT_WIDTH, height);
+        for (i, &b) in data.iter().enumerate(){
+            let i = i as u32;
+            grid.set(i % DEFAULT_WIDTH, i / DEFAULT_WIDTH, b.wrapping_sub(0x20) as u16);
+        }
+        Art{
+            grid:        grid,
+            sauce:       None,
+            palette:     Palette::vga(),
+            ice_colours: false,
+            font:        None
+        }
+    }
+
+    // Plays an .ANS file through the terminal onto a grid as wide as its
+    // SAUCE record says (80 columns otherwise) and as tall as it turns out.
+    pub fn from_ansi(data: &[u8], tiles: &TileMap) -> Result<Art, ArtErr>{
+        let (content, sauce) = Sauce::split(data);
+        let mut grid = match text_grid(content, &sauce){
+            Ok(g)  => g,
+            Err(e) => return Err(e)
+        };
+        let mut terminal = Terminal::with_charset(Charset::Cp437, tiles.clone());
+        terminal.feed(&mut grid, content);
+        Ok(Art::from_text(grid, sauce))
+    }
+
+    // PCBoard text: ANSI art that may also set colours with `@X` and two hex
+    // digits (background, then foreground), clear with `@CLS@` and move to a
+    // column with `@POS:n@`.
+    pub fn from_pcboard(data: &[u8], tiles: &TileMap) -> Result<Art, ArtErr>{
+        let (content, sauce) = Sauce::split(data);
+        let mut grid = match text_grid(content, &sauce){
+            Ok(g)  => g,
+            Err(e) => return Err(e)
+        };
+        let mut terminal = Terminal::with_charset(Charset::Cp437, tiles.clone());
+        let hex = |b: u8| (b as char).to_digit(16).map(|d| d as u8);
+        let mut i = 0;
+        while i < content.len(){
+            let rest = &content[i..];
+            if rest.len() >= 4 && rest[0] == b'@' && (rest[1] == b'X' || rest[1] == b'x'){
+                if let (Some(bg), Some(fg)) = (hex(rest[2]), hex(rest[3])){
+                    grid.set_pen(bg << 4 | fg);
+                    i += 4;
+                    continue;
+                }
+            }
+            if rest.starts_with(b"@CLS@"){
+                grid.erase_in_display(2);
+                grid.set_cursor(0, 0);
+                i += 5;
+                continue;
+            }
+            if rest.starts_with(b"@POS:"){
+                if let Some(end) = rest.iter().skip(5).position(|&b| b == b'@'){
+                    let column = ::std::str::from_utf8(&rest[5..5 + end]).ok()
+                        .and_then(|n| n.parse::<u32>().ok());
+                    if let Some(column) = column{
+                        let (_, y) = grid.cursor();
+                        grid.set_cursor(column.saturating_sub(1), y);
+                        i += 5 + end + 1;
+                        continue;
+                    }
+                }
+            }
+            terminal.feed(&mut grid, &content[i..i + 1]);
+            i += 1;
+        }
+        Ok(Art::from_text(grid, sauce))
+    }
+
+    // Avatar/0 (FSC-0025): ^L clears, ^Y repeats a character and ^V starts
+    // a command; anything else is text, with ANSI understood as well.
+    pub fn from_avatar(data: &[u8], tiles: &TileMap) -> Result<Art, ArtErr>{
+        let (content, sauce) = Sauce::split(data);
+        let mut grid = match text_grid(content, &sauce){
+            Ok(g)  => g,
+            Err(e) => return Err(e)
+        };
+        let mut terminal = Terminal::with_charset(Charset::Cp437, tiles.clone());
+        let arg = |i: usize| content.get(i).cloned().unwrap_or(0);
+        let mut i = 0;
+        while i < content.len(){
+            match content[i]{
+                0x0c => {
+                    grid.clear();
+                    grid.set_pen(0x03);
+                    i += 1;
+                },
+                0x19 => {
+                    let (c, count) = (arg(i + 1), arg(i + 2));
+                    for _ in 0..count{
+                        terminal.feed(&mut grid, &[c]);
+                    }
+                    i += 3;
+                },
+                0x16 => {
+                    let (x, y) = grid.cursor();
+                    i += match arg(i + 1){
+                        0x01 => { grid.set_pen(arg(i + 2) & 0x7f); 3 },
+                        0x02 => { let pen = grid.pen(); grid.set_pen(pen | 0x80); 2 },
+                        0x03 => { grid.set_cursor(x, y.saturating_sub(1)); 2 },
+                        0x04 => { grid.set_cursor(x, y + 1); 2 },
+                        0x05 => { grid.set_cursor(x.saturating_sub(1), y); 2 },
+                        0x06 => { grid.set_cursor(x + 1, y); 2 },
+                        0x07 => { grid.erase_in_line(0); 2 },
+                        0x08 => {
+                            let (row, column) = (arg(i + 2) as u32, arg(i + 3) as u32);
+                            grid.set_cursor(column.saturating_sub(1), row.saturating_sub(1));
+                            4
+                        },
+                        _ => 2
+                    };
+                },
+                _ => {
+                    terminal.feed(&mut grid, &content[i..i + 1]);
+                    i += 1;
+                }
+            }
+        }
+        Ok(Art::from_text(grid, sauce))
+    }
+
+    // BIN: a dump of text-mode video memory, character and attribute pairs
+    // row after row, as wide as the SAUCE record says.
+    pub fn from_bin(data: &[u8], tiles: &TileMap) -> Result<Art, ArtErr>{
+        let (content, sauce) = Sauce::split_binary(data);
+        let width = sauce.as_ref().and_then(|s| s.width()).unwrap_or(DEFAULT_BIN_WIDTH);
+        if width > MAX_WIDTH{
+            return Err(ArtErr::TooWide(width));
+        }
+        let ice_colours = sauce.as_ref().map(|s| s.ice_colours()).unwrap_or(false);
+        let grid = cell_grid(content, width, tiles);
+        Ok(Art{
+            grid:        grid,
+            sauce:       sauce,
+            palette:     Palette::vga(),
+            ice_colours: ice_colours,
+            font:        None
+        })
+    }
+
+    // XBin: a header, then optionally a palette and a font, then cells like
+    // BIN's, optionally run-length compressed.
+    pub fn from_xbin(data: &[u8], tiles: &TileMap) -> Result<Art, ArtErr>{
+        let (content, sauce) = Sauce::split_binary(data);
+        if content.len() < 11 || !content.starts_with(XBIN_ID){
+            return Err(ArtErr::NotXBin);
+        }
+        let width  = content[5] as u32 | (content[6] as u32) << 8;
+        let height = content[7] as u32 | (content[8] as u32) << 8;
+        let font_height = content[9];
+        let flags  = content[10];
+        let mut at = 11;
+        // the header could ask for gigabytes
+        if width > MAX_WIDTH{
+            return Err(ArtErr::TooWide(width));
+        }
+        let count = match width.checked_mul(height){
+            Some(n) if n <= MAX_CELLS => n as usize,
+            _                         => return Err(ArtErr::TooLarge(width, height))
+        };
+
+        let mut palette = Palette::vga();
+        if flags & XBIN_PALETTE != 0{
+            if content.len() < at + 48{
+                return Err(ArtErr::Truncated);
+            }
+            for (i, rgb) in content[at..at + 48].chunks(3).enumerate(){
+                // 6 bit VGA DAC values
+                let scale = |v: u8| (v & 0x3f) << 2 | (v & 0x3f) >> 4;
+                palette.colours[i] = (scale(rgb[0]), scale(rgb[1]), scale(rgb[2]));
+            }
+            at += 48;
+        }
+
+        let mut font = None;
+        if flags & XBIN_FONT != 0{
+            let glyphs = if flags & XBIN_512 != 0 { 512 } else { 256 };
+            let len = glyphs * font_height as usize;
+            if content.len() < at + len{
+                return Err(ArtErr::Truncated);
+            }
+            font = Some(BitmapFont{
+                height: font_height as u16,
+                bitmap: content[at..at + len].to_vec()
+            });
+            at += len;
+        }
+
+        let cells =
+            if flags & XBIN_COMPRESS != 0{
+                match decompress_xbin(&content[at..], count){
+                    Some(c) => c,
+                    None    => return Err(ArtErr::Truncated)
+                }
+            }
+            else{
+                content[at..].to_vec()
+            };
+        if cells.len() < count * 2{
+            return Err(ArtErr::Truncated);
+        }
+
+        let mut grid = Grid::new(width, height);
+        for (i, pair) in cells.chunks(2).take(count).enumerate(){
+            let (x, y) = (i as u32 % width, i as u32 / width);
+            let (c, mut attr) = (pair[0], pair[1]);
+            let tile = match font{
+                // in 512 character fonts the bright bit picks the second half
+                Some(_) if flags & XBIN_512 != 0 => {
+                    let high = (attr & 0x08) as u16;
+                    attr &= !0x08;
+                    c as u16 | high << 5
+                },
+                Some(_) => c as u16,
+                None    => tiles.tile(cp437::to_char(c))
+            };
+            grid.set_cell(x, y, tile, attr);
+        }
+        Ok(Art{
+            grid:        grid,
+            sauce:       sauce,
+            palette:     palette,
+            ice_colours: flags & XBIN_NONBLINK != 0,
+            font:        font
+        })
+    }
+
+    // Crops a grid that text was played onto to the rows actually used.
+    fn from_text(mut grid: Grid, sauce: Option<Sauce>) -> Art{
+        let ice_colours = sauce.as_ref().map(|s| s.ice_colours()).unwrap_or(false);
+        let lines = sauce.as_ref().and_then(|s| s.height()).unwrap_or(0);
+        let used  = cmp::max(last_used_row(&grid, ice_colours), lines);
+        let width = grid.width();
+        grid.resize(width, cmp::max(used, 1));
+        grid.set_cursor(0, 0);
+        grid.set_pen(DEFAULT_ATTR);
+        Art{
+            grid:        grid,
+            sauce:       sauce,
+            palette:     Palette::vga(),
+            ice_colours: ice_colours,
+            font:        None
+        }
+    }
+
+    pub fn width(&self) -> u32{
+        self.grid.width()
+    }
+
+    pub fn height(&self) -> u32{
+        self.grid.height()
+    }
+
+    pub fn caption(&self) -> String{
+        match self.sauce{
+            Some(ref s) => s.caption(),
+            None        => String::from("untitled")
+        }
+    }
+}
+
+// A grid for text to be played onto: as wide as the SAUCE record says (80
+// columns otherwise), with a screenful of room below the last line break for
+// art that moves the cursor down past it.
+fn text_grid(content: &[u8], sauce: &Option<Sauce>) -> Result<Grid, ArtErr>{
+    let width  = sauce.as_ref().and_then(|s| s.width()).unwrap_or(DEFAULT_WIDTH);
+    let lines  = sauce.as_ref().and_then(|s| s.height()).unwrap_or(0);
+    let breaks = content.iter().filter(|&&b| b == b'\n').count() as u32;
+    let height = cmp::max(lines, breaks.saturating_add(1)).saturating_add(25);
+    if width > MAX_WIDTH{
+        return Err(ArtErr::TooWide(width));
+    }
+    match width.checked_mul(height){
+        Some(n) if n <= MAX_CELLS => Ok(Grid::new(width, height)),
+        _                         => Err(ArtErr::TooLarge(width, height))
+    }
+}
+
+// Lays out character and attribute pairs `width` to a row.
+fn cell_grid(cells: &[u8], width: u32, tiles: &TileMap) -> Grid{
+    let count  = (cells.len() / 2) as u32;
+    let height = cmp::max((count + width - 1) / cmp::max(width, 1), 1);
+    let mut grid = Grid::new(width, height);
+    for (i, pair) in cells.chunks(2).filter(|p| p.len() == 2).enumerate(){
+        let i = i as u32;
+        grid.set_cell(i % width, i / width, tiles.tile(cp437::to_char(pair[0])), pair[1]);
+    }
+    grid
+}
+
+// Expands XBin's run-length encoding into `cells` character and attribute
+// pairs. Each run starts with a byte whose top two bits say what repeats
+// and whose low six bits are the length less one.
+fn decompress_xbin(data: &[u8], cells: usize) -> Option<Vec<u8>>{
+    let mut out = Vec::with_capacity(cells * 2);
+    let mut i = 0;
+    while out.len() < cells * 2 && i < data.len(){
+        let (kind, count) = (data[i] >> 6, (data[i] & 0x3f) as usize + 1);
+        i += 1;
+        match kind{
+            0 => { // nothing repeats
+                let run = match data.get(i..i + count * 2){ Some(r) => r, None => return None };
+                out.extend_from_slice(run);
+                i += count * 2;
+            },
+            1 => { // one character, varying attributes
+                let run = match data.get(i..i + 1 + count){ Some(r) => r, None => return None };
+                for &attr in &run[1..]{
+                    out.push(run[0]);
+                    out.push(attr);
+                }
+                i += 1 + count;
+            },
+            2 => { // one attribute, varying characters
+                let run = match data.get(i..i + 1 + count){ Some(r) => r, None => return None };
+                for &c in &run[1..]{
+                    out.push(c);
+                    out.push(run[0]);
+                }
+                i += 1 + count;
+            },
+            _ => { // one cell over and over
+                let run = match data.get(i..i + 2){ Some(r) => r, None => return None };
+                for _ in 0..count{
+                    out.extend_from_slice(run);
+                }
+                i += 2;
+            }
+        }
+    }
+    if out.len() >= cells * 2 { Some(out) } else { None }
+}
+
+// The number of rows down to the last one with a glyph or a coloured
+// background in it.
+fn last_used_row(grid: &Grid, ice_colours: bool) -> u32{
+    let background = if ice_colours { 0xf0 } else { 0x70 };
+    let width = grid.width() as usize;
+    for y in (0..grid.height() as usize).rev(){
+        let row = y * width..(y + 1) * width;
+        if grid.cells()[row.clone()].iter().any(|&t| t != BLANK_TILE)
+            || grid.attrs()[row].iter().any(|&a| a & background != 0){
+            return y as u32 + 1;
+        }
+    }
+    0
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    fn xbin(width: u16, height: u16, flags: u8, body: &[u8]) -> Vec<u8>{
+        let mut data = XBIN_ID.to_vec();
+        data.extend_from_slice(&[width as u8, (width >> 8) as u8, height as u8, (height >> 8) as u8, 16, flags]);
+        data.extend_from_slice(body);
+        data
+    }
+
+    fn sauced(content: &[u8], width: u16, height: u16) -> Vec<u8>{
+        let mut sauce = Sauce::new();
+        sauce.data_type = sauce::DATA_CHARACTER;
+        sauce.tinfo = [width, height, 0, 0];
+        let mut data = content.to_vec();
+        data.push(0x1a);
+        data.extend_from_slice(&sauce.to_bytes());
+        data
+    }
+
+    #[test]
+    fn expands_each_kind_of_xbin_run(){
+        let data = [0x01, b'a', 1, b'b', 2,  // two cells as they are
+                    0x41, b'c', 3, 4,        // one character, two attributes
+                    0x81, 5, b'd', b'e',     // one attribute, two characters
+                    0xc2, b'f', 6];          // one cell three times
+        assert_eq!(decompress_xbin(&data, 9).unwrap(),
+                   b"a\x01b\x02c\x03c\x04d\x05e\x05f\x06f\x06f\x06".to_vec());
+        assert_eq!(decompress_xbin(&data, 10), None);
+        assert_eq!(decompress_xbin(&data[..6], 3), None);
+    }
+
+    #[test]
+    fn loads_a_compressed_xbin(){
+        let art = Art::from_xbin(&xbin(2, 2, XBIN_COMPRESS | XBIN_NONBLINK, &[0xc3, b'A', 0x1f]), &TileMap::ascii()).unwrap();
+        assert_eq!((art.width(), art.height()), (2, 2));
+        assert_eq!(art.grid.get(1, 1), Some(TileMap::ascii().tile('A')));
+        assert_eq!(art.grid.attrs(), &[0x1f; 4]);
+        assert!(art.ice_colours);
+    }
+
+    #[test]
+    fn rejects_xbins_too_large_or_cut_short(){
+        let tiles = TileMap::ascii();
+        match Art::from_xbin(&xbin(1000, 60000, 0, &[]), &tiles){
+            Err(ArtErr::TooLarge(1000, 60000)) => (),
+            other                              => panic!("{:?}", other.map(|_| ()))
+        }
+        match Art::from_xbin(&xbin(2000, 1, 0, &[]), &tiles){
+            Err(ArtErr::TooWide(2000)) => (),
+            other                      => panic!("{:?}", other.map(|_| ()))
+        }
+        for &(flags, ref body) in &[(0, vec![0; 7]), (XBIN_PALETTE, vec![0; 47]),
+                                    (XBIN_FONT, vec![0; 100]), (XBIN_COMPRESS, vec![0xc2, b'A'])]{
+            match Art::from_xbin(&xbin(2, 2, flags, body), &tiles){
+                Err(ArtErr::Truncated) => (),
+                other                  => panic!("{:x}: {:?}", flags, other.map(|_| ()))
+            }
+        }
+        match Art::from_xbin(b"XBIN", &tiles){
+            Err(ArtErr::NotXBin) => (),
+            other                => panic!("{:?}", other.map(|_| ()))
+        }
+    }
+
+    #[test]
+    fn believes_a_sauce_size_only_within_bounds(){
+        let tiles = TileMap::ascii();
+        let art = Art::from_ansi(&sauced(b"hi", 132, 30), &tiles).unwrap();
+        assert_eq!((art.width(), art.height()), (132, 30));
+        match Art::from_ansi(&sauced(b"hi", 65535, 65535), &tiles){
+            Err(ArtErr::TooWide(65535)) => (),
+            other                       => panic!("{:?}", other.map(|_| ()))
+        }
+        match Art::from_ansi(&sauced(b"hi", 1000, 65535), &tiles){
+            Err(ArtErr::TooLarge(1000, _)) => (),
+            other                          => panic!("{:?}", other.map(|_| ()))
+        }
+    }
+
+    #[test]
+    fn reads_pcboard_codes(){
+        let tiles = TileMap::ascii();
+        let art = Art::from_pcboard(b"@X1Fa@POS:4@b@x0", &tiles).unwrap();
+        assert_eq!(art.grid.get(0, 0), Some(tiles.tile('a')));
+        assert_eq!(art.grid.attrs()[0], 0x1f);
+        assert_eq!(art.grid.get(3, 0), Some(tiles.tile('b')));
+        // not a colour code after all, so shown as it is
+        assert_eq!(art.grid.get(4, 0), Some(tiles.tile('@')));
+
+        let art = Art::from_pcboard(b"gone@CLS@x", &tiles).unwrap();
+        assert_eq!(art.grid.get(0, 0), Some(tiles.tile('x')));
+        assert_eq!(art.grid.get(1, 0), Some(BLANK_TILE));
+    }
+
+    #[test]
+    fn reads_avatar_codes(){
+        let tiles = TileMap::ascii();
+        let art = Art::from_avatar(b"\x16\x01\x9ea\x19b\x03\x16\x08\x02\x06c\x16\x03d", &tiles).unwrap();
+        // ^V^A sets the colour, less the blink bit
+        assert_eq!(art.grid.get(0, 0), Some(tiles.tile('a')));
+        assert_eq!(art.grid.attrs()[0], 0x1e);
+        // ^Y repeats
+        for x in 1..4{
+            assert_eq!(art.grid.get(x, 0), Some(tiles.tile('b')));
+        }
+        // ^V^H goes to row 2, column 6, then ^V^C up a row
+        assert_eq!(art.grid.get(5, 1), Some(tiles.tile('c')));
+        assert_eq!(art.grid.get(6, 0), Some(tiles.tile('d')));
+
+        // cut short, the arguments are taken as 0
+        assert!(Art::from_avatar(b"\x19", &tiles).is_ok());
+        assert!(Art::from_avatar(b"\x16\x08", &tiles).is_ok());
+    }
+}
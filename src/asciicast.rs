@@ -0,0 +1,223 @@
+// asciicast v2, asciinema's recording format: a JSON header line followed by
+// a line per event, `[seconds, "o", "output"]`. Sessions record everything
+// their terminal is fed, and recordings are read back for the player.
+
+use rustc_serialize::json;
+use rustc_serialize::json::Json;
+use std::error;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::str;
+use time;
+use time::PreciseTime;
+
+use player::{Frame, FrameData, Recording};
+
+// The largest terminal a recording is believed about, in either direction;
+// the player opens a window that size.
+const MAX_SIZE: u64 = 1024;
+
+#[derive(Debug)]
+pub enum CastErr{
+    Json(usize, json::ParserError), // line number, from 1
+    NotAsciicast,                   // the header isn't a version 2 one
+    TooLarge(u64, u64),             // the width and height the header gives
+    BadEvent(usize)
+}
+
+impl fmt::Display for CastErr{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result{
+        match *self{
+            CastErr::Json(line, ref e) => write!(f, "line {}: {}", line, e),
+            CastErr::NotAsciicast      => write!(f, "not an asciicast version 2 recording"),
+            CastErr::TooLarge(w, h)    => write!(f, "a {}x{} terminal is too large; at most {} either way",
+                                                 w, h, MAX_SIZE),
+            CastErr::BadEvent(line)    => write!(f, "line {}: expected [seconds, code, data]", line)
+        }
+    }
+}
+
+impl error::Error for CastErr{}
+
+#[derive(RustcEncodable)]
+struct Header{
+    version:   u32,
+    width:     u32,
+    height:    u32,
+    timestamp: i64,
+    title:     String
+}
+
+fn encode_err(e: json::EncoderError) -> io::Error{
+    io::Error::new(io::ErrorKind::InvalidData, format!("{:?}", e))
+}
+
+// Writes a session's output to a file as it arrives.
+pub struct Recorder{
+    out:     BufWriter<File>,
+    start:   PreciseTime,
+    pending: Vec<u8> // the start of a UTF-8 sequence the next output finishes
+}
+
+impl Recorder{
+    pub fn create(path: &Path, width: u32, height: u32, title: &str) -> io::Result<Recorder>{
+        let file = match File::create(path){
+            Ok(f)  => f,
+            Err(e) => return Err(e)
+        };
+        let mut recorder = Recorder{
+            out:     BufWriter::new(file),
+            start:   PreciseTime::now(),
+            pending: Vec::new()
+        };
+        let header = Header{
+            version:   2,
+            width:     width,
+            height:    height,
+            timestamp: time::get_time().sec,
+            title:     String::from(title)
+        };
+        match json::encode(&header){
+            Ok(line) => match writeln!(recorder.out, "{}", line){
+                Ok(())  => Ok(recorder),
+                Err(e)  => Err(e)
+            },
+            Err(e) => Err(encode_err(e))
+        }
+    }
+
+    fn event(&mut self, code: &str, data: &str) -> io::Result<()>{
+        let elapsed = match self.start.to(PreciseTime::now()).num_microseconds(){
+            Some(us) => us as f64 / 1_000_000.0,
+            None     => 0.0
+        };
+        let line = match json::encode(&(elapsed, code, data)){
+            Ok(line) => line,
+            Err(e)   => return Err(encode_err(e))
+        };
+        match writeln!(self.out, "{}", line){
+            Ok(())  => self.out.flush(),
+            Err(e)  => Err(e)
+        }
+    }
+
+    // Records output as text. Bytes that aren't UTF-8 become U+FFFD, since
+    // asciicast has no way to carry them, but a sequence split between two
+    // calls is kept whole.
+    pub fn output(&mut self, data: &[u8]) -> io::Result<()>{
+        self.pending.extend_from_slice(data);
+        let end = match str::from_utf8(&self.pending){
+            Err(ref e) if e.error_len().is_none() => e.valid_up_to(),
+            _                                     => self.pending.len()
+        };
+        if end == 0{
+            return Ok(());
+        }
+        let text = String::from_utf8_lossy(&self.pending[..end]).into_owned();
+        self.pending.drain(..end);
+        self.event("o", &text)
+    }
+
+    pub fn resize(&mut self, width: u32, height: u32) -> io::Result<()>{
+        self.event("r", &format!("{}x{}", width, height))
+    }
+}
+
+// Whether a terminal `width` by `height` can be played.
+fn size_ok(width: u64, height: u64) -> bool{
+    width > 0 && height > 0 && width <= MAX_SIZE && height <= MAX_SIZE
+}
+
+fn parse_resize(size: &str) -> Option<(u32, u32)>{
+    let mut parts = size.splitn(2, 'x');
+    match (parts.next().and_then(|w| w.parse().ok()), parts.next().and_then(|h| h.parse().ok())){
+        (Some(w), Some(h)) if size_ok(w, h) => Some((w as u32, h as u32)),
+        _                                   => None
+    }
+}
+
+// Reads a recording's output and resize events. Input and marker events
+// are skipped.
+pub fn parse(text: &str) -> Result<Recording, CastErr>{
+    let mut lines = text.lines().enumerate().filter(|&(_, l)| !l.trim().is_empty());
+    let header = match lines.next().map(|(_, l)| Json::from_str(l)){
+        Some(Ok(header)) => header,
+        Some(Err(e))     => return Err(CastErr::Json(1, e)),
+        None             => return Err(CastErr::NotAsciicast)
+    };
+    let field = |name: &str| header.find(name).and_then(|v| v.as_u64());
+    let (width, height) = match (field("version"), field("width"), field("height")){
+        (Some(2), Some(w), Some(h)) if size_ok(w, h)        => (w as u32, h as u32),
+        (Some(2), Some(w), Some(h)) if w > 0 && h > 0       => return Err(CastErr::TooLarge(w, h)),
+        _                                                   => return Err(CastErr::NotAsciicast)
+    };
+    let title = header.find("title").and_then(|t| t.as_string()).unwrap_or("").to_string();
+
+    let mut frames = Vec::new();
+    for (i, line) in lines{
+        let event = match Json::from_str(line){
+            Ok(event) => event,
+            Err(e)    => return Err(CastErr::Json(i + 1, e))
+        };
+        let event = match event.as_array(){
+            Some(event) if event.len() == 3 => event.clone(),
+            _                               => return Err(CastErr::BadEvent(i + 1))
+        };
+        let (time, data) = match (event[0].as_f64(), event[1].as_string(), event[2].as_string()){
+            (Some(t), Some("o"), Some(d)) => (t, FrameData::Output(d.as_bytes().to_vec())),
+            (Some(t), Some("r"), Some(d)) => match parse_resize(d){
+                Some((w, h)) => (t, FrameData::Resize(w, h)),
+                None         => return Err(CastErr::BadEvent(i + 1))
+            },
+            (Some(_), Some(_), Some(_))   => continue,
+            _                             => return Err(CastErr::BadEvent(i + 1))
+        };
+        frames.push(Frame{ time: time, data: data });
+    }
+    Ok(Recording{
+        width:  width,
+        height: height,
+        title:  title,
+        frames: frames
+    })
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn reads_output_and_resizes(){
+        let recording = parse("{\"version\": 2, \"width\": 80, \"height\": 25, \"title\": \"t\"}\n\
+                               [0.5, \"o\", \"hi\"]\n[0.6, \"i\", \"x\"]\n[1.0, \"r\", \"100x40\"]\n").unwrap();
+        assert_eq!((recording.width, recording.height, &recording.title[..]), (80, 25, "t"));
+        assert_eq!(recording.frames.len(), 2);
+        assert_eq!(recording.frames[1].data, FrameData::Resize(100, 40));
+    }
+
+    #[test]
+    fn rejects_a_terminal_too_large_or_of_no_size(){
+        let header = |w: u64, h: u64| parse(&format!("{{\"version\": 2, \"width\": {}, \"height\": {}}}", w, h));
+        for &(w, h) in &[(4294967296, 25), (80, 1025)]{
+            match header(w, h){
+                Err(CastErr::TooLarge(..)) => (),
+                other                      => panic!("{}x{}: {:?}", w, h, other.map(|_| ()))
+            }
+        }
+        for &(w, h) in &[(0, 25), (80, 0)]{
+            match header(w, h){
+                Err(CastErr::NotAsciicast) => (),
+                other                      => panic!("{}x{}: {:?}", w, h, other.map(|_| ()))
+            }
+        }
+        for size in &["0x25", "4294967296x25", "2000x25", "80"]{
+            match parse(&format!("{{\"version\": 2, \"width\": 80, \"height\": 25}}\n[0, \"r\", \"{}\"]", size)){
+                Err(CastErr::BadEvent(2)) => (),
+                other                     => panic!("{}: {:?}", size, other.map(|_| ()))
+            }
+        }
+    }
+}
@@ -2,9 +2,19 @@ use std;
 use rustc_serialize;
 use image;
 use glium;
+use grid::{TileMap, DEC_GRAPHICS, PAGE_REGULAR, PAGE_BOLD, PAGE_ITALIC, PAGE_BOLD_ITALIC, PAGE_GRAPHICS,
+           on_page, tile_index, tile_page};
+use std::collections::BTreeMap;
+use std::error;
+use std::fmt;
+use std::fs;
 use std::fs::File;
+use std::io;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
 
-#[derive(Copy,Clone)]
+#[derive(Copy,Clone,PartialEq)]
 pub struct AtlasDimensions{
     /* The following data is needed both as integer and as float.
      * Conversion between the two at runtime can be slow, so keep it in both
@@ -35,6 +45,19 @@ impl AtlasDimensions{
             tile_h_f: tile_height   as f32,
         }
     }
+
+    pub fn columns(&self) -> u32{
+        (self.atlas_w_u / self.tile_w_u) as u32
+    }
+
+    pub fn rows(&self) -> u32{
+        (self.atlas_h_u / self.tile_h_u) as u32
+    }
+
+    // How many tiles the sheet holds.
+    pub fn tile_count(&self) -> usize{
+        self.columns() as usize * self.rows() as usize
+    }
 /*   fn new_from_f32(atlas_width: f32, atlas_height: f32,
                     tile_width: f32, tile_height: f32) -> AtlasDimensions{
         AtlasDimensions {
@@ -58,7 +81,19 @@ struct AtlasDescriptor{
     tile_width:  i64,
     tile_height: i64,
     atlas_path:  String,
-    tile_labels: Option<Vec<String>>
+    tile_labels: Option<Vec<String>>,
+    pages:       Option<BTreeMap<String, String>> // page names to images, like "bold": "bold.png"
+}
+
+// The pages a descriptor's `pages` can add to the regular one at `atlas_path`.
+const PAGE_NAMES: [(&'static str, u16); 4] =
+    [("bold",        PAGE_BOLD),
+     ("italic",      PAGE_ITALIC),
+     ("bold_italic", PAGE_BOLD_ITALIC),
+     ("graphics",    PAGE_GRAPHICS)];
+
+fn page_name(page: u16) -> &'static str{
+    PAGE_NAMES.iter().find(|&&(_, p)| p == page).map(|&(name, _)| name).unwrap_or("regular")
 }
 
 #[derive(Debug)]
@@ -66,36 +101,196 @@ pub enum AtlasErr{
     Io(std::io::Error),
     Parse(rustc_serialize::json::DecoderError),
     Image(image::ImageError),
-    IntegerRange
+    IntegerRange,
+    ZeroTileSize,
+    SheetTooSmall(u32, u32, u16, u16), // the image's width and height, then the tile's
+    TooFewTiles(usize, usize),       // tiles in the sheet, labels in the descriptor
+    ImageTooLarge(u32, u32),
+    Texture(glium::texture::TextureCreationError),
+    ImageOutside(String), // the descriptor's atlas_path leads out of the allowed directories
+    UnknownPage(String),
+    PageSize(String, u32, u32) // a page whose image isn't the regular one's size, and its size
 }
 
+impl fmt::Display for AtlasErr{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result{
+        match *self{
+            AtlasErr::Io(ref e)           => write!(f, "{}", e),
+            AtlasErr::Parse(ref e)        => write!(f, "bad descriptor: {}", e),
+            AtlasErr::Image(ref e)        => write!(f, "bad image: {}", e),
+            AtlasErr::IntegerRange        => write!(f, "a size is out of range"),
+            AtlasErr::ZeroTileSize        => write!(f, "tile_width and tile_height can't be 0"),
+            AtlasErr::SheetTooSmall(w, h, tw, th) =>
+                write!(f, "the {}x{} image is smaller than a {}x{} tile", w, h, tw, th),
+            AtlasErr::TooFewTiles(tiles, labels) =>
+                write!(f, "tile_labels names {} tiles but the image holds {}", labels, tiles),
+            AtlasErr::ImageTooLarge(w, h) =>
+                write!(f, "the {}x{} image is too large; atlases can be up to {}x{}", w, h, std::u16::MAX, std::u16::MAX),
+            AtlasErr::Texture(ref e)      => write!(f, "couldn't upload the texture: {}", e),
+            AtlasErr::ImageOutside(ref p) => write!(f, "atlas_path {:?} isn't inside the atlas's directory or an asset root", p),
+            AtlasErr::UnknownPage(ref name) => write!(f, "there's no page called {:?}", name),
+            AtlasErr::PageSize(ref name, w, h) =>
+                write!(f, "the {} page is {}x{}, not the regular page's size", name, w, h)
+        }
+    }
+}
+
+impl error::Error for AtlasErr{}
+
+// An atlas has a regular page and may have others (see `grid::PAGE_SHIFT`),
+// stacked below it in the texture. `dimensions` are those of one page.
 pub struct Atlas{
     pub dimensions: AtlasDimensions,
     pub texture:    glium::texture::Texture2d,
-    pub labels:     Vec<String>
+    pub labels:     Vec<String>,
+    pub pages:      Vec<u16>, // which page each sheet in the texture is, top first
+    pub pixels:     Vec<u8>,  // a copy of the texture, RGBA with the top row first
+    graphics:       Vec<u16>  // the tiles drawn for line drawing; see `drawn_tile`
+}
+
+// An atlas as read from disk or made from a font, before it is uploaded to
+// the GPU; enough to draw it in software.
+pub struct AtlasImage{
+    pub dimensions: AtlasDimensions,
+    pub labels:     Vec<String>,
+    pub pages:      Vec<u16>,
+    pub pixels:     Vec<u8> // RGBA with the top row first, a page after another
+}
+
+// Where each page a tile id can ask for is among `pages`, 3 bits apiece for
+// the shader. Missing pages fall back to the regular one, except that bold
+// italic makes do with bold or italic.
+fn page_map(pages: &[u16]) -> u32{
+    let sheet = |page: u16| pages.iter().position(|&p| p == page);
+    let mut map = 0u32;
+    for &(_, page) in PAGE_NAMES.iter(){
+        let found = match page{
+            PAGE_BOLD_ITALIC => sheet(page).or(sheet(PAGE_BOLD)).or(sheet(PAGE_ITALIC)),
+            _                => sheet(page)
+        };
+        map |= (found.unwrap_or(0) as u32 & 0x7) << (page as u32 * 3);
+    }
+    map
 }
 
 use std::path::Path;
 use std::path::PathBuf;
-impl Atlas{
-    pub fn new_from_file_blocking<F: glium::backend::Facade>
-        (glium: &F, path: &Path) -> Result<Atlas, AtlasErr>
-    {
-        use std::io::Read;
-        let descriptor:AtlasDescriptor =
-            match rustc_serialize::json::decode(&mut
-                                          match File::open(path){
-                                              Ok(mut f)  => {let mut s: String = String::new();
-                                                         match f.read_to_string(&mut s){
-                                                             Ok(_)  => s,
-                                                             Err(e) => return Err(AtlasErr::Io(e))
-                                                         }
-                                              },
-                                              Err(e) => return Err(AtlasErr::Io(e))
-                                          }){
-                Ok(ok) => ok,
-                Err(e) => return Err(AtlasErr::Parse(e))
-            };
+
+// Where the descriptor at `path` says its image is: beside it, or failing
+// that in one of `roots`. Atlases come from strangers, so the image has to
+// stay inside those directories, symlinks and all; the path given back has
+// them resolved, so it is the file that was checked that gets opened.
+fn resolve_image(path: &Path, atlas_path: &str, roots: &[PathBuf]) -> Result<PathBuf, AtlasErr>{
+    use std::path::Component;
+    let relative = Path::new(atlas_path);
+    let plain = relative.components().all(|c| match c{
+        Component::Normal(_) | Component::CurDir => true,
+        _                                         => false // .., / and C:
+    });
+    if !plain || atlas_path.is_empty(){
+        return Err(AtlasErr::ImageOutside(String::from(atlas_path)));
+    }
+    let dir = match path.parent(){
+        Some(d) if !d.as_os_str().is_empty() => d,
+        _                                    => Path::new(".")
+    };
+    let bases: Vec<&Path> = Some(dir).into_iter().chain(roots.iter().map(|r| r.as_path())).collect();
+    let allowed: Vec<PathBuf> = bases.iter().filter_map(|b| b.canonicalize().ok()).collect();
+
+    let mut missing = None;
+    for base in bases.iter(){
+        let image_path = base.join(relative);
+        match image_path.canonicalize(){
+            Ok(ref real) if allowed.iter().any(|a| real.starts_with(a)) => return Ok(real.clone()),
+            Ok(_)  => return Err(AtlasErr::ImageOutside(String::from(atlas_path))),
+            Err(e) => if missing.is_none(){
+                missing = Some(std::io::Error::new(e.kind(), format!("{}: {}", image_path.display(), e)));
+            }
+        }
+    }
+    match missing{
+        Some(e) => Err(AtlasErr::Io(e)),
+        None    => Err(AtlasErr::ImageOutside(String::from(atlas_path)))
+    }
+}
+
+fn read_descriptor(path: &Path) -> Result<AtlasDescriptor, AtlasErr>{
+    use std::io::Read;
+    let descriptor:AtlasDescriptor =
+        match rustc_serialize::json::decode(&mut
+                                      match File::open(path){
+                                          Ok(mut f)  => {let mut s: String = String::new();
+                                                     match f.read_to_string(&mut s){
+                                                         Ok(_)  => s,
+                                                         Err(e) => return Err(AtlasErr::Io(e))
+                                                     }
+                                          },
+                                          Err(e) => return Err(AtlasErr::Io(e))
+                                      }){
+            Ok(ok) => ok,
+            Err(e) => return Err(AtlasErr::Parse(e))
+        };
+    Ok(descriptor)
+}
+
+impl AtlasImage{
+    // Checks that `width` by `height` pixels hold a tile for each of
+    // `labels`. Pixels past the last whole column or row of tiles go unused.
+    pub fn new(width: u32, height: u32, tile_width: u16, tile_height: u16,
+               labels: Vec<String>, pixels: Vec<u8>) -> Result<AtlasImage, AtlasErr>{
+        if tile_width == 0 || tile_height == 0{
+            return Err(AtlasErr::ZeroTileSize);
+        }
+        if width > std::u16::MAX as u32 || height > std::u16::MAX as u32{
+            return Err(AtlasErr::ImageTooLarge(width, height));
+        }
+        if width < tile_width as u32 || height < tile_height as u32{
+            return Err(AtlasErr::SheetTooSmall(width, height, tile_width, tile_height));
+        }
+        let dimensions = AtlasDimensions::new_from_u16(width as u16, height as u16, tile_width, tile_height);
+        if labels.len() > dimensions.tile_count(){
+            return Err(AtlasErr::TooFewTiles(dimensions.tile_count(), labels.len()));
+        }
+        Ok(AtlasImage {
+            dimensions: dimensions,
+            labels:     labels,
+            pages:      vec![PAGE_REGULAR],
+            pixels:     pixels
+        })
+    }
+
+    pub fn tile_count(&self) -> usize{
+        self.dimensions.tile_count()
+    }
+
+    // Adds `page`, `width` by `height` pixels laid out like the regular page,
+    // below the others.
+    pub fn add_page(&mut self, page: u16, width: u32, height: u32, pixels: Vec<u8>) -> Result<(), AtlasErr>{
+        let (page_w, page_h) = (self.dimensions.atlas_w_u as u32, self.dimensions.atlas_h_u as u32);
+        if (width, height) != (page_w, page_h){
+            return Err(AtlasErr::PageSize(String::from(page_name(page)), width, height));
+        }
+        let sheet_h = page_h * (self.pages.len() as u32 + 1);
+        if sheet_h > std::u16::MAX as u32{
+            return Err(AtlasErr::ImageTooLarge(page_w, sheet_h));
+        }
+        self.pages.push(page);
+        self.pixels.extend_from_slice(&pixels);
+        Ok(())
+    }
+
+    // Reads an atlas whose image is beside its descriptor.
+    pub fn load(path: &Path) -> Result<AtlasImage, AtlasErr>{
+        AtlasImage::load_within(path, &[])
+    }
+
+    // Reads an atlas whose image is beside its descriptor or in one of
+    // `roots`, such as a shared directory of sheets.
+    pub fn load_within(path: &Path, roots: &[PathBuf]) -> Result<AtlasImage, AtlasErr>{
+        let descriptor = match read_descriptor(path){
+            Ok(d)  => d,
+            Err(e) => return Err(e)
+        };
 
         if     descriptor.tile_width  < std::u16::MIN as i64 || descriptor.tile_width  > std::u16::MAX as i64
             || descriptor.tile_height < std::u16::MIN as i64 || descriptor.tile_height > std::u16::MAX as i64
@@ -103,27 +298,380 @@ impl Atlas{
             return Err(AtlasErr::IntegerRange);
         }
 
-        let mut image_pathbuf = PathBuf::from(path);
-        image_pathbuf.pop();
-        image_pathbuf.push(&descriptor.atlas_path); // SEC: consider directory traversal
-        let image_path    = image_pathbuf.as_path();
+        let image_path = match resolve_image(path, &descriptor.atlas_path, roots){
+            Ok(p)  => p,
+            Err(e) => return Err(e)
+        };
         let image = match image::open(&image_path){
             Ok(ok) => ok.to_rgba(),
             Err(e) => return Err(AtlasErr::Image(e))
         };
-        let image_dimensions = image.dimensions();
-        let image = glium::texture::RawImage2d::from_raw_rgba_reversed(image.into_raw(), image_dimensions);
-        let texture = glium::texture::Texture2d::new(glium, image).expect("Failed to get glium texture for atlas");
+        let (width, height) = image.dimensions();
+        let mut atlas =
+            match AtlasImage::new(width, height,
+                                  descriptor.tile_width as u16, descriptor.tile_height as u16,
+                                  descriptor.tile_labels.unwrap_or(Vec::new()),
+                                  image.into_raw()){
+                Ok(a)  => a,
+                Err(e) => return Err(e)
+            };
+        for (name, page_path) in descriptor.pages.unwrap_or(BTreeMap::new()){
+            let page = match PAGE_NAMES.iter().find(|&&(n, _)| n == name){
+                Some(&(_, p)) => p,
+                None          => return Err(AtlasErr::UnknownPage(name))
+            };
+            let image = match resolve_image(path, &page_path, roots).and_then(|p| {
+                image::open(&p).map_err(AtlasErr::Image)
+            }){
+                Ok(ok) => ok.to_rgba(),
+                Err(e) => return Err(e)
+            };
+            let (width, height) = image.dimensions();
+            if let Err(e) = atlas.add_page(page, width, height, image.into_raw()){
+                return Err(e);
+            }
+        }
+        Ok(atlas)
+    }
+
+    // Builds an atlas from a bitmap font of 8 pixel wide glyphs, `height`
+    // bytes each with the leftmost pixel in the top bit, such as the fonts
+    // embedded in XBin files. Glyph `n` becomes tile `n`.
+    pub fn from_bitmap_font(height: u16, bitmap: &[u8], labels: Vec<String>) -> Result<AtlasImage, AtlasErr>{
+        const COLUMNS:     usize = 32;
+        const GLYPH_WIDTH: usize = 8;
+        if height == 0{
+            return Err(AtlasErr::ZeroTileSize);
+        }
+        let glyphs = bitmap.len() / height as usize;
+        let rows   = (glyphs + COLUMNS - 1) / COLUMNS;
+        let (atlas_w, atlas_h) = (COLUMNS * GLYPH_WIDTH, rows * height as usize);
+        if atlas_h > std::u16::MAX as usize{
+            return Err(AtlasErr::ImageTooLarge(atlas_w as u32, atlas_h as u32));
+        }
+
+        // white glyphs on a transparent background, top row first
+        let mut pixels = vec![0u8; atlas_w * atlas_h * 4];
+        for glyph in 0..glyphs{
+            let (left, top) = ((glyph % COLUMNS) * GLYPH_WIDTH, (glyph / COLUMNS) * height as usize);
+            for row in 0..height as usize{
+                let bits = bitmap[glyph * height as usize + row];
+                for x in 0..GLYPH_WIDTH{
+                    if bits & (0x80 >> x) != 0{
+                        let i = ((top + row) * atlas_w + left + x) * 4;
+                        for channel in &mut pixels[i..i + 4]{
+                            *channel = 0xff;
+                        }
+                    }
+                }
+            }
+        }
+
+        AtlasImage::new(atlas_w as u32, atlas_h as u32, GLYPH_WIDTH as u16, height, labels, pixels)
+    }
+
+    // Writes the sheet as a PNG beside `path`, any other pages beside that
+    // named for the page, and the descriptor naming them to `path`, so
+    // `load` reads the same atlas back.
+    pub fn save(&self, path: &Path) -> Result<(), AtlasErr>{
+        use std::io::Write;
+        let (width, height) = (self.dimensions.atlas_w_u as u32, self.dimensions.atlas_h_u as u32);
+        let page_size = (width * height * 4) as usize;
+        let mut image_names = Vec::new();
+        for (i, &page) in self.pages.iter().enumerate(){
+            let image_path = match page{
+                PAGE_REGULAR => path.with_extension("png"),
+                _ => {
+                    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("");
+                    path.with_file_name(format!("{}-{}.png", stem, page_name(page)))
+                }
+            };
+            match image_path.file_name().and_then(|n| n.to_str()){
+                Some(n) => image_names.push((page, String::from(n))),
+                None    => return Err(AtlasErr::Io(std::io::Error::new(std::io::ErrorKind::InvalidInput,
+                                                                      "the atlas needs a file name")))
+            }
+            let pixels = match self.pixels.get(i * page_size..(i + 1) * page_size){
+                Some(p) => p,
+                None    => return Err(AtlasErr::Io(std::io::Error::new(std::io::ErrorKind::InvalidData,
+                                                                      "the atlas is missing pixels")))
+            };
+            if let Err(e) = image::save_buffer(&image_path, pixels, width, height, image::ColorType::RGBA(8)){
+                return Err(AtlasErr::Io(e));
+            }
+        }
+        let pages: BTreeMap<String, String> = image_names.iter()
+            .filter(|&&(page, _)| page != PAGE_REGULAR)
+            .map(|&(page, ref name)| (String::from(page_name(page)), name.clone()))
+            .collect();
+        let descriptor = AtlasDescriptor{
+            tile_width:  self.dimensions.tile_w_u as i64,
+            tile_height: self.dimensions.tile_h_u as i64,
+            atlas_path:  image_names[0].1.clone(),
+            tile_labels: if self.labels.is_empty() { None } else { Some(self.labels.clone()) },
+            pages:       if pages.is_empty() { None } else { Some(pages) }
+        };
+        let json = match rustc_serialize::json::encode(&descriptor){
+            Ok(j)  => j,
+            Err(e) => return Err(AtlasErr::Io(std::io::Error::new(std::io::ErrorKind::InvalidData,
+                                                                 format!("{:?}", e))))
+        };
+        match File::create(path).and_then(|mut f| f.write_all(json.as_bytes())){
+            Ok(())  => Ok(()),
+            Err(e)  => Err(AtlasErr::Io(e))
+        }
+    }
+}
+
+impl Atlas{
+    // Uploads an atlas image as a texture, its pages one below another.
+    pub fn upload<F: glium::backend::Facade>
+        (glium: &F, image: AtlasImage) -> Result<Atlas, AtlasErr>
+    {
+        let size = (image.dimensions.atlas_w_u as u32,
+                    image.dimensions.atlas_h_u as u32 * image.pages.len() as u32);
+        let raw = glium::texture::RawImage2d::from_raw_rgba_reversed(image.pixels.clone(), size);
+        let texture = match glium::texture::Texture2d::new(glium, raw){
+            Ok(t)  => t,
+            Err(e) => return Err(AtlasErr::Texture(e))
+        };
+        let tiles = TileMap::from_labels(&image.labels);
+        let has_graphics = image.pages.contains(&PAGE_GRAPHICS);
+        let graphics = (0..DEC_GRAPHICS.len() as u16)
+            .map(|i| on_page(i, PAGE_GRAPHICS))
+            .map(|t| if has_graphics { t } else { tiles.text_tile(t) })
+            .collect();
         Ok(Atlas {
-            dimensions: AtlasDimensions::new_from_u16(image_dimensions.0 as u16,
-                                                      image_dimensions.1 as u16,
-                                                      descriptor.tile_width as u16,
-                                                      descriptor.tile_height as u16),
+            dimensions: image.dimensions,
             texture:    texture,
-            labels: match descriptor.tile_labels {
-                Some(l) => l,
-                None    => Vec::new()
-            }
+            labels:     image.labels,
+            pages:      image.pages,
+            pixels:     image.pixels,
+            graphics:   graphics
         })
     }
+
+    // The tile drawn for `tile`: itself, unless it is line drawing and the
+    // atlas has no graphics page, when it is drawn from the text (see
+    // `TileMap::text_tile`).
+    pub fn drawn_tile(&self, tile: u16) -> u16{
+        match tile_page(tile){
+            PAGE_GRAPHICS => self.graphics.get(tile_index(tile) as usize).cloned().unwrap_or(tile),
+            _             => tile
+        }
+    }
+
+    // The whole texture's layout: every page as one tall sheet.
+    pub fn sheet(&self) -> AtlasDimensions{
+        let d = &self.dimensions;
+        AtlasDimensions::new_from_u16(d.atlas_w_u, d.atlas_h_u * self.pages.len() as u16, d.tile_w_u, d.tile_h_u)
+    }
+
+    // See `page_map`.
+    pub fn page_map(&self) -> u32{
+        page_map(&self.pages)
+    }
+
+    // See `AtlasImage::load_within`.
+    pub fn new_from_file_blocking<F: glium::backend::Facade>
+        (glium: &F, path: &Path, roots: &[PathBuf]) -> Result<Atlas, AtlasErr>
+    {
+        match AtlasImage::load_within(path, roots){
+            Ok(image) => Atlas::upload(glium, image),
+            Err(e)    => Err(e)
+        }
+    }
+
+    // See `AtlasImage::from_bitmap_font`.
+    pub fn from_bitmap_font<F: glium::backend::Facade>
+        (glium: &F, height: u16, bitmap: &[u8], labels: Vec<String>) -> Result<Atlas, AtlasErr>
+    {
+        match AtlasImage::from_bitmap_font(height, bitmap, labels){
+            Ok(image) => Atlas::upload(glium, image),
+            Err(e)    => Err(e)
+        }
+    }
+
+    pub fn tile_count(&self) -> usize{
+        self.dimensions.tile_count()
+    }
+
+    // Looks up a tile by its label in the descriptor's `tile_labels`.
+    pub fn tile_for_label(&self, label: &str) -> Option<u16>{
+        self.labels.iter()
+            .position(|l| l == label)
+            .map(|i| i as u16)
+    }
+}
+
+// Reads an atlas on a worker thread, so the window keeps drawing meanwhile.
+// The texture can only be made on the thread with the GL context, so that
+// polls for the image and uploads it when it's ready.
+pub struct AtlasLoader{
+    path:     PathBuf,
+    receiver: mpsc::Receiver<Result<AtlasImage, AtlasErr>>
+}
+
+impl AtlasLoader{
+    // Starts reading the atlas at `path` as `AtlasImage::load_within` does.
+    pub fn spawn(path: &Path, roots: &[PathBuf]) -> AtlasLoader{
+        let (sender, receiver) = mpsc::channel();
+        let failed = sender.clone();
+        let (descriptor, roots) = (path.to_path_buf(), roots.to_vec());
+        let worker = thread::Builder::new()
+            .name(String::from("atlas loader"))
+            .spawn(move || { let _ = sender.send(AtlasImage::load_within(&descriptor, &roots)); });
+        if let Err(e) = worker{
+            let _ = failed.send(Err(AtlasErr::Io(e)));
+        }
+        AtlasLoader{
+            path:     path.to_path_buf(),
+            receiver: receiver
+        }
+    }
+
+    pub fn path(&self) -> &Path{
+        &self.path
+    }
+
+    // The uploaded atlas once the worker is done, or `None` while it isn't.
+    pub fn poll<F: glium::backend::Facade>(&self, glium: &F) -> Option<Result<Atlas, AtlasErr>>{
+        match self.receiver.try_recv(){
+            Ok(Ok(image)) => Some(Atlas::upload(glium, image)),
+            Ok(Err(e))    => Some(Err(e)),
+            Err(mpsc::TryRecvError::Empty) => None,
+            Err(mpsc::TryRecvError::Disconnected) =>
+                Some(Err(AtlasErr::Io(io::Error::new(io::ErrorKind::Other, "the loader stopped"))))
+        }
+    }
+}
+
+// How often `AtlasWatcher` looks at the files.
+const WATCH_INTERVAL_MS: u64 = 500;
+
+fn modified(path: &Path) -> Option<SystemTime>{
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+// An atlas's descriptor and whichever of the images it names can be found,
+// with when each was last modified.
+fn atlas_files(path: &Path, roots: &[PathBuf]) -> Vec<(PathBuf, Option<SystemTime>)>{
+    let mut files = vec![path.to_path_buf()];
+    if let Ok(descriptor) = read_descriptor(path){
+        let images = Some(descriptor.atlas_path).into_iter()
+            .chain(descriptor.pages.unwrap_or(BTreeMap::new()).into_iter().map(|(_, image)| image));
+        files.extend(images.filter_map(|image| resolve_image(path, &image, roots).ok()));
+    }
+    files.into_iter().map(|f| { let time = modified(&f); (f, time) }).collect()
+}
+
+// Notices when an atlas's files change on disk, by polling their
+// modification times.
+pub struct AtlasWatcher{
+    path:    PathBuf,
+    roots:   Vec<PathBuf>,
+    files:   Vec<(PathBuf, Option<SystemTime>)>,
+    checked: Instant
+}
+
+impl AtlasWatcher{
+    pub fn new(path: &Path, roots: &[PathBuf]) -> AtlasWatcher{
+        AtlasWatcher{
+            path:    path.to_path_buf(),
+            roots:   roots.to_vec(),
+            files:   atlas_files(path, roots),
+            checked: Instant::now()
+        }
+    }
+
+    pub fn path(&self) -> &Path{
+        &self.path
+    }
+
+    // Whether the descriptor or image has changed since the last time this
+    // said so. Looks at most every `WATCH_INTERVAL_MS`, so it can be called
+    // every frame.
+    pub fn changed(&mut self) -> bool{
+        if self.checked.elapsed() < Duration::from_millis(WATCH_INTERVAL_MS){
+            return false;
+        }
+        self.checked = Instant::now();
+        if self.files.iter().all(|&(ref f, time)| modified(f) == time){
+            return false;
+        }
+        // the descriptor may now name another image
+        self.files = atlas_files(&self.path, &self.roots);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use grid::char_to_tile;
+    use std::env;
+    use std::fs;
+    use std::os::unix::fs::symlink;
+    use std::process;
+
+    // A directory holding an atlas descriptor and a sheet, with another
+    // sheet beside it, outside, and the descriptor's path by way of a link
+    // to its directory.
+    fn atlas_dir() -> (PathBuf, PathBuf){
+        let top = env::temp_dir().join(format!("textbbs-atlas-{}", process::id()));
+        let _ = fs::remove_dir_all(&top);
+        let dir = top.join("atlas");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("sheet.png"), b"").unwrap();
+        fs::write(top.join("secret.png"), b"").unwrap();
+        symlink(top.join("secret.png"), dir.join("link.png")).unwrap();
+        symlink(&dir, top.join("alias")).unwrap();
+        (top.clone(), top.join("alias").join("atlas.json"))
+    }
+
+    #[test]
+    fn keeps_the_image_inside_the_atlas_directory(){
+        let (top, descriptor) = atlas_dir();
+        let outside = top.join("secret.png").to_string_lossy().into_owned();
+        for image in &[&outside[..], "../secret.png", "./../secret.png", "link.png", ""]{
+            match resolve_image(&descriptor, image, &[]){
+                Err(AtlasErr::ImageOutside(ref given)) if given == image => (),
+                other => panic!("{}: {:?}", image, other)
+            }
+        }
+        // the checked path, symlinks resolved
+        let found = resolve_image(&descriptor, "sheet.png", &[]).unwrap();
+        let real = top.canonicalize().unwrap().join("atlas").join("sheet.png");
+        let _ = fs::remove_dir_all(&top);
+        assert_eq!(found, real);
+    }
+
+    #[test]
+    fn loads_the_shipped_atlas(){
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("atlas.json");
+        let image = match AtlasImage::load_within(&path, &[]){
+            Ok(image) => image,
+            Err(e)    => panic!("{}: {}", path.display(), e)
+        };
+        assert_eq!((image.dimensions.tile_w_u, image.dimensions.tile_h_u), (10, 14));
+        // unlabelled, so drawn with the ASCII tiles
+        assert!(image.labels.is_empty());
+        assert!(image.dimensions.tile_count() > char_to_tile('~') as usize);
+    }
+
+    #[test]
+    fn ignores_pixels_past_the_last_whole_tile(){
+        let image = AtlasImage::new(25, 20, 8, 16, Vec::new(), vec![0; 25 * 20 * 4]).unwrap();
+        assert_eq!((image.dimensions.columns(), image.dimensions.rows()), (3, 1));
+
+        let labels = (0..4).map(|i| i.to_string()).collect();
+        match AtlasImage::new(25, 20, 8, 16, labels, vec![0; 25 * 20 * 4]){
+            Err(AtlasErr::TooFewTiles(3, 4)) => (),
+            other                            => panic!("{:?}", other.map(|_| ()))
+        }
+        match AtlasImage::new(7, 20, 8, 16, Vec::new(), vec![0; 7 * 20 * 4]){
+            Err(AtlasErr::SheetTooSmall(7, 20, 8, 16)) => (),
+            other                                      => panic!("{:?}", other.map(|_| ()))
+        }
+    }
 }
@@ -0,0 +1,247 @@
+// Sound: tones synthesized as 16-bit mono PCM, played by the system's audio
+// player or written to a WAV file.
+
+use std::f32::consts::PI;
+use std::fs::File;
+use std::io;
+use std::io::{Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub const SAMPLE_RATE: u32 = 44100;
+
+// How long a tone takes to fade in and out, so it doesn't click.
+const FADE_MS: u32 = 5;
+
+// The least time between sounds a `Player` starts a player for; a host
+// ringing the bell in a loop would otherwise start hundreds.
+const GAP_MS: u64 = 100;
+
+// A sine tone, as the bell rings.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Beep{
+    pub pitch:  f32, // Hz
+    pub length: u32, // milliseconds
+    pub volume: f32  // 0 to 1
+}
+
+impl Beep{
+    pub fn standard() -> Beep{
+        Beep{ pitch: 880.0, length: 150, volume: 0.5 }
+    }
+
+    pub fn samples(&self) -> Vec<i16>{
+        let count = (SAMPLE_RATE as u64 * self.length as u64 / 1000) as usize;
+        let fade = ::std::cmp::min(count / 2, (SAMPLE_RATE * FADE_MS / 1000) as usize);
+        let amplitude = self.volume.max(0.0).min(1.0) * i16::max_value() as f32;
+        (0..count).map(|i| {
+            let envelope = match ::std::cmp::min(i, count - i){
+                edge if edge < fade => edge as f32 / fade as f32,
+                _                   => 1.0
+            };
+            let phase = 2.0 * PI * self.pitch * i as f32 / SAMPLE_RATE as f32;
+            (phase.sin() * envelope * amplitude) as i16
+        }).collect()
+    }
+}
+
+fn put_u16(out: &mut Vec<u8>, n: u16){
+    out.push(n as u8);
+    out.push((n >> 8) as u8);
+}
+
+fn put_u32(out: &mut Vec<u8>, n: u32){
+    for i in 0..4{
+        out.push((n >> (i * 8)) as u8);
+    }
+}
+
+// The start of a WAV file of `count` samples of 16-bit mono PCM at
+// `SAMPLE_RATE`.
+pub fn wav_header(count: u32) -> Vec<u8>{
+    let data = count.saturating_mul(2).min(u32::max_value() - 36);
+    let mut out = Vec::with_capacity(44);
+    out.extend_from_slice(b"RIFF");
+    put_u32(&mut out, 36 + data);
+    out.extend_from_slice(b"WAVEfmt ");
+    put_u32(&mut out, 16);              // the size of this chunk
+    put_u16(&mut out, 1);               // PCM
+    put_u16(&mut out, 1);               // channels
+    put_u32(&mut out, SAMPLE_RATE);
+    put_u32(&mut out, SAMPLE_RATE * 2); // bytes a second
+    put_u16(&mut out, 2);               // bytes a sample
+    put_u16(&mut out, 16);              // bits a sample
+    out.extend_from_slice(b"data");
+    put_u32(&mut out, data);
+    out
+}
+
+// Appends `samples` as they are laid out in a WAV file.
+pub fn put_samples(out: &mut Vec<u8>, samples: &[i16]){
+    for &s in samples{
+        put_u16(out, s as u16);
+    }
+}
+
+pub fn to_wav(samples: &[i16]) -> Vec<u8>{
+    let mut out = wav_header(samples.len() as u32);
+    put_samples(&mut out, samples);
+    out
+}
+
+// Starts `command`, like "aplay -q", to be given a WAV file on its standard
+// input.
+pub fn spawn_player(command: &str) -> io::Result<Child>{
+    let words: Vec<&str> = command.split_whitespace().collect();
+    let (program, args) = match words.split_first(){
+        Some(c) => c,
+        None    => return Err(io::Error::new(io::ErrorKind::InvalidInput, "no audio player"))
+    };
+    match Command::new(program).args(args)
+                               .stdin(Stdio::piped())
+                               .stdout(Stdio::null())
+                               .stderr(Stdio::null())
+                               .spawn(){
+        Ok(c)  => Ok(c),
+        Err(e) => Err(io::Error::new(e.kind(), format!("running {}: {}", program, e)))
+    }
+}
+
+// Somewhere sounds go.
+pub trait AudioSink{
+    fn play(&mut self, samples: &[i16]) -> io::Result<()>;
+}
+
+// Runs a command, like "aplay -q", for each sound with the sound as a WAV
+// file on its standard input, without waiting for it to finish. Sounds
+// coming faster than one every `GAP_MS` are dropped.
+pub struct Player{
+    command: String,
+    playing: Vec<Child>,
+    last:    Option<Instant> // when the last sound started
+}
+
+impl Player{
+    pub fn new(command: &str) -> Player{
+        Player{
+            command: String::from(command),
+            playing: Vec::new(),
+            last:    None
+        }
+    }
+}
+
+impl AudioSink for Player{
+    fn play(&mut self, samples: &[i16]) -> io::Result<()>{
+        let now = Instant::now();
+        match self.last{
+            Some(last) if now.duration_since(last) < Duration::from_millis(GAP_MS) => return Ok(()),
+            _                                                                      => self.last = Some(now)
+        }
+
+        // forget the players that have finished
+        let mut i = 0;
+        while i < self.playing.len(){
+            match self.playing[i].try_wait(){
+                Ok(None) => i += 1,
+                _        => { self.playing.swap_remove(i); }
+            }
+        }
+
+        let mut child = match spawn_player(&self.command){
+            Ok(c)  => c,
+            Err(e) => return Err(e)
+        };
+        // players read as they play, so a long sound would hold up the caller
+        if let Some(mut stdin) = child.stdin.take(){
+            let wav = to_wav(samples);
+            thread::spawn(move || stdin.write_all(&wav));
+        }
+        self.playing.push(child);
+        Ok(())
+    }
+}
+
+// Collects every sound into one WAV file, created with the first; each is
+// appended and the header patched to match, so it is always whole. For
+// checking what would have been heard.
+pub struct WavFile{
+    path:  PathBuf,
+    file:  Option<File>,
+    count: u32 // samples written
+}
+
+impl WavFile{
+    pub fn new(path: &Path) -> WavFile{
+        WavFile{
+            path:  path.to_path_buf(),
+            file:  None,
+            count: 0
+        }
+    }
+}
+
+impl AudioSink for WavFile{
+    fn play(&mut self, samples: &[i16]) -> io::Result<()>{
+        if self.file.is_none(){
+            match File::create(&self.path).and_then(|mut f| f.write_all(&wav_header(0)).map(|_| f)){
+                Ok(f)  => self.file = Some(f),
+                Err(e) => return Err(e)
+            }
+        }
+        let file = match self.file{
+            Some(ref mut f) => f,
+            None            => return Ok(())
+        };
+        let mut bytes = Vec::with_capacity(samples.len() * 2);
+        put_samples(&mut bytes, samples);
+        self.count = self.count.saturating_add(samples.len() as u32);
+        let header = wav_header(self.count);
+        file.seek(SeekFrom::End(0))
+            .and_then(|_| file.write_all(&bytes))
+            .and_then(|_| file.seek(SeekFrom::Start(0)))
+            .and_then(|_| file.write_all(&header))
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    fn u32_at(bytes: &[u8], at: usize) -> u32{
+        (0..4).fold(0, |n, i| n | (bytes[at + i] as u32) << (i * 8))
+    }
+
+    #[test]
+    fn describes_16_bit_mono_in_the_header(){
+        let header = wav_header(10);
+        assert_eq!(header.len(), 44);
+        assert_eq!(&header[..4], b"RIFF");
+        assert_eq!(u32_at(&header, 4), 36 + 20);
+        assert_eq!(&header[8..16], b"WAVEfmt ");
+        assert_eq!(u32_at(&header, 24), SAMPLE_RATE);
+        assert_eq!(&header[36..40], b"data");
+        assert_eq!(u32_at(&header, 40), 20);
+    }
+
+    #[test]
+    fn keeps_the_wav_file_whole_after_each_beep(){
+        let path = env::temp_dir().join(format!("textbbs-bell-{}.wav", process::id()));
+        let beep = Beep::standard().samples();
+        let mut wav = WavFile::new(&path);
+        for n in 1..4{
+            wav.play(&beep).unwrap();
+            let bytes = fs::read(&path).unwrap();
+            let data = (n * beep.len() * 2) as u32;
+            assert_eq!(bytes.len() as u32, 44 + data);
+            assert_eq!(u32_at(&bytes, 4), 36 + data);
+            assert_eq!(u32_at(&bytes, 40), data);
+        }
+        let _ = fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,374 @@
+// Boot scripts: a screen that plays out over time, like a machine booting,
+// rather than appearing all at once. A script is a channel, so what it
+// prints goes through the terminal as a host's output would.
+//
+// Each line of a script is text to print, followed by a new line, or one of:
+//     @print <text>            print without the new line
+//     @speed <ms>              wait this long after each character printed
+//                              from here on; 0 prints lines at once
+//     @type <min> <max> <text> type the text out, waiting a random time
+//                              between min and max ms after each character
+//     @pause <ms>
+//     @at <column> <row>       move the cursor, counting from 1
+//     @clear                   clear the screen and go to the top left
+//     @wait                    wait for a key
+//     @loop                    start again from the top
+//     # a comment
+//
+// In text, \e is escape, \n a new line, \xNN that byte and \ anything else
+// that character, so "\@" prints an @ and "\\" a backslash. Once the script
+// ends, keys are echoed as with nothing attached.
+
+use rand;
+use rand::Rng;
+use std::error;
+use std::fmt;
+use std::str::FromStr;
+use time::PreciseTime;
+
+use session::{Channel, Loopback};
+
+#[derive(Debug)]
+pub enum BootErr{
+    UnknownCommand(usize, String), // line number, from 1
+    BadArgument(usize, String),    // and the command
+    BadEscape(usize)
+}
+
+impl fmt::Display for BootErr{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result{
+        match *self{
+            BootErr::UnknownCommand(line, ref c) => write!(f, "line {}: no command @{}", line, c),
+            BootErr::BadArgument(line, ref c)    => write!(f, "line {}: bad arguments to @{}", line, c),
+            BootErr::BadEscape(line)             => write!(f, "line {}: \\x needs two hex digits", line)
+        }
+    }
+}
+
+impl error::Error for BootErr{}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Step{
+    Print(Vec<u8>),          // at the current speed
+    Type(u32, u32, Vec<u8>), // waiting from min to max ms after each character
+    Code(Vec<u8>),           // at once, whatever the speed
+    Speed(u32),
+    Pause(u32),
+    Wait,
+    Loop
+}
+
+fn unescape(text: &str, line: usize) -> Result<Vec<u8>, BootErr>{
+    let mut out = Vec::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next(){
+        match c{
+            '\\' => match chars.next(){
+                Some('e') => out.push(0x1b),
+                Some('n') => out.extend_from_slice(b"\r\n"),
+                Some('x') => {
+                    let hex: String = chars.by_ref().take(2).collect();
+                    match u8::from_str_radix(&hex, 16){
+                        Ok(b) if hex.len() == 2 => out.push(b),
+                        _                       => return Err(BootErr::BadEscape(line))
+                    }
+                },
+                Some(c) => { let mut b = [0; 4]; out.extend_from_slice(c.encode_utf8(&mut b).as_bytes()); },
+                None    => out.push(b'\\')
+            },
+            c => { let mut b = [0; 4]; out.extend_from_slice(c.encode_utf8(&mut b).as_bytes()); }
+        }
+    }
+    Ok(out)
+}
+
+// Splits the first `count` numbers off `args`, returning them and the rest.
+fn numbers<'a>(args: &'a str, count: usize) -> Option<(Vec<u32>, &'a str)>{
+    let mut rest = args;
+    let mut found = Vec::new();
+    for _ in 0..count{
+        let trimmed = rest.trim_start();
+        let end = trimmed.find(' ').unwrap_or(trimmed.len());
+        match u32::from_str(&trimmed[..end]){
+            Ok(n)  => found.push(n),
+            Err(_) => return None
+        }
+        rest = &trimmed[end..];
+    }
+    Some((found, rest))
+}
+
+pub fn parse(script: &str) -> Result<Vec<Step>, BootErr>{
+    let mut steps = Vec::new();
+    for (i, text) in script.lines().enumerate(){
+        let line = i + 1;
+        if text.starts_with('#'){
+            continue;
+        }
+        if !text.starts_with('@'){
+            let mut bytes = match unescape(text, line){
+                Ok(b)  => b,
+                Err(e) => return Err(e)
+            };
+            bytes.extend_from_slice(b"\r\n");
+            steps.push(Step::Print(bytes));
+            continue;
+        }
+
+        let (command, args) = match text[1..].find(' '){
+            Some(at) => (&text[1..at + 1], &text[at + 2..]),
+            None     => (&text[1..], "")
+        };
+        let bad = || BootErr::BadArgument(line, String::from(command));
+        let wanted = match command{
+            "speed" | "pause" => 1,
+            "at" | "type"     => 2,
+            _                 => 0
+        };
+        let (n, rest) = match numbers(args, wanted){
+            Some(found) => found,
+            None        => return Err(bad())
+        };
+        let takes_text = command == "print" || command == "type";
+        if !takes_text && !rest.trim().is_empty(){
+            return Err(bad());
+        }
+        // one space separates the numbers from the text; the rest is text
+        let rest = if wanted > 0 && rest.starts_with(' ') { &rest[1..] } else { rest };
+        let step = match command{
+            "print" => match unescape(rest, line){
+                Ok(b)  => Step::Print(b),
+                Err(e) => return Err(e)
+            },
+            // a wait of u32::MAX ms is past what a range up to it can hold
+            "type" if n[0] <= n[1] && n[1] < u32::max_value() => match unescape(rest, line){
+                Ok(b)  => Step::Type(n[0], n[1], b),
+                Err(e) => return Err(e)
+            },
+            "type"  => return Err(bad()),
+            "speed" => Step::Speed(n[0]),
+            "pause" => Step::Pause(n[0]),
+            "at" if n[0] > 0 && n[1] > 0 => Step::Code(format!("\x1b[{};{}H", n[1], n[0]).into_bytes()),
+            "at"    => return Err(bad()),
+            "clear" => Step::Code(b"\x1b[2J\x1b[H".to_vec()),
+            "wait"  => Step::Wait,
+            "loop"  => Step::Loop,
+            _       => return Err(BootErr::UnknownCommand(line, String::from(command)))
+        };
+        steps.push(step);
+    }
+    Ok(steps)
+}
+
+// The length of the first thing in `bytes` that is printed at once: an
+// escape sequence, a whole UTF-8 character or a byte; and whether it shows
+// as a character.
+fn unit(bytes: &[u8]) -> (usize, bool){
+    match bytes[0]{
+        0x1b if bytes.get(1) == Some(&b'[') => {
+            match bytes[2..].iter().position(|&b| b >= 0x40 && b <= 0x7e){
+                Some(end) => (end + 3, false),
+                None      => (bytes.len(), false)
+            }
+        },
+        0x1b => (::std::cmp::min(2, bytes.len()), false),
+        b if b < 0x20 || b == 0x7f => (1, false),
+        b if b >= 0xc0 => (1 + bytes[1..].iter().take_while(|&&b| b & 0xc0 == 0x80).count(), true),
+        _ => (1, true)
+    }
+}
+
+// Plays a script out, as a channel.
+pub struct BootScript{
+    steps: Vec<Step>,
+    step:  usize,
+    at:    usize,    // how much of the step's text has been printed
+    speed: u32,
+    clock: f64,      // ms since the start
+    due:   f64,      // when the next thing happens
+    keyed: bool,     // a key was pressed while waiting for one
+    last:  Option<PreciseTime>,
+    echo:  Loopback  // for once it's over
+}
+
+impl BootScript{
+    pub fn new(steps: Vec<Step>) -> BootScript{
+        BootScript{
+            steps: steps,
+            step:  0,
+            at:    0,
+            speed: 0,
+            clock: 0.0,
+            due:   0.0,
+            keyed: false,
+            last:  None,
+            echo:  Loopback::new()
+        }
+    }
+
+    pub fn is_finished(&self) -> bool{
+        self.step >= self.steps.len()
+    }
+
+    fn next_step(&mut self){
+        self.step += 1;
+        self.at = 0;
+    }
+
+    // Appends what falls due in the next `elapsed` ms.
+    pub fn run(&mut self, elapsed: f64, buf: &mut Vec<u8>){
+        self.clock += elapsed;
+        while self.step < self.steps.len() && self.due <= self.clock{
+            let delay = match self.steps[self.step]{
+                Step::Print(ref text) | Step::Type(_, _, ref text) if self.at >= text.len() => None,
+                Step::Print(ref text) if self.speed == 0 => {
+                    buf.extend_from_slice(&text[self.at..]);
+                    self.at = text.len();
+                    None
+                },
+                Step::Print(ref text) => {
+                    let (len, shows) = unit(&text[self.at..]);
+                    buf.extend_from_slice(&text[self.at..self.at + len]);
+                    self.at += len;
+                    if shows { Some(self.speed) } else { Some(0) }
+                },
+                Step::Type(min, max, ref text) => {
+                    let (len, shows) = unit(&text[self.at..]);
+                    buf.extend_from_slice(&text[self.at..self.at + len]);
+                    self.at += len;
+                    if shows { Some(rand::thread_rng().gen_range(min, max + 1)) } else { Some(0) }
+                },
+                Step::Code(ref code) => { buf.extend_from_slice(code); None },
+                Step::Speed(ms) => { self.speed = ms; None },
+                Step::Pause(ms) => { self.due += ms as f64; self.next_step(); continue },
+                Step::Wait if self.keyed => {
+                    // the time spent waiting doesn't count against what follows
+                    self.keyed = false;
+                    self.due = self.clock;
+                    None
+                },
+                Step::Wait => break,
+                Step::Loop => {
+                    // one time round at most, so a script with nothing to
+                    // wait on can't hold things up
+                    self.step = 0;
+                    self.at = 0;
+                    break;
+                }
+            };
+            match delay{
+                Some(ms) => self.due += ms as f64,
+                None     => self.next_step()
+            }
+        }
+    }
+}
+
+impl Channel for BootScript{
+    fn send(&mut self, bytes: &[u8]){
+        if self.is_finished(){
+            self.echo.send(bytes);
+        }
+        // only once it is waiting; keys pressed before then don't count
+        else if self.steps[self.step] == Step::Wait && self.due <= self.clock && !bytes.is_empty(){
+            self.keyed = true;
+        }
+    }
+
+    fn receive(&mut self, buf: &mut Vec<u8>) -> bool{
+        let now = PreciseTime::now();
+        let elapsed = match self.last.map(|last| last.to(now).num_microseconds()){
+            Some(Some(us)) => us as f64 / 1000.0,
+            _              => 0.0
+        };
+        self.last = Some(now);
+        self.run(elapsed, buf);
+        self.echo.receive(buf)
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    fn printed(script: &str) -> Vec<u8>{
+        match parse(script).unwrap()[0]{
+            Step::Print(ref text) | Step::Type(_, _, ref text) => text.clone(),
+            ref step                                         => panic!("{:?} prints nothing", step)
+        }
+    }
+
+    #[test]
+    fn unescapes_bytes_in_hex(){
+        assert_eq!(printed("@print \\x41\\x7e\\e"), b"A~\x1b".to_vec());
+        assert_eq!(printed("@print \\\\x41"), b"\\x41".to_vec());
+        match parse("@print \\x4"){
+            Err(BootErr::BadEscape(1)) => (),
+            other                      => panic!("{:?}", other)
+        }
+        match parse("\n\\xg0"){
+            Err(BootErr::BadEscape(2)) => (),
+            other                      => panic!("{:?}", other)
+        }
+    }
+
+    #[test]
+    fn takes_one_space_before_the_text(){
+        assert_eq!(printed("@print  two"), b" two".to_vec());
+        assert_eq!(printed("@type 1 2  two"), b" two".to_vec());
+        assert_eq!(parse("@type 5 10 hi").unwrap(), vec![Step::Type(5, 10, b"hi".to_vec())]);
+    }
+
+    #[test]
+    fn rejects_bad_arguments(){
+        for script in &["@at 0 1", "@at 1 0", "@at 1", "@type 10 5 x", "@type 0 4294967295 x", "@pause", "@wait 3"]{
+            match parse(script){
+                Err(BootErr::BadArgument(1, _)) => (),
+                other                           => panic!("{}: {:?}", script, other)
+            }
+        }
+        assert_eq!(parse("@at 3 2").unwrap(), vec![Step::Code(b"\x1b[2;3H".to_vec())]);
+    }
+
+    #[test]
+    fn splits_text_into_what_prints_at_once(){
+        assert_eq!(unit(b"\x1b[1;31mx"), (7, false));
+        assert_eq!(unit(b"\x1b[1;3"), (5, false));
+        assert_eq!(unit(b"\x1b7x"), (2, false));
+        assert_eq!(unit("\u{e9}x".as_bytes()), (2, true));
+        assert_eq!(unit("\u{2500}".as_bytes()), (3, true));
+        assert_eq!(unit(b"\r\n"), (1, false));
+        assert_eq!(unit(b"ab"), (1, true));
+    }
+
+    fn run(script: &mut BootScript, elapsed: f64) -> Vec<u8>{
+        let mut buf = Vec::new();
+        script.run(elapsed, &mut buf);
+        buf
+    }
+
+    #[test]
+    fn plays_out_over_time(){
+        let mut script = BootScript::new(parse("@speed 10\n@print a\\e[1mb\n@pause 100\n@speed 0\nc").unwrap());
+        assert_eq!(run(&mut script, 0.0), b"a".to_vec());
+        assert_eq!(run(&mut script, 5.0), b"".to_vec());
+        // the escape sequence comes with what follows it
+        assert_eq!(run(&mut script, 5.0), b"\x1b[1mb".to_vec());
+        assert_eq!(run(&mut script, 50.0), b"".to_vec());
+        assert_eq!(run(&mut script, 59.0), b"".to_vec());
+        assert_eq!(run(&mut script, 1.0), b"c\r\n".to_vec());
+        assert!(script.is_finished());
+    }
+
+    #[test]
+    fn waits_for_a_key_pressed_while_waiting(){
+        let mut script = BootScript::new(parse("@pause 10\n@wait\ngo").unwrap());
+        assert_eq!(run(&mut script, 0.0), b"".to_vec());
+        // too soon
+        script.send(b"x");
+        assert_eq!(run(&mut script, 20.0), b"".to_vec());
+        assert_eq!(run(&mut script, 20.0), b"".to_vec());
+        script.send(b"x");
+        assert_eq!(run(&mut script, 1.0), b"go\r\n".to_vec());
+    }
+}
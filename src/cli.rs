@@ -0,0 +1,436 @@
+// The command line: `textbbs [options] [command] [arguments]`. Options that
+// pick settings go with every command, before or after it; the rest only
+// with the commands that take them.
+
+use packer;
+use rustc_serialize::json;
+use throttle;
+
+// Exit codes.
+pub const SUCCESS: i32 = 0;
+pub const FAILURE: i32 = 1; // the command was understood but couldn't be done
+pub const USAGE:   i32 = 2; // the command line didn't make sense
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command{
+    Screen,                // no command: the screen file, painted in through the terminal
+    View{ path: String, edit: bool },
+    Shell(Option<String>), // run through `/bin/sh -c`, or the user's shell
+    Connect(String),       // a URL, see `transport::connect`
+    Serve(u16),
+    Render{ path: String, output: String, frame: Option<usize> },
+    Play{ path: String, frame: Option<usize> },
+    AtlasBuild{ font: String, height: Option<u16>, output: String },
+    AtlasPack{ glyphs: String, tile: Option<(u16, u16)>, columns: u32, output: String },
+    Help(Option<String>)   // about a command, or all of them
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Options{
+    pub config:   Option<String>,
+    pub settings: Vec<String>, // "table.name=value", see `Config::set`
+    pub baud:     Option<u32>,
+    pub noise:    f32,
+    pub record:   Option<String>
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cli{
+    pub command: Command,
+    pub options: Options
+}
+
+const OVERVIEW: &'static str = "\
+usage: textbbs [options] [command] [arguments]
+
+With no command, plays the screen file, or the boot script if files.boot is
+set, through the terminal.
+
+commands:
+    view <file>        show a piece of art
+    edit <file>        draw a piece of art
+    shell [command]    run a command, or your shell, in a terminal
+    connect <url>      connect to a telnet, tcp://, serial: or shell: URL
+    serve --port <n>   serve the screen file to telnet clients
+    render <file> -o <out>
+                       write art, or a frame of a recording, to a file
+    play <recording>   play an asciicast or ttyrec recording
+    atlas build (--font <file> | --glyphs <dir>) -o <atlas.json>
+                       make an atlas from a bitmap font or glyph images
+    help [command]     show this, or more about a command
+
+options:
+    --config <file>    read settings from this .toml or .json file
+    --set <setting>    change a setting, such as grid.width=132
+    --atlas <file>     draw with this atlas
+    --fullscreen       fill the screen
+    --baud <rate>      slow output to a modem's rate, such as 2400
+    --noise <p>        garble each byte with this probability, with --baud
+    --record <file>    record the session as asciicast
+    -h, --help         show help
+";
+
+// The help for each command, by name.
+const COMMAND_HELP: [(&'static str, &'static str); 9] = [
+    ("view", "\
+usage: textbbs view [--edit] <file>
+
+Shows ANSI, BIN, XBin, PCBoard, Avatar or .init art in its own font, or the
+atlas for its SAUCE font. E switches to editing it and Escape back; Escape
+or Q closes it.
+"),
+    ("edit", "\
+usage: textbbs edit <file>
+
+Opens art in the editor, on a blank page if the file doesn't exist yet.
+Ctrl+S saves it and Ctrl+Q closes it.
+"),
+    ("shell", "\
+usage: textbbs shell [command...]
+
+Runs the command through /bin/sh -c, or $SHELL with no command, in a
+terminal. Everything after the command's first word is passed on as it is.
+A command in one word, such as 'ls | less', is run as a line of shell.
+"),
+    ("connect", "\
+usage: textbbs connect <url>
+
+Connects to host:port over telnet, or to tcp://host:port,
+serial:/dev/ttyS0,9600,8N1 or shell:command.
+"),
+    ("serve", "\
+usage: textbbs serve --port <n>
+
+Listens for telnet clients and shows each the screen file.
+"),
+    ("render", "\
+usage: textbbs render <file> -o <out> [--frame <n>]
+
+Writes art, or a recording (.cast, .ttyrec or .rec) after <n> frames or at
+its end, to a .png, .svg, .html, .ans, .txt or .init file without opening a
+window.
+"),
+    ("play", "\
+usage: textbbs play <recording> [--frame <n>]
+
+Plays an asciicast or ttyrec recording, starting paused after <n> frames if
+given. Space pauses, Left and Right seek, Comma and Period step a frame, Up
+and Down change speed and Escape or Q closes it.
+"),
+    ("atlas", "\
+usage: textbbs atlas build --font <file> [--height <n>] -o <atlas.json>
+       textbbs atlas build --glyphs <dir> [--tile <w>x<h>] [--columns <n>]
+                           -o <atlas.json>
+
+Makes an atlas, writing the sheet beside the descriptor as a PNG.
+
+With --font, of 256 CP437 glyphs from a raw bitmap font, 8 pixels wide and
+<n> bytes per glyph (the file's size over 256 if not given).
+
+With --glyphs, of the PNGs in <dir>, each named for what it shows: U+2591.png
+or 0x41.png for a character by code point, or the label, like A.png. They
+must all be the tile size (the first one's if not given) and are packed <n>
+to a row (16 if not given).
+"),
+    ("help", "\
+usage: textbbs help [command]
+")
+];
+
+// The help for `command`, or the overview for anything else.
+pub fn help(command: Option<&str>) -> &'static str{
+    match COMMAND_HELP.iter().find(|&&(name, _)| Some(name) == command){
+        Some(&(_, text)) => text,
+        None             => OVERVIEW
+    }
+}
+
+impl Options{
+    pub fn new() -> Options{
+        Options{
+            config:   None,
+            settings: Vec::new(),
+            baud:     None,
+            noise:    0.0,
+            record:   None
+        }
+    }
+}
+
+fn value<I: Iterator<Item=String>>(args: &mut I, option: &str, what: &str) -> Result<String, String>{
+    match args.next(){
+        Some(v) => Ok(v),
+        None    => Err(format!("{} needs {}", option, what))
+    }
+}
+
+fn number<T: ::std::str::FromStr, I: Iterator<Item=String>>(args: &mut I, option: &str, what: &str) -> Result<T, String>{
+    match args.next().and_then(|n| n.parse::<T>().ok()){
+        Some(n) => Ok(n),
+        None    => Err(format!("{} needs {}", option, what))
+    }
+}
+
+// "8x16"
+fn parse_size(text: &str) -> Option<(u16, u16)>{
+    let mut parts = text.splitn(2, 'x');
+    match (parts.next().and_then(|w| w.parse().ok()), parts.next().and_then(|h| h.parse().ok())){
+        (Some(w), Some(h)) if w > 0 && h > 0 => Some((w, h)),
+        _                                    => None
+    }
+}
+
+// `text` as one word to /bin/sh, quoted unless it is plain.
+fn shell_quote(text: &str) -> String{
+    let plain = |c: char| c.is_ascii_alphanumeric() || "-_./=:,+@%".contains(c);
+    if !text.is_empty() && text.chars().all(plain){
+        String::from(text)
+    }
+    else{
+        format!("'{}'", text.replace('\'', "'\\''"))
+    }
+}
+
+// `atlas build` makes an atlas from a font or from glyph images, with the
+// options for whichever it is.
+fn atlas_build(font:    Option<String>,
+               glyphs:  Option<String>,
+               height:  Option<u16>,
+               tile:    Option<(u16, u16)>,
+               columns: u32,
+               given:   &[String],
+               output:  String) -> Result<Command, String>{
+    let packing = given.iter().any(|g| g == "--tile" || g == "--columns");
+    match (font, glyphs){
+        (Some(_), Some(_))                  => Err(String::from("atlas build takes --font or --glyphs, not both")),
+        (Some(_), None) if packing          => Err(String::from("--tile and --columns go with --glyphs")),
+        (Some(f), None)                     => Ok(Command::AtlasBuild{ font: f, height: height, output: output }),
+        (None, Some(_)) if height.is_some() => Err(String::from("--height goes with --font")),
+        (None, Some(g))                     => Ok(Command::AtlasPack{ glyphs: g, tile: tile, columns: columns, output: output }),
+        (None, None)                        => Err(String::from("atlas build needs --font or --glyphs"))
+    }
+}
+
+// Reads the arguments after the program name. Errors say what was wrong,
+// for printing above the usage.
+pub fn parse<I: IntoIterator<Item=String>>(args: I) -> Result<Cli, String>{
+    let mut options = Options::new();
+    let mut words:   Vec<String>        = Vec::new(); // the command and its arguments
+    let mut given:   Vec<String>        = Vec::new(); // options only some commands take
+    let mut output:  Option<String>     = None;
+    let mut frame:   Option<usize>      = None;
+    let mut port:    Option<u16>        = None;
+    let mut font:    Option<String>     = None;
+    let mut height:  Option<u16>        = None;
+    let mut glyphs:  Option<String>     = None;
+    let mut tile:    Option<(u16, u16)> = None;
+    let mut columns: u32                = packer::DEFAULT_COLUMNS;
+    let mut edit  = false;
+    let mut help  = false;
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next(){
+        // a shell command keeps its own options
+        if words.len() >= 2 && words[0] == "shell"{
+            words.push(arg);
+            continue;
+        }
+        let parsed = match &arg[..]{
+            "-h" | "--help" => { help = true; Ok(()) },
+            "--config"      => value(&mut args, &arg, "a .toml or .json file").map(|v| options.config = Some(v)),
+            "--set"         => value(&mut args, &arg, "a setting, such as grid.width=132").map(|v| options.settings.push(v)),
+            // as a JSON string, so `Config::set` reads back the path exactly
+            "--atlas"       => value(&mut args, &arg, "an atlas file").and_then(|v| match json::encode(&v){
+                Ok(quoted) => { options.settings.push(format!("files.atlas={}", quoted)); Ok(()) },
+                Err(e)     => Err(format!("{} {}: {}", arg, v, e))
+            }),
+            "--fullscreen"  => { options.settings.push(String::from("window.fullscreen=true")); Ok(()) },
+            "--baud"        => match number::<u32, _>(&mut args, &arg, "a rate"){
+                Ok(b) if b > 0 => { options.baud = Some(b); Ok(()) },
+                _ => Err(format!("{} needs a rate, such as {:?}", arg, throttle::STANDARD_RATES))
+            },
+            "--noise"       => match number::<f32, _>(&mut args, &arg, "a probability from 0 to 1"){
+                Ok(n) if n >= 0.0 && n <= 1.0 => { options.noise = n; Ok(()) },
+                _ => Err(format!("{} needs a probability from 0 to 1", arg))
+            },
+            "--record"      => value(&mut args, &arg, "a file to write the recording to").map(|v| options.record = Some(v)),
+            "-o" | "--output" => value(&mut args, &arg, "a file to write").map(|v| output = Some(v)),
+            "--frame"       => number(&mut args, &arg, "a frame number").map(|n| frame = Some(n)),
+            "-p" | "--port" => number(&mut args, &arg, "a port number").map(|p| port = Some(p)),
+            "--font"        => value(&mut args, &arg, "a bitmap font file").map(|v| font = Some(v)),
+            "--height"      => match number::<u16, _>(&mut args, &arg, "a glyph height in pixels"){
+                Ok(h) if h > 0 => { height = Some(h); Ok(()) },
+                _ => Err(format!("{} needs a glyph height in pixels", arg))
+            },
+            "--glyphs"      => value(&mut args, &arg, "a directory of glyph images").map(|v| glyphs = Some(v)),
+            "--tile"        => match value(&mut args, &arg, "a tile size, such as 8x16").map(|v| parse_size(&v)){
+                Ok(Some(size)) => { tile = Some(size); Ok(()) },
+                _ => Err(format!("{} needs a tile size, such as 8x16", arg))
+            },
+            "--columns"     => match number::<u32, _>(&mut args, &arg, "a number of tiles per row"){
+                Ok(n) if n > 0 => { columns = n; Ok(()) },
+                _ => Err(format!("{} needs a number of tiles per row", arg))
+            },
+            "--edit"        => { edit = true; Ok(()) },
+            "--"            => { words.extend(args.by_ref()); Ok(()) },
+            _ if arg.starts_with('-') && arg.len() > 1 => Err(format!("unknown option {}", arg)),
+            _ => { words.push(arg.clone()); Ok(()) }
+        };
+        if let Err(e) = parsed{
+            return Err(e);
+        }
+        match &arg[..]{
+            "-o" | "--output" | "--frame" | "-p" | "--port" | "--font" | "--height" | "--edit"
+                 | "--glyphs" | "--tile" | "--columns" =>
+                given.push(arg.clone()),
+            _ => ()
+        }
+    }
+
+    let name = words.first().cloned();
+    if help{
+        return Ok(Cli{ command: Command::Help(name), options: options });
+    }
+    let takes: &[&str] = match name.as_ref().map(|n| &n[..]){
+        Some("view")   => &["--edit"],
+        Some("render") => &["-o", "--output", "--frame"],
+        Some("play")   => &["--frame"],
+        Some("serve")  => &["-p", "--port"],
+        Some("atlas")  => &["-o", "--output", "--font", "--height", "--glyphs", "--tile", "--columns"],
+        _              => &[]
+    };
+    if let Some(option) = given.iter().find(|o| !takes.contains(&&o[..])){
+        return Err(match name{
+            Some(ref n) => format!("{} doesn't take {}", n, option),
+            None        => format!("{} goes with a command", option)
+        });
+    }
+
+    let arguments = if words.is_empty() { &words[..] } else { &words[1..] };
+    let one = |what: &str| match arguments.len(){
+        1 => Ok(arguments[0].clone()),
+        0 => Err(format!("{} needs {}", words[0], what)),
+        _ => Err(format!("{} takes only {}", words[0], what))
+    };
+    let command = match name.as_ref().map(|n| &n[..]){
+        None            => Ok(Command::Screen),
+        Some("view")    => one("an art file").map(|p| Command::View{ path: p, edit: edit }),
+        Some("edit")    => one("an art file").map(|p| Command::View{ path: p, edit: true }),
+        Some("shell")   => Ok(Command::Shell(match arguments.len(){
+            0 => None,
+            1 => Some(arguments[0].clone()),
+            _ => Some(arguments.iter().map(|a| shell_quote(a)).collect::<Vec<_>>().join(" "))
+        })),
+        Some("connect") => one("a URL").map(Command::Connect),
+        Some("serve")   => match (arguments.len(), port){
+            (0, Some(p)) => Ok(Command::Serve(p)),
+            (0, None)    => Err(String::from("serve needs --port")),
+            _            => Err(String::from("serve takes no arguments"))
+        },
+        Some("render")  => match (one("an art file or recording"), output){
+            (Ok(p), Some(o)) => Ok(Command::Render{ path: p, output: o, frame: frame }),
+            (Ok(_), None)    => Err(String::from("render needs -o and a file to write")),
+            (Err(e), _)      => Err(e)
+        },
+        Some("play")    => one("a recording").map(|p| Command::Play{ path: p, frame: frame }),
+        Some("atlas")   => match (arguments.first().map(|a| &a[..]), arguments.len(), output){
+            (Some("build"), 1, Some(o)) => atlas_build(font, glyphs, height, tile, columns, &given, o),
+            (Some("build"), 1, None)    => Err(String::from("atlas build needs -o and a file to write")),
+            _                           => Err(String::from("atlas takes build"))
+        },
+        Some("help")    => match arguments.len(){
+            0 | 1 => Ok(Command::Help(arguments.first().cloned())),
+            _     => Err(String::from("help takes one command"))
+        },
+        Some(other)     => Err(format!("unknown command {}", other))
+    };
+    match command{
+        Ok(c)  => Ok(Cli{ command: c, options: options }),
+        Err(e) => Err(e)
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use config::Config;
+    use std::path::PathBuf;
+
+    fn parsed(args: &[&str]) -> Result<Cli, String>{
+        parse(args.iter().map(|a| String::from(*a)))
+    }
+
+    fn command(args: &[&str]) -> Command{
+        match parsed(args){
+            Ok(cli) => cli.command,
+            Err(e)  => panic!("{:?}: {}", args, e)
+        }
+    }
+
+    fn some(text: &str) -> Option<String>{
+        Some(String::from(text))
+    }
+
+    #[test]
+    fn keeps_the_exit_codes_apart(){
+        assert_eq!(SUCCESS, 0);
+        assert!(FAILURE != SUCCESS && USAGE != SUCCESS && USAGE != FAILURE);
+    }
+
+    #[test]
+    fn reads_each_command(){
+        assert_eq!(command(&[]), Command::Screen);
+        assert_eq!(command(&["view", "a.ans"]), Command::View{ path: String::from("a.ans"), edit: false });
+        assert_eq!(command(&["view", "--edit", "a.ans"]), Command::View{ path: String::from("a.ans"), edit: true });
+        assert_eq!(command(&["edit", "a.ans"]), Command::View{ path: String::from("a.ans"), edit: true });
+        assert_eq!(command(&["shell"]), Command::Shell(None));
+        assert_eq!(command(&["shell", "ls | less"]), Command::Shell(some("ls | less")));
+        assert_eq!(command(&["shell", "grep", "-r", "a b", "it's"]), Command::Shell(some("grep -r 'a b' 'it'\\''s'")));
+        assert_eq!(command(&["connect", "bbs.example:23"]), Command::Connect(String::from("bbs.example:23")));
+        assert_eq!(command(&["serve", "-p", "2323"]), Command::Serve(2323));
+        assert_eq!(command(&["render", "a.cast", "-o", "a.png", "--frame", "3"]),
+                   Command::Render{ path: String::from("a.cast"), output: String::from("a.png"), frame: Some(3) });
+        assert_eq!(command(&["play", "a.cast"]), Command::Play{ path: String::from("a.cast"), frame: None });
+        assert_eq!(command(&["atlas", "build", "--font", "f.f16", "--height", "16", "-o", "a.json"]),
+                   Command::AtlasBuild{ font: String::from("f.f16"), height: Some(16), output: String::from("a.json") });
+        assert_eq!(command(&["atlas", "build", "--glyphs", "g", "--tile", "8x16", "-o", "a.json"]),
+                   Command::AtlasPack{ glyphs: String::from("g"), tile: Some((8, 16)),
+                                       columns: packer::DEFAULT_COLUMNS, output: String::from("a.json") });
+        assert_eq!(command(&["help", "render"]), Command::Help(some("render")));
+        assert_eq!(command(&["render", "--help"]), Command::Help(some("render")));
+    }
+
+    #[test]
+    fn reads_options_on_either_side_of_the_command(){
+        let cli = parsed(&["--baud", "2400", "connect", "host", "--noise", "0.1", "--record", "r.cast"]).unwrap();
+        assert_eq!(cli.options.baud, Some(2400));
+        assert_eq!(cli.options.noise, 0.1);
+        assert_eq!(cli.options.record, some("r.cast"));
+        assert_eq!(parsed(&["--fullscreen", "--set", "grid.width=132"]).unwrap().options.settings,
+                   vec![String::from("window.fullscreen=true"), String::from("grid.width=132")]);
+        // the command's own, after it
+        assert_eq!(command(&["shell", "top", "--baud", "1"]), Command::Shell(some("top --baud 1")));
+    }
+
+    #[test]
+    fn rejects_what_makes_no_sense(){
+        for args in &[&["bogus"][..], &["--bogus"], &["view"], &["view", "a", "b"], &["serve"],
+                      &["serve", "x", "-p", "1"], &["render", "a.ans"], &["play", "a", "-o", "b"],
+                      &["--edit"], &["atlas"], &["atlas", "build", "-o", "a.json"],
+                      &["atlas", "build", "--font", "f", "--glyphs", "g", "-o", "a.json"],
+                      &["atlas", "build", "--font", "f", "--tile", "8x8", "-o", "a.json"],
+                      &["atlas", "build", "--glyphs", "g", "--height", "8", "-o", "a.json"],
+                      &["--baud", "0"], &["--noise", "2"], &["--tile", "8"], &["help", "a", "b"]]{
+            assert!(parsed(args).is_err(), "{:?}", args);
+        }
+    }
+
+    #[test]
+    fn passes_the_atlas_path_on_exactly(){
+        for path in &["plain.json", "with \"quotes\".json", "cafe\u{301}\\x.json"]{
+            let cli = parsed(&["--atlas", path]).unwrap();
+            let mut config = Config::new();
+            config.set(&cli.options.settings[0]).unwrap();
+            assert_eq!(config.atlas, PathBuf::from(path));
+        }
+    }
+}
@@ -0,0 +1,472 @@
+// Settings that differ between deployments, read from config.toml or
+// config.json in the first XDG config directory with either under
+// `textbbs/`, then overridden from the command line. Everything is optional:
+//
+//     [window]
+//     width      = 800
+//     height     = 600
+//     fullscreen = false
+//
+//     [grid]
+//     width   = 80
+//     height  = 25
+//     charset = "utf8" # or "cp437", how hosts' output and recordings are read
+//
+//     [files]
+//     atlas       = "atlas.json"
+//     screen      = "screen.init"
+//     boot        = "boot.txt" # a script played instead; see `boot`
+//     asset_roots = ["/usr/share/textbbs"] # atlas images may be in these too
+//     fonts       = ["thin.json"] # more atlases for next_font to switch to
+//
+//     [palette]
+//     # 16 colours in attribute order: black, blue, green, cyan, red,
+//     # magenta, brown, grey, then the bright ones
+//     colours = ["#000000", "#0000aa", ...]
+//
+//     [effects]
+//     scanlines  = true
+//     brightness = 0.5  # away from the scanline
+//     glow       = 0.03 # extra green and blue on it
+//     jitter     = 0.05 # of each glyph's height
+//
+//     [scrolling]
+//     smooth = false # start in smooth scroll mode, as if the host asked
+//     speed  = 6     # rows a second when scrolling smoothly
+//
+//     [bell]
+//     visual  = true  # flash the pane that rang
+//     audible = false
+//     urgent  = true  # mark the window urgent if it isn't focused
+//     pitch   = 880   # Hz
+//     length  = 150   # milliseconds
+//     volume  = 0.5
+//     player  = "aplay -q" # given each beep as a WAV file on its input
+//     wav     = "bell.wav" # write beeps here instead of playing them
+//
+//     [sound]
+//     enabled = false
+//     keys    = 0.3   # volume of key clicks; 0 silences one sound
+//     hum     = 0.05  # the CRT's hum and flyback whine, all the time
+//     degauss = 0.5   # on starting
+//     modem   = 0.4   # dialling and handshaking, on connecting
+//     player  = "aplay -q --buffer-time=50000" # streamed a WAV file; keep
+//                                               # its buffer short
+//
+//     [bindings]
+//     split_columns = "ctrl+shift+e" # see `input::ACTION_NAMES`; "" unbinds
+//
+// The JSON form has the same tables as objects.
+
+use rustc_serialize::json;
+use rustc_serialize::json::Json;
+use std::collections::BTreeMap;
+use std::env;
+use std::error;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use toml;
+
+use audio::Beep;
+use input;
+use input::{Action, Chord};
+use palette::Palette;
+use session::Scrolling;
+use sound::Volumes;
+use terminal::Charset;
+
+const APP_DIR:    &'static str      = "textbbs";
+const FILE_NAMES: [&'static str; 2] = ["config.toml", "config.json"];
+const MAX_SIZE:   u64               = 16384;
+const MAX_SPEED:  f64               = 1000.0;
+const MAX_PITCH:  f64               = 20000.0;
+
+#[derive(Debug)]
+pub enum ConfigErr{
+    Io(PathBuf, io::Error),
+    Toml(PathBuf, String), // the line and column, and what's wrong there
+    Json(PathBuf, json::ParserError),
+    Invalid(String, String) // the key, like "window.width", and what's wrong with it
+}
+
+impl fmt::Display for ConfigErr{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result{
+        match *self{
+            ConfigErr::Io(ref path, ref e)         => write!(f, "{}: {}", path.display(), e),
+            ConfigErr::Toml(ref path, ref problem) => write!(f, "{}: {}", path.display(), problem),
+            ConfigErr::Json(ref path, ref e)       => write!(f, "{}: {}", path.display(), e),
+            ConfigErr::Invalid(ref key, ref problem) if key.is_empty() => write!(f, "{}", problem),
+            ConfigErr::Invalid(ref key, ref problem) => write!(f, "{}: {}", key, problem)
+        }
+    }
+}
+
+impl error::Error for ConfigErr{}
+
+fn invalid(key: &str, problem: &str) -> ConfigErr{
+    ConfigErr::Invalid(String::from(key), String::from(problem))
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Effects{
+    pub scanlines:  bool,
+    pub brightness: f32,
+    pub glow:       f32,
+    pub jitter:     f32
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Bell{
+    pub visual:  bool,
+    pub audible: bool,
+    pub urgent:  bool,
+    pub beep:    Beep,
+    pub player:  String,
+    pub wav:     Option<PathBuf>
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Sounds{
+    pub enabled: bool,
+    pub volumes: Volumes,
+    pub player:  String
+}
+
+pub struct Config{
+    pub window_width:  u32,
+    pub window_height: u32,
+    pub fullscreen:    bool,
+    pub grid_width:    u32,
+    pub grid_height:   u32,
+    pub charset:       Charset,
+    pub atlas:         PathBuf,
+    pub screen:        PathBuf,
+    pub boot:          Option<PathBuf>,
+    pub asset_roots:   Vec<PathBuf>, // see `AtlasImage::load_within`
+    pub fonts:         Vec<PathBuf>, // atlases besides `atlas` to switch between
+    pub palette:       Palette,
+    pub effects:       Effects,
+    pub scrolling:     Scrolling,
+    pub bell:          Bell,
+    pub sound:         Sounds,
+    pub bindings:      Vec<(Chord, Action)>
+}
+
+fn set_size(target: &mut u32, key: &str, value: &Json) -> Result<(), ConfigErr>{
+    match value.as_u64(){
+        Some(n) if n > 0 && n <= MAX_SIZE => { *target = n as u32; Ok(()) },
+        _ => Err(invalid(key, &format!("expected a whole number from 1 to {}", MAX_SIZE)))
+    }
+}
+
+fn set_flag(target: &mut bool, key: &str, value: &Json) -> Result<(), ConfigErr>{
+    match value.as_boolean(){
+        Some(b) => { *target = b; Ok(()) },
+        None    => Err(invalid(key, "expected true or false"))
+    }
+}
+
+fn set_fraction(target: &mut f32, key: &str, value: &Json) -> Result<(), ConfigErr>{
+    match value.as_f64(){
+        Some(f) if f >= 0.0 && f <= 1.0 => { *target = f as f32; Ok(()) },
+        _ => Err(invalid(key, "expected a number from 0 to 1"))
+    }
+}
+
+// A number of `unit`, like "rows a second".
+fn set_positive(target: &mut f32, key: &str, value: &Json, max: f64, unit: &str) -> Result<(), ConfigErr>{
+    match value.as_f64(){
+        Some(f) if f > 0.0 && f <= max => { *target = f as f32; Ok(()) },
+        _ => Err(invalid(key, &format!("expected a number of {}, above 0 and up to {}", unit, max)))
+    }
+}
+
+fn set_string(target: &mut String, key: &str, value: &Json, what: &str) -> Result<(), ConfigErr>{
+    match value.as_string(){
+        Some(s) if !s.trim().is_empty() => { *target = String::from(s); Ok(()) },
+        _ => Err(invalid(key, &format!("expected {}", what)))
+    }
+}
+
+fn set_charset(target: &mut Charset, key: &str, value: &Json) -> Result<(), ConfigErr>{
+    match value.as_string(){
+        Some("utf8")  => { *target = Charset::Utf8; Ok(()) },
+        Some("cp437") => { *target = Charset::Cp437; Ok(()) },
+        _             => Err(invalid(key, "expected \"utf8\" or \"cp437\""))
+    }
+}
+
+fn set_path(target: &mut PathBuf, key: &str, value: &Json) -> Result<(), ConfigErr>{
+    match value.as_string(){
+        Some(s) if !s.is_empty() => { *target = PathBuf::from(s); Ok(()) },
+        _ => Err(invalid(key, "expected a file name"))
+    }
+}
+
+// A list of `what`, like "directory": files or directories.
+fn set_paths(target: &mut Vec<PathBuf>, key: &str, value: &Json, what: &str) -> Result<(), ConfigErr>{
+    let paths = match value.as_array(){
+        Some(p) => p,
+        None    => return Err(invalid(key, &format!("expected a list of {} names", what)))
+    };
+    let mut found = Vec::new();
+    for (i, path) in paths.iter().enumerate(){
+        match path.as_string(){
+            Some(p) if !p.is_empty() => found.push(PathBuf::from(p)),
+            _ => return Err(invalid(&format!("{}[{}]", key, i), &format!("expected a {} name", what)))
+        }
+    }
+    *target = found;
+    Ok(())
+}
+
+// "#rrggbb"
+fn parse_colour(text: &str) -> Option<(u8, u8, u8)>{
+    if text.len() != 7 || !text.starts_with('#') || !text.is_ascii(){
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(&text[i..i + 2], 16).ok();
+    match (channel(1), channel(3), channel(5)){
+        (Some(r), Some(g), Some(b)) => Some((r, g, b)),
+        _                           => None
+    }
+}
+
+fn set_colours(target: &mut Palette, key: &str, value: &Json) -> Result<(), ConfigErr>{
+    let colours = match value.as_array(){
+        Some(c) if c.len() == 16 => c,
+        _ => return Err(invalid(key, "expected a list of 16 colours"))
+    };
+    for (i, colour) in colours.iter().enumerate(){
+        match colour.as_string().and_then(parse_colour){
+            Some(c) => target.colours[i] = c,
+            None    => return Err(invalid(&format!("{}[{}]", key, i), "expected a colour like \"#aa5500\""))
+        }
+    }
+    Ok(())
+}
+
+// Binds `action` to the chord in `value` instead of its usual chords, or
+// to nothing for "".
+fn set_binding(bindings: &mut Vec<(Chord, Action)>, action: &str, key: &str, value: &Json) -> Result<(), ConfigErr>{
+    let action = match input::ACTION_NAMES.iter().find(|&&(name, _)| name == action){
+        Some(&(_, a)) => a,
+        None          => return Err(invalid(key, "unknown action"))
+    };
+    let chord = match value.as_string(){
+        Some("")   => None,
+        Some(text) => match Chord::parse(text){
+            Some(c) => Some(c),
+            None    => return Err(invalid(key, "expected a chord like \"ctrl+shift+e\""))
+        },
+        None => return Err(invalid(key, "expected a chord like \"ctrl+shift+e\""))
+    };
+    bindings.retain(|&(_, a)| a != action);
+    if let Some(chord) = chord{
+        bindings.retain(|&(c, _)| c != chord);
+        bindings.push((chord, action));
+    }
+    Ok(())
+}
+
+fn toml_to_json(value: toml::Value) -> Json{
+    match value{
+        toml::Value::String(s)   => Json::String(s),
+        toml::Value::Integer(i)  => Json::I64(i),
+        toml::Value::Float(f)    => Json::F64(f),
+        toml::Value::Boolean(b)  => Json::Boolean(b),
+        toml::Value::Datetime(d) => Json::String(d),
+        toml::Value::Array(a)    => Json::Array(a.into_iter().map(toml_to_json).collect()),
+        toml::Value::Table(t)    => Json::Object(t.into_iter().map(|(k, v)| (k, toml_to_json(v))).collect())
+    }
+}
+
+// Where config files are looked for, best first.
+pub fn search_paths() -> Vec<PathBuf>{
+    let mut dirs = Vec::new();
+    match env::var_os("XDG_CONFIG_HOME"){
+        Some(ref dir) if !dir.is_empty() => dirs.push(PathBuf::from(dir)),
+        _ => if let Some(home) = env::var_os("HOME"){
+            dirs.push(PathBuf::from(home).join(".config"));
+        }
+    }
+    let system = match env::var("XDG_CONFIG_DIRS"){
+        Ok(ref d) if !d.is_empty() => d.clone(),
+        _                          => String::from("/etc/xdg")
+    };
+    dirs.extend(system.split(':').filter(|d| !d.is_empty()).map(PathBuf::from));
+    dirs.iter()
+        .flat_map(|d| FILE_NAMES.iter().map(move |f| d.join(APP_DIR).join(f)))
+        .collect()
+}
+
+impl Config{
+    // The settings that were built in before there was a config file.
+    pub fn new() -> Config{
+        Config{
+            window_width:  800,
+            window_height: 600,
+            fullscreen:    false,
+            grid_width:    80,
+            grid_height:   25,
+            charset:       Charset::Utf8,
+            atlas:         PathBuf::from("atlas.json"),
+            screen:        PathBuf::from("screen.init"),
+            boot:          None,
+            asset_roots:   Vec::new(),
+            fonts:         Vec::new(),
+            palette:       Palette::vga(),
+            effects:       Effects{
+                scanlines:  true,
+                brightness: 0.5,
+                glow:       0.03,
+                jitter:     0.05
+            },
+            scrolling:     Scrolling::vt220(),
+            bell:          Bell{
+                visual:  true,
+                audible: false,
+                urgent:  true,
+                beep:    Beep::standard(),
+                player:  String::from("aplay -q"),
+                wav:     None
+            },
+            sound:         Sounds{
+                enabled: false,
+                volumes: Volumes::standard(),
+                player:  String::from("aplay -q --buffer-time=50000")
+            },
+            bindings:      input::default_bindings()
+        }
+    }
+
+    // The defaults with the first config file found applied, if any.
+    pub fn find() -> Result<Config, ConfigErr>{
+        let mut config = Config::new();
+        match search_paths().into_iter().find(|p| p.is_file()){
+            Some(path) => match config.load(&path){
+                Ok(())  => Ok(config),
+                Err(e)  => Err(e)
+            },
+            None => Ok(config)
+        }
+    }
+
+    // Applies a config file, read as JSON if it ends in .json and as TOML
+    // otherwise.
+    pub fn load(&mut self, path: &Path) -> Result<(), ConfigErr>{
+        let mut text = String::new();
+        if let Err(e) = File::open(path).and_then(|mut f| f.read_to_string(&mut text)){
+            return Err(ConfigErr::Io(path.to_path_buf(), e));
+        }
+        let doc = if path.extension().map(|e| e == "json").unwrap_or(false){
+            match Json::from_str(&text){
+                Ok(doc) => doc,
+                Err(e)  => return Err(ConfigErr::Json(path.to_path_buf(), e))
+            }
+        }
+        else{
+            let mut parser = toml::Parser::new(&text);
+            match parser.parse(){
+                Some(table) => toml_to_json(toml::Value::Table(table)),
+                None => {
+                    let problem = match parser.errors.first(){
+                        Some(e) => {
+                            let (line, column) = parser.to_linecol(e.lo);
+                            format!("line {}, column {}: {}", line + 1, column + 1, e.desc)
+                        },
+                        None => String::from("unreadable")
+                    };
+                    return Err(ConfigErr::Toml(path.to_path_buf(), problem));
+                }
+            }
+        };
+        self.apply(&doc)
+    }
+
+    // Applies the tables in `doc`, stopping at the first key that is unknown
+    // or has an unusable value.
+    pub fn apply(&mut self, doc: &Json) -> Result<(), ConfigErr>{
+        let tables = match doc.as_object(){
+            Some(t) => t,
+            None    => return Err(invalid("", "expected a table of settings"))
+        };
+        for (table, values) in tables{
+            let values = match values.as_object(){
+                Some(v) => v,
+                None    => return Err(invalid(table, "expected a table"))
+            };
+            for (name, value) in values{
+                let key = format!("{}.{}", table, name);
+                let result = match (&table[..], &name[..]){
+                    ("window",  "width")       => set_size(&mut self.window_width, &key, value),
+                    ("window",  "height")      => set_size(&mut self.window_height, &key, value),
+                    ("window",  "fullscreen")  => set_flag(&mut self.fullscreen, &key, value),
+                    ("grid",    "width")       => set_size(&mut self.grid_width, &key, value),
+                    ("grid",    "height")      => set_size(&mut self.grid_height, &key, value),
+                    ("grid",    "charset")     => set_charset(&mut self.charset, &key, value),
+                    ("files",   "atlas")       => set_path(&mut self.atlas, &key, value),
+                    ("files",   "screen")      => set_path(&mut self.screen, &key, value),
+                    ("files",   "boot")        => {
+                        let mut path = PathBuf::new();
+                        set_path(&mut path, &key, value).map(|_| self.boot = Some(path))
+                    },
+                    ("files",   "asset_roots") => set_paths(&mut self.asset_roots, &key, value, "directory"),
+                    ("files",   "fonts")       => set_paths(&mut self.fonts, &key, value, "file"),
+                    ("palette", "colours")     => set_colours(&mut self.palette, &key, value),
+                    ("effects", "scanlines")   => set_flag(&mut self.effects.scanlines, &key, value),
+                    ("effects", "brightness")  => set_fraction(&mut self.effects.brightness, &key, value),
+                    ("effects", "glow")        => set_fraction(&mut self.effects.glow, &key, value),
+                    ("effects", "jitter")      => set_fraction(&mut self.effects.jitter, &key, value),
+                    ("scrolling", "smooth")    => set_flag(&mut self.scrolling.smooth, &key, value),
+                    ("scrolling", "speed")     => set_positive(&mut self.scrolling.speed, &key, value, MAX_SPEED, "rows a second"),
+                    ("bell",    "visual")      => set_flag(&mut self.bell.visual, &key, value),
+                    ("bell",    "audible")     => set_flag(&mut self.bell.audible, &key, value),
+                    ("bell",    "urgent")      => set_flag(&mut self.bell.urgent, &key, value),
+                    ("bell",    "pitch")       => set_positive(&mut self.bell.beep.pitch, &key, value, MAX_PITCH, "Hz"),
+                    ("bell",    "length")      => set_size(&mut self.bell.beep.length, &key, value),
+                    ("bell",    "volume")      => set_fraction(&mut self.bell.beep.volume, &key, value),
+                    ("bell",    "player")      => set_string(&mut self.bell.player, &key, value, "a command"),
+                    ("bell",    "wav")         => {
+                        let mut path = PathBuf::new();
+                        set_path(&mut path, &key, value).map(|_| self.bell.wav = Some(path))
+                    },
+                    ("sound",   "enabled")     => set_flag(&mut self.sound.enabled, &key, value),
+                    ("sound",   "keys")        => set_fraction(&mut self.sound.volumes.keys, &key, value),
+                    ("sound",   "hum")         => set_fraction(&mut self.sound.volumes.hum, &key, value),
+                    ("sound",   "degauss")     => set_fraction(&mut self.sound.volumes.degauss, &key, value),
+                    ("sound",   "modem")       => set_fraction(&mut self.sound.volumes.modem, &key, value),
+                    ("sound",   "player")      => set_string(&mut self.sound.player, &key, value, "a command"),
+                    ("bindings", action)       => set_binding(&mut self.bindings, action, &key, value),
+                    _ => Err(invalid(&key, "unknown setting"))
+                };
+                if let Err(e) = result{
+                    return Err(e);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Applies one "table.name=value" setting from the command line. The
+    // value is read as JSON, or taken as a string if it isn't any.
+    pub fn set(&mut self, setting: &str) -> Result<(), ConfigErr>{
+        let (key, value) = match setting.find('='){
+            Some(i) => (&setting[..i], &setting[i + 1..]),
+            None    => return Err(invalid(setting, "expected table.name=value"))
+        };
+        let (table, name) = match key.find('.'){
+            Some(i) => (&key[..i], &key[i + 1..]),
+            None    => return Err(invalid(key, "expected table.name=value"))
+        };
+        let value = Json::from_str(value).unwrap_or(Json::String(String::from(value)));
+        let mut values = BTreeMap::new();
+        values.insert(String::from(name), value);
+        let mut doc = BTreeMap::new();
+        doc.insert(String::from(table), Json::Object(values));
+        self.apply(&Json::Object(doc))
+    }
+}
@@ -0,0 +1,498 @@
+// Draws art in place: a cursor over the grid for typing, a popup for picking
+// any glyph in the atlas, foreground and background painting, block
+// selection with copy and paste, undo and redo, and saving.
+//
+// Keys:
+//     arrows, Home, End, PgUp, PgDn  move (with Shift, select)
+//     typing, Backspace, Delete      write and erase
+//     F2                             glyph picker; Enter puts the glyph
+//     F3                             put the last picked glyph again
+//     F5 / F6, F7 / F8               previous / next foreground, background
+//     Ctrl+F, Ctrl+B                 paint the foreground, background
+//     Ctrl+C, Ctrl+X, Ctrl+V         copy, cut, paste
+//     Ctrl+Z, Ctrl+Y                 undo, redo
+//     Ctrl+S                         save
+//     Escape                         drop the selection, or back to viewing
+//     Ctrl+Q                         quit
+
+use std::cmp;
+use std::path::PathBuf;
+
+use glium;
+use glium::glutin::{Event, VirtualKeyCode};
+
+use art::Art;
+use atlas::Atlas;
+use export;
+use grid::{Grid, TileMap, BLANK_TILE, DEFAULT_ATTR};
+use input::{Input, Keyboard};
+use sauce::Sauce;
+use textblock::{GridView, TextBlockErr};
+use viewer::Request;
+
+const UNDO_LIMIT: usize = 100;
+const PICKER_COLUMNS: u32 = 32;
+
+const STATUS_ATTR:   u8 = 0x70; // black on grey
+const PICKER_ATTR:   u8 = 0x17; // grey on blue
+const SELECTED_ATTR: u8 = 0x71; // blue on grey
+
+// Swaps the foreground and background, keeping the bright and blink bits.
+fn inverse(attr: u8) -> u8{
+    (attr & 0x88) | (attr & 0x07) << 4 | (attr & 0x70) >> 4
+}
+
+fn write_text(grid: &mut Grid, tiles: &TileMap, x: u32, y: u32, text: &str, attr: u8){
+    for (i, c) in text.chars().enumerate(){
+        grid.set_cell(x + i as u32, y, tiles.tile(c), attr);
+    }
+}
+
+// A copied block of cells.
+struct Clip{
+    width:  u32,
+    height: u32,
+    cells:  Vec<u16>,
+    attrs:  Vec<u8>
+}
+
+struct Picker{
+    selected: u16,
+    top:      u32 // first row of glyphs shown
+}
+
+pub struct Editor{
+    art:        Art,
+    path:       PathBuf,
+    tiles:      TileMap,
+    tile_count: u16,
+    keyboard:   Keyboard,
+    view:       GridView,
+    cursor:     (u32, u32),
+    top:        u32,
+    rows:       u32, // rows of art shown, above the status line
+    pen:        u8,
+    glyph:      u16, // the last glyph picked
+    anchor:     Option<(u32, u32)>, // the selection's other corner
+    clip:       Option<Clip>,
+    undo:       Vec<Grid>,
+    redo:       Vec<Grid>,
+    picker:     Option<Picker>,
+    modified:   bool,
+    message:    String
+}
+
+impl Editor{
+    // Edits `art`, saving to `path` in the format its extension names (see
+    // `export::write`). `rows` rows of art are shown at once.
+    pub fn new(art: Art, path: PathBuf, atlas: &Atlas, rows: u32) -> Editor{
+        let tile_count = atlas.tile_count() as u32;
+        let mut view = GridView::new();
+        view.set_palette(art.palette);
+        view.set_ice_colours(art.ice_colours);
+        view.set_phosphor(false);
+        // the same map the art was loaded with, so saving gives back what
+        // was there
+        let tiles = TileMap::for_art(&atlas.labels);
+        let glyph = tiles.tile('#');
+        Editor{
+            art:        art,
+            path:       path,
+            tiles:      tiles,
+            tile_count: cmp::min(tile_count, ::std::u16::MAX as u32) as u16,
+            keyboard:   Keyboard::new(Vec::new()),
+            view:       view,
+            cursor:     (0, 0),
+            top:        0,
+            rows:       rows,
+            pen:        DEFAULT_ATTR,
+            glyph:      glyph,
+            anchor:     None,
+            clip:       None,
+            undo:       Vec::new(),
+            redo:       Vec::new(),
+            picker:     None,
+            modified:   false,
+            message:    String::new()
+        }
+    }
+
+    pub fn into_art(self) -> Art{
+        self.art
+    }
+
+    pub fn width(&self) -> u32{
+        self.art.width()
+    }
+
+    // Rows drawn, including the status line.
+    pub fn rows(&self) -> u32{
+        self.rows + 1
+    }
+
+    // Remembers the art as it is before a change.
+    fn snapshot(&mut self){
+        if self.undo.len() >= UNDO_LIMIT{
+            self.undo.remove(0);
+        }
+        self.undo.push(self.art.grid.clone());
+        self.redo.clear();
+        self.modified = true;
+    }
+
+    fn undo(&mut self){
+        if let Some(grid) = self.undo.pop(){
+            self.redo.push(::std::mem::replace(&mut self.art.grid, grid));
+            self.modified = true;
+            self.clamp_cursor();
+        }
+    }
+
+    fn redo(&mut self){
+        if let Some(grid) = self.redo.pop(){
+            self.undo.push(::std::mem::replace(&mut self.art.grid, grid));
+            self.modified = true;
+            self.clamp_cursor();
+        }
+    }
+
+    fn clamp_cursor(&mut self){
+        let (x, y) = self.cursor;
+        self.cursor = (cmp::min(x, self.art.width().saturating_sub(1)),
+                       cmp::min(y, self.art.height().saturating_sub(1)));
+        self.anchor = None;
+        self.scroll_to_cursor();
+    }
+
+    fn scroll_to_cursor(&mut self){
+        let y = self.cursor.1;
+        if y < self.top{
+            self.top = y;
+        }
+        else if y >= self.top + self.rows{
+            self.top = y + 1 - self.rows;
+        }
+    }
+
+    // Moves the cursor, growing the art downwards when it goes past the
+    // bottom. With `select` the selection stretches from where it started.
+    fn move_to(&mut self, x: i64, y: i64, select: bool){
+        if select && self.anchor.is_none(){
+            self.anchor = Some(self.cursor);
+        }
+        else if !select{
+            self.anchor = None;
+        }
+        let x = cmp::max(cmp::min(x, self.art.width() as i64 - 1), 0) as u32;
+        let y = cmp::max(y, 0) as u32;
+        if y >= self.art.height(){
+            let width = self.art.width();
+            self.art.grid.resize(width, y + 1);
+        }
+        self.cursor = (x, y);
+        self.scroll_to_cursor();
+    }
+
+    fn move_by(&mut self, dx: i64, dy: i64, select: bool){
+        let (x, y) = self.cursor;
+        self.move_to(x as i64 + dx, y as i64 + dy, select);
+    }
+
+    // The selected block as left, top, right, bottom (inclusive), or just the
+    // cursor's cell.
+    fn selection(&self) -> (u32, u32, u32, u32){
+        let (x, y) = self.cursor;
+        let (ax, ay) = self.anchor.unwrap_or(self.cursor);
+        (cmp::min(x, ax), cmp::min(y, ay), cmp::max(x, ax), cmp::max(y, ay))
+    }
+
+    // Writes `tile` at the cursor in the pen's colours and moves on, to the
+    // next row after the last column.
+    fn put(&mut self, tile: u16){
+        self.snapshot();
+        let ((x, y), pen) = (self.cursor, self.pen);
+        self.art.grid.set_cell(x, y, tile, pen);
+        if x + 1 < self.art.width(){
+            self.move_to(x as i64 + 1, y as i64, false);
+        }
+        else{
+            self.move_to(0, y as i64 + 1, false);
+        }
+    }
+
+    // Applies `f` to the attribute of every selected cell.
+    fn paint<F>(&mut self, f: F) where F: Fn(u8) -> u8{
+        self.snapshot();
+        let (left, top, right, bottom) = self.selection();
+        for y in top..bottom + 1{
+            for x in left..right + 1{
+                if let (Some(tile), Some(attr)) = (self.art.grid.get(x, y), self.art.grid.get_attr(x, y)){
+                    self.art.grid.set_cell(x, y, tile, f(attr));
+                }
+            }
+        }
+    }
+
+    fn erase(&mut self){
+        let pen = self.pen;
+        self.paint(|_| pen & 0xf0 | DEFAULT_ATTR & 0x0f);
+        let (left, top, right, bottom) = self.selection();
+        for y in top..bottom + 1{
+            for x in left..right + 1{
+                self.art.grid.set(x, y, BLANK_TILE);
+            }
+        }
+    }
+
+    fn copy(&mut self){
+        let (left, top, right, bottom) = self.selection();
+        let mut clip = Clip{
+            width:  right - left + 1,
+            height: bottom - top + 1,
+            cells:  Vec::new(),
+            attrs:  Vec::new()
+        };
+        for y in top..bottom + 1{
+            for x in left..right + 1{
+                clip.cells.push(self.art.grid.get(x, y).unwrap_or(BLANK_TILE));
+                clip.attrs.push(self.art.grid.get_attr(x, y).unwrap_or(DEFAULT_ATTR));
+            }
+        }
+        self.message = format!("copied {}x{}", clip.width, clip.height);
+        self.clip = Some(clip);
+    }
+
+    // Pastes the clip with its top left corner at the cursor, cutting off
+    // whatever goes past the right edge.
+    fn paste(&mut self){
+        let clip = match self.clip.take(){
+            Some(c) => c,
+            None    => return self.message = String::from("nothing to paste")
+        };
+        self.snapshot();
+        let (cx, cy) = self.cursor;
+        if cy + clip.height > self.art.height(){
+            let width = self.art.width();
+            self.art.grid.resize(width, cy + clip.height);
+        }
+        for y in 0..clip.height{
+            for x in 0..clip.width{
+                let i = (y * clip.width + x) as usize;
+                self.art.grid.set_cell(cx + x, cy + y, clip.cells[i], clip.attrs[i]);
+            }
+        }
+        self.clip = Some(clip);
+    }
+
+    // Steps one of the pen's colours, a nibble picked by `shift`, through
+    // the `count` colours it can be.
+    fn step_colour(&mut self, shift: u8, count: u8, forward: bool){
+        let colour = (self.pen >> shift) & 0x0f;
+        let colour = if forward { (colour + 1) % count } else { (colour + count - 1) % count };
+        self.pen = (self.pen & !(0x0f << shift)) | colour << shift;
+    }
+
+    fn save(&mut self, atlas: &Atlas){
+        let style = export::Style{
+            dimensions:  atlas.dimensions,
+            pixels:      &atlas.pixels,
+            tiles:       &self.tiles,
+            palette:     &self.art.palette,
+            ice_colours: self.art.ice_colours
+        };
+        // BIN, XBin and the other formats that are read but not written are
+        // saved alongside as ANSI instead, from then on
+        if !export::can_write(&self.path){
+            let ansi = self.path.with_extension("ans");
+            if ansi.exists(){
+                self.message = format!("can't save .{} files, and {} is already there",
+                                       self.path.extension().and_then(|e| e.to_str()).unwrap_or(""),
+                                       ansi.display());
+                return;
+            }
+            self.path = ansi;
+        }
+        let sauce = self.art.sauce.clone().unwrap_or(Sauce::new());
+        self.message = match export::write(&self.path, &self.art.grid, &style, &sauce){
+            Ok(())  => { self.modified = false; format!("saved {}", self.path.display()) },
+            Err(e)  => format!("can't save: {}", e)
+        };
+    }
+
+    fn handle_picker(&mut self, key: VirtualKeyCode){
+        use self::VirtualKeyCode as K;
+        let count = self.tile_count as i64;
+        let mut picker = match self.picker.take(){
+            Some(p) => p,
+            None    => return
+        };
+        let step = match key{
+            K::Left  => -1,
+            K::Right => 1,
+            K::Up    => -(PICKER_COLUMNS as i64),
+            K::Down  => PICKER_COLUMNS as i64,
+            K::Return => {
+                self.glyph = picker.selected;
+                let glyph = self.glyph;
+                return self.put(glyph);
+            },
+            K::Escape | K::F2 => return,
+            _ => 0
+        };
+        picker.selected = cmp::max(cmp::min(picker.selected as i64 + step, count - 1), 0) as u16;
+        self.picker = Some(picker);
+    }
+
+    // Edits for keys and typing. Asks to go back to viewing on Escape
+    // without a selection, and to close on Ctrl+Q.
+    pub fn handle(&mut self, event: &Event, atlas: &Atlas) -> Request{
+        use self::VirtualKeyCode as K;
+        if let Event::Closed = *event{
+            return Request::Close;
+        }
+        let (key, mods) = match self.keyboard.handle(event){
+            Some(Input::Key(key, mods)) => (key, mods),
+            Some(Input::Char(c)) => {
+                let mods = self.keyboard.modifiers();
+                if self.picker.is_none() && !mods.ctrl && !mods.alt && c >= ' ' && c != '\x7f'{
+                    let tile = self.tiles.tile(c);
+                    self.put(tile);
+                }
+                return Request::Continue;
+            },
+            _ => return Request::Continue
+        };
+        if self.picker.is_some(){
+            self.handle_picker(key);
+            return Request::Continue;
+        }
+        self.message.clear();
+
+        let (x, y) = self.cursor;
+        let (page, select) = (self.rows as i64, mods.shift);
+        let backgrounds = if self.art.ice_colours { 16 } else { 8 };
+        match key{
+            K::Left     => self.move_by(-1, 0, select),
+            K::Right    => self.move_by(1, 0, select),
+            K::Up       => self.move_by(0, -1, select),
+            K::Down     => self.move_by(0, 1, select),
+            K::Home     => self.move_to(0, y as i64, select),
+            K::End      => self.move_to(self.art.width() as i64 - 1, y as i64, select),
+            K::PageUp   => self.move_by(0, -page, select),
+            K::PageDown => self.move_by(0, page, select),
+            K::Return   => self.move_to(0, y as i64 + 1, false),
+            K::Back     => if x > 0{
+                self.move_by(-1, 0, false);
+                self.erase();
+            },
+            K::Delete   => self.erase(),
+            K::F2       => self.picker = Some(Picker{ selected: self.glyph, top: 0 }),
+            K::F3       => { let glyph = self.glyph; self.put(glyph) },
+            K::F5       => self.step_colour(0, 16, false),
+            K::F6       => self.step_colour(0, 16, true),
+            K::F7       => self.step_colour(4, backgrounds, false),
+            K::F8       => self.step_colour(4, backgrounds, true),
+            K::Escape   => if self.anchor.is_some(){
+                self.anchor = None;
+            }
+            else{
+                return Request::SwitchMode;
+            },
+            _ if mods.ctrl => {
+                let pen = self.pen;
+                match key{
+                    K::F => self.paint(|a| (a & 0xf0) | (pen & 0x0f)),
+                    K::B => self.paint(|a| (a & 0x0f) | (pen & 0xf0)),
+                    K::C => self.copy(),
+                    K::X => { self.copy(); self.erase(); },
+                    K::V => self.paste(),
+                    K::Z if mods.shift => self.redo(),
+                    K::Z => self.undo(),
+                    K::Y => self.redo(),
+                    K::S => self.save(atlas),
+                    K::Q => return Request::Close,
+                    _    => ()
+                }
+            },
+            _ => ()
+        }
+        Request::Continue
+    }
+
+    // The art as shown, with the selection and cursor in inverse, the status
+    // line and the picker on top.
+    fn compose(&mut self) -> Grid{
+        let (width, rows) = (self.art.width(), self.rows);
+        let mut screen = Grid::new(width, rows + 1);
+        for y in 0..cmp::min(rows, self.art.height().saturating_sub(self.top)){
+            for x in 0..width{
+                let (tile, attr) = (self.art.grid.get(x, self.top + y).unwrap_or(BLANK_TILE),
+                                    self.art.grid.get_attr(x, self.top + y).unwrap_or(DEFAULT_ATTR));
+                screen.set_cell(x, y, tile, attr);
+            }
+        }
+
+        let (left, top, right, bottom) = self.selection();
+        for y in cmp::max(top, self.top)..cmp::min(bottom + 1, self.top + rows){
+            for x in left..right + 1{
+                let attr = screen.get_attr(x, y - self.top).unwrap_or(DEFAULT_ATTR);
+                screen.set_cell(x, y - self.top, screen.get(x, y - self.top).unwrap_or(BLANK_TILE), inverse(attr));
+            }
+        }
+
+        let status = format!(" {:3},{:<4} fg {:2} bg {:2}    {}{}",
+                             self.cursor.0 + 1, self.cursor.1 + 1,
+                             self.pen & 0x0f, self.pen >> 4,
+                             if self.modified { "* " } else { "" }, self.message);
+        for x in 0..width{
+            screen.set_cell(x, rows, BLANK_TILE, STATUS_ATTR);
+        }
+        write_text(&mut screen, &self.tiles, 0, rows, &status, STATUS_ATTR);
+        // a swatch of the pen with the last picked glyph
+        screen.set_cell(21, rows, self.glyph, self.pen);
+
+        if let Some(ref mut picker) = self.picker{
+            let columns = cmp::min(PICKER_COLUMNS, width.saturating_sub(2));
+            let glyph_rows = (self.tile_count as u32 + PICKER_COLUMNS - 1) / PICKER_COLUMNS;
+            let shown = cmp::min(glyph_rows, rows.saturating_sub(2));
+            let row = picker.selected as u32 / PICKER_COLUMNS;
+            if row < picker.top{
+                picker.top = row;
+            }
+            else if row >= picker.top + shown{
+                picker.top = row + 1 - shown;
+            }
+            for y in 0..shown{
+                for x in 0..columns{
+                    let tile = (picker.top + y) * PICKER_COLUMNS + x;
+                    if tile >= self.tile_count as u32{
+                        screen.set_cell(x + 1, y + 1, BLANK_TILE, PICKER_ATTR);
+                        continue;
+                    }
+                    let attr = if tile == picker.selected as u32 { SELECTED_ATTR } else { PICKER_ATTR };
+                    screen.set_cell(x + 1, y + 1, tile as u16, attr);
+                }
+            }
+        }
+        else{
+            let (x, y) = self.cursor;
+            if y >= self.top && y < self.top + rows{
+                let attr = screen.get_attr(x, y - self.top).unwrap_or(DEFAULT_ATTR);
+                let tile = screen.get(x, y - self.top).unwrap_or(BLANK_TILE);
+                screen.set_cell(x, y - self.top, tile, inverse(attr));
+            }
+        }
+        screen
+    }
+
+    pub fn draw<F>(&mut self,
+                   display: &F,
+                   program: &glium::Program,
+                   target:  &mut glium::Frame,
+                   atlas:   &Atlas) -> Result<(), TextBlockErr>
+        where F: glium::backend::Facade{
+        let screen = self.compose();
+        self.view.draw(display, program, target, atlas, screen.width(), screen.height(),
+                       screen.cells(), screen.attrs(), &[], None)
+    }
+}
@@ -0,0 +1,78 @@
+// Everything that can stop a command, each with what was being worked on at
+// the time, for reporting to whoever ran it.
+
+use glium;
+use std::error;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+use art::ArtErr;
+use asciicast::CastErr;
+use atlas::AtlasErr;
+use boot::BootErr;
+use config::ConfigErr;
+use packer::PackErr;
+use textblock::TextBlockErr;
+use tileblock::TileBlockErr;
+use ttyrec::TtyrecErr;
+
+#[derive(Debug)]
+pub enum Error{
+    Io(String, io::Error),      // what was being done, like "reading screen.init"
+    Config(ConfigErr),          // which names its file or setting itself
+    Atlas(PathBuf, AtlasErr),   // the descriptor, or the font the atlas was made from
+    Pack(PathBuf, PackErr),     // the directory of glyphs
+    Art(PathBuf, ArtErr),
+    Cast(PathBuf, CastErr),
+    Ttyrec(PathBuf, TtyrecErr),
+    Boot(PathBuf, BootErr),
+    Draw(&'static str, TextBlockErr), // what was being drawn
+    TileBlock(TileBlockErr),
+    Window(glium::GliumCreationError<glium::glutin::CreationError>),
+    Shader(glium::ProgramCreationError),
+    SwapBuffers(glium::SwapBuffersError),
+    Invalid(String)             // asked for something that can't be done
+}
+
+impl fmt::Display for Error{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result{
+        match *self{
+            Error::Io(ref doing, ref e)    => write!(f, "{}: {}", doing, e),
+            Error::Config(ref e)           => write!(f, "config {}", e),
+            Error::Atlas(ref path, ref e)  => write!(f, "atlas {}: {}", path.display(), e),
+            Error::Pack(ref path, ref e)   => write!(f, "packing {}: {}", path.display(), e),
+            Error::Art(ref path, ref e)    => write!(f, "{}: {}", path.display(), e),
+            Error::Cast(ref path, ref e)   => write!(f, "{}: {}", path.display(), e),
+            Error::Ttyrec(ref path, ref e) => write!(f, "{}: {}", path.display(), e),
+            Error::Boot(ref path, ref e)   => write!(f, "{}: {}", path.display(), e),
+            Error::Draw(what, ref e)       => write!(f, "drawing {}: {}", what, e),
+            Error::TileBlock(ref e)        => write!(f, "drawing tiles: {}", e),
+            Error::Window(ref e)           => write!(f, "opening the window: {}", e),
+            Error::Shader(ref e)           => write!(f, "compiling the shaders: {}", e),
+            Error::SwapBuffers(ref e)      => write!(f, "showing the frame: {}", e),
+            Error::Invalid(ref problem)    => write!(f, "{}", problem)
+        }
+    }
+}
+
+impl error::Error for Error{
+    fn source(&self) -> Option<&(dyn error::Error + 'static)>{
+        match *self{
+            Error::Io(_, ref e)       => Some(e),
+            Error::Config(ref e)      => Some(e),
+            Error::Atlas(_, ref e)    => Some(e),
+            Error::Pack(_, ref e)     => Some(e),
+            Error::Art(_, ref e)      => Some(e),
+            Error::Cast(_, ref e)     => Some(e),
+            Error::Ttyrec(_, ref e)   => Some(e),
+            Error::Boot(_, ref e)     => Some(e),
+            Error::Draw(_, ref e)     => Some(e),
+            Error::TileBlock(ref e)   => Some(e),
+            Error::Window(ref e)      => Some(e),
+            Error::Shader(ref e)      => Some(e),
+            Error::SwapBuffers(ref e) => Some(e),
+            Error::Invalid(_)         => None
+        }
+    }
+}
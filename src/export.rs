@@ -0,0 +1,370 @@
+// Writes grids out for use elsewhere: as ANSI art with a SAUCE record, plain
+// text, an HTML page, or an SVG or PNG image drawn with the atlas's own
+// glyphs.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io;
+use std::io::Write;
+use image;
+use std::path::Path;
+use time;
+
+use ansi;
+use atlas::AtlasDimensions;
+use cp437;
+use grid;
+use grid::{Grid, LineSize, TileMap, BLANK_TILE, DEFAULT_ATTR, WIDE_RIGHT, tile_index};
+use palette::Palette;
+use sauce;
+use sauce::Sauce;
+
+// How a grid's tiles and attributes look on screen: the atlas's layout and
+// pixels (see `Atlas` and `AtlasImage`), its tile map and the colours.
+pub struct Style<'a>{
+    pub dimensions:  AtlasDimensions,
+    pub pixels:      &'a [u8],
+    pub tiles:       &'a TileMap,
+    pub palette:     &'a Palette,
+    pub ice_colours: bool
+}
+
+impl<'a> Style<'a>{
+    // Palette indices of an attribute's foreground and background.
+    fn colours(&self, attr: u8) -> (usize, usize){
+        let background = if self.ice_colours { attr >> 4 } else { (attr >> 4) & 0x07 };
+        ((attr & 0x0f) as usize, background as usize)
+    }
+
+    fn hex(&self, colour: usize) -> String{
+        let (r, g, b) = self.palette.colours[colour];
+        format!("#{:02x}{:02x}{:02x}", r, g, b)
+    }
+}
+
+fn row_cells<'g>(grid: &'g Grid, y: u32) -> (&'g [u16], &'g [u8]){
+    let (start, end) = ((y * grid.width()) as usize, ((y + 1) * grid.width()) as usize);
+    (&grid.cells()[start..end], &grid.attrs()[start..end])
+}
+
+// The tiles and attributes a row shows, for formats with a character to a
+// column: a double size row's first half, a blank after each cell to spread
+// it across the width.
+fn shown_row(grid: &Grid, y: u32) -> (Vec<u16>, Vec<u8>){
+    let (cells, attrs) = row_cells(grid, y);
+    match grid.lines().get(y as usize){
+        Some(&LineSize::Single) | None => (cells.to_vec(), attrs.to_vec()),
+        Some(_) => {
+            let half = (cells.len() + 1) / 2;
+            (cells[..half].iter().flat_map(|&t| vec![t, BLANK_TILE]).take(cells.len()).collect(),
+             attrs[..half].iter().flat_map(|&a| vec![a, a]).take(cells.len()).collect())
+        }
+    }
+}
+
+// Bytes that an ANSI art viewer acts on rather than draws.
+fn is_control(b: u8) -> bool{
+    match b{
+        0x00 | 0x07..=0x0d | 0x1a | 0x1b => true,
+        _                                => false
+    }
+}
+
+// ANSI art in code page 437 followed by a SAUCE record built from `sauce`,
+// with the size, type and iCE flag filled in from the grid and style.
+// Rows as wide as the grid rely on the viewer wrapping rather than ending in
+// a line break. Double size rows are spread out (see `shown_row`) and the
+// right halves of wide characters, which CP437 hasn't got, are left blank.
+pub fn to_ansi(grid: &Grid, style: &Style, sauce: &Sauce) -> Vec<u8>{
+    let mut out = b"\x1b[0m".to_vec();
+    let mut pen = DEFAULT_ATTR;
+    for y in 0..grid.height(){
+        let (mut cells, attrs) = shown_row(grid, y);
+        for tile in cells.iter_mut().filter(|t| **t == WIDE_RIGHT){
+            *tile = BLANK_TILE;
+        }
+        let end = (0..cells.len()).rev()
+            .find(|&x| style.tiles.char(cells[x]) != ' ' || style.colours(attrs[x]).1 != 0)
+            .map(|x| x + 1)
+            .unwrap_or(0);
+        for x in 0..end{
+            if attrs[x] != pen{
+                pen = attrs[x];
+                ansi::write_sgr(&mut out, pen);
+            }
+            let b = cp437::from_char(style.tiles.char(cells[x])).unwrap_or(b'?');
+            out.push(if is_control(b) { b'?' } else { b });
+        }
+        if end < cells.len() && y + 1 < grid.height(){
+            out.extend_from_slice(b"\r\n");
+        }
+    }
+    out.extend_from_slice(b"\x1b[0m");
+
+    let mut record = sauce.clone();
+    record.file_size = out.len() as u32;
+    record.data_type = sauce::DATA_CHARACTER;
+    record.file_type = sauce::FILE_ANSI;
+    record.tinfo     = [grid.width() as u16, grid.height() as u16, 0, 0];
+    record.flags     = (record.flags & !0x01) | if style.ice_colours { 0x01 } else { 0 };
+    if record.date.is_empty(){
+        record.date = time::strftime("%Y%m%d", &time::now()).unwrap_or(String::new());
+    }
+    out.push(0x1a);
+    out.extend(record.to_bytes());
+    out
+}
+
+// The grid in `screen.init`'s layout, a byte per cell holding the tile plus
+// 0x20. Colours and pages are lost, line drawing becomes its stand-in, the
+// right halves of wide characters are blank and double size rows are spread
+// out (see `shown_row`).
+pub fn to_native(grid: &Grid) -> Vec<u8>{
+    let tiles = TileMap::ascii();
+    let mut out = Vec::with_capacity(grid.cells().len());
+    for y in 0..grid.height(){
+        let (cells, _) = shown_row(grid, y);
+        out.extend(cells.iter().map(|&t| match t{
+            WIDE_RIGHT => b' ',
+            _          => (tile_index(tiles.text_tile(t)) as u8).wrapping_add(0x20)
+        }));
+    }
+    out
+}
+
+// The grid as UTF-8 text, one line per row without trailing spaces. Wide
+// characters are written once and double size rows spread out (see
+// `shown_row`).
+pub fn to_text(grid: &Grid, style: &Style) -> String{
+    let mut text = String::new();
+    for y in 0..grid.height(){
+        let (cells, _) = shown_row(grid, y);
+        let line: String = cells.iter()
+            .filter(|&&t| t != WIDE_RIGHT)
+            .map(|&t| style.tiles.char(t))
+            .collect();
+        text.push_str(line.trim_end());
+        text.push('\n');
+    }
+    text
+}
+
+fn escape_html(c: char, out: &mut String){
+    match c{
+        '&' => out.push_str("&amp;"),
+        '<' => out.push_str("&lt;"),
+        '>' => out.push_str("&gt;"),
+        '"' => out.push_str("&quot;"),
+        _   => out.push(c)
+    }
+}
+
+// A standalone page with the grid in a <pre>, coloured with inline styles.
+// Blinking isn't shown, and double size rows are spread out as in `to_text`.
+pub fn to_html(grid: &Grid, style: &Style, title: &str) -> String{
+    let mut html = String::from("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>");
+    for c in title.chars(){
+        escape_html(c, &mut html);
+    }
+    html.push_str(&format!("</title>\n</head>\n<body style=\"background: {}\">\n\
+                            <pre style=\"font-family: monospace; line-height: 1\">",
+                           style.hex(0)));
+    for y in 0..grid.height(){
+        let (cells, attrs) = shown_row(grid, y);
+        let mut x = 0;
+        while x < cells.len(){
+            let attr = attrs[x];
+            let (fg, bg) = style.colours(attr);
+            html.push_str(&format!("<span style=\"color: {}; background: {}\">",
+                                   style.hex(fg), style.hex(bg)));
+            while x < cells.len() && attrs[x] == attr{
+                if cells[x] != WIDE_RIGHT{
+                    escape_html(style.tiles.char(cells[x]), &mut html);
+                }
+                x += 1;
+            }
+            html.push_str("</span>");
+        }
+        html.push('\n');
+    }
+    html.push_str("</pre>\n</body>\n</html>\n");
+    html
+}
+
+// An SVG path tracing the opaque pixels of `tile` in the atlas, in tile
+// coordinates, or nothing if the tile is blank or outside the atlas. Every
+// page is drawn from the regular one, line drawing as its text.
+fn glyph_path(style: &Style, tile: u16) -> String{
+    let tile     = tile_index(style.tiles.text_tile(tile));
+    let dims     = &style.dimensions;
+    let (aw, ah) = (dims.atlas_w_u as usize, dims.atlas_h_u as usize);
+    let (tw, th) = (dims.tile_w_u as usize, dims.tile_h_u as usize);
+    let columns  = if tw > 0 { aw / tw } else { 0 };
+    let mut path = String::new();
+    if columns == 0 || (tile as usize / columns + 1) * th > ah || style.pixels.len() < aw * ah * 4{
+        return path;
+    }
+    let (left, top) = ((tile as usize % columns) * tw, (tile as usize / columns) * th);
+    for y in 0..th{
+        let mut x = 0;
+        while x < tw{
+            let opaque = |x: usize| style.pixels[((top + y) * aw + left + x) * 4 + 3] > 0x7f;
+            if !opaque(x){
+                x += 1;
+                continue;
+            }
+            let start = x;
+            while x < tw && opaque(x){
+                x += 1;
+            }
+            path.push_str(&format!("M{} {}h{}v1h-{}z", start, y, x - start, x - start));
+        }
+    }
+    path
+}
+
+// An image of the grid at the atlas's tile size, with each glyph used traced
+// from the atlas once and then placed in its cell's colours. Wide characters
+// and double size rows are stretched over their cells as on screen.
+pub fn to_svg(grid: &Grid, style: &Style) -> String{
+    let (tw, th) = (style.dimensions.tile_w_u as u32, style.dimensions.tile_h_u as u32);
+    let (width, height) = (grid.width() * tw, grid.height() * th);
+    let (cells, attrs, spans) = grid::layout(grid.width(), grid.cells(), grid.attrs(), grid.lines());
+
+    let mut glyphs = BTreeMap::new();
+    for &tile in cells.iter(){
+        if !glyphs.contains_key(&tile){
+            glyphs.insert(tile, glyph_path(style, tile));
+        }
+    }
+
+    let mut svg = format!("<svg xmlns=\"http://www.w3.org/2000/svg\" \
+                           xmlns:xlink=\"http://www.w3.org/1999/xlink\" \
+                           width=\"{0}\" height=\"{1}\" viewBox=\"0 0 {0} {1}\">\n<defs>\n",
+                          width, height);
+    for (tile, path) in glyphs.iter().filter(|&(_, p)| !p.is_empty()){
+        svg.push_str(&format!("<path id=\"t{}\" d=\"{}\"/>\n", tile, path));
+    }
+    svg.push_str("</defs>\n");
+    svg.push_str(&format!("<rect width=\"{}\" height=\"{}\" fill=\"{}\"/>\n", width, height, style.hex(0)));
+
+    let row = grid.width().max(1) as usize;
+    for (y, ((cells, attrs), spans)) in cells.chunks(row).zip(attrs.chunks(row)).zip(spans.chunks(row)).enumerate(){
+        let y = y as u32;
+        // backgrounds in runs of the same colour
+        let mut x = 0;
+        while x < cells.len(){
+            let bg = style.colours(attrs[x]).1;
+            let start = x;
+            while x < cells.len() && style.colours(attrs[x]).1 == bg{
+                x += 1;
+            }
+            if bg != 0{
+                svg.push_str(&format!("<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\"/>\n",
+                                      start as u32 * tw, y * th, (x - start) as u32 * tw, th, style.hex(bg)));
+            }
+        }
+        for (x, ((&tile, &attr), &span)) in cells.iter().zip(attrs.iter()).zip(spans.iter()).enumerate(){
+            if glyphs.get(&tile).map(|p| p.is_empty()).unwrap_or(true){
+                continue;
+            }
+            let fill = style.hex(style.colours(attr).0);
+            if span == 0{
+                svg.push_str(&format!("<use xlink:href=\"#t{}\" x=\"{}\" y=\"{}\" fill=\"{}\"/>\n",
+                                      tile, x as u32 * tw, y * th, fill));
+                continue;
+            }
+            // the part of the glyph the cell shows, stretched across it
+            let (part, parts, half) = grid::span_parts(span);
+            let (part_w, part_h) = (tw as f32 / parts as f32, if half == 0 { th as f32 } else { th as f32 / 2.0 });
+            let (part_x, part_y) = (part as f32 * part_w, if half == 2 { part_h } else { 0.0 });
+            svg.push_str(&format!("<svg x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" viewBox=\"{} {} {} {}\" \
+                                   preserveAspectRatio=\"none\"><use xlink:href=\"#t{}\" fill=\"{}\"/></svg>\n",
+                                  x as u32 * tw, y * th, tw, th, part_x, part_y, part_w, part_h, tile, fill));
+        }
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+// The grid drawn at the atlas's tile size as RGBA pixels, top row first,
+// along with the width and height. Each glyph's alpha blends its cell's
+// background into its foreground, as on screen, and wide characters and
+// double size rows are stretched over their cells.
+pub fn to_pixels(grid: &Grid, style: &Style) -> (u32, u32, Vec<u8>){
+    let dims     = &style.dimensions;
+    let (aw, ah) = (dims.atlas_w_u as usize, dims.atlas_h_u as usize);
+    let (tw, th) = (dims.tile_w_u as usize, dims.tile_h_u as usize);
+    let columns  = if tw > 0 { aw / tw } else { 0 };
+    let (width, height) = (grid.width() as usize * tw, grid.height() as usize * th);
+    let mut pixels = vec![0u8; width * height * 4];
+    let have_pixels = columns > 0 && style.pixels.len() >= aw * ah * 4;
+    let (cells, attrs, spans) = grid::layout(grid.width(), grid.cells(), grid.attrs(), grid.lines());
+
+    for (i, ((&tile, &attr), &span)) in cells.iter().zip(attrs.iter()).zip(spans.iter()).enumerate(){
+        let (fg, bg) = style.colours(attr);
+        let (fg, bg) = (style.palette.colours[fg], style.palette.colours[bg]);
+        let (left, top) = ((i % grid.width() as usize) * tw, (i / grid.width() as usize) * th);
+        let tile = tile_index(style.tiles.text_tile(tile)) as usize;
+        let (tile_left, tile_top) = ((tile % columns.max(1)) * tw, (tile / columns.max(1)) * th);
+        let in_atlas = have_pixels && tile_top + th <= ah;
+        let (part, parts, half) = grid::span_parts(span);
+        let (part, parts, half) = (part as usize, parts as usize, half as usize);
+        for y in 0..th{
+            // the row and column of the tile shown here
+            let from_y = if half == 0 { y } else { ((half - 1) * th + y) / 2 };
+            for x in 0..tw{
+                let from_x = (part * tw + x) / parts;
+                let alpha = if in_atlas { style.pixels[((tile_top + from_y) * aw + tile_left + from_x) * 4 + 3] as u32 } else { 0 };
+                let mix = |b: u8, f: u8| ((b as u32 * (255 - alpha) + f as u32 * alpha) / 255) as u8;
+                let at = ((top + y) * width + left + x) * 4;
+                pixels[at]     = mix(bg.0, fg.0);
+                pixels[at + 1] = mix(bg.1, fg.1);
+                pixels[at + 2] = mix(bg.2, fg.2);
+                pixels[at + 3] = 0xff;
+            }
+        }
+    }
+    (width as u32, height as u32, pixels)
+}
+
+// The extension of `path`, in lower case.
+fn extension(path: &Path) -> String{
+    path.extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_lowercase())
+        .unwrap_or(String::new())
+}
+
+// Whether `write` knows the format `path`'s extension names.
+pub fn can_write(path: &Path) -> bool{
+    match &extension(path)[..]{
+        "png" | "ans" | "txt" | "html" | "htm" | "svg" | "init" => true,
+        _                                                       => false
+    }
+}
+
+// Writes the grid in the format its extension names: .ans, .txt, .html,
+// .svg, .png or .init. `sauce` gives the title, and the rest of the record
+// for .ans.
+pub fn write(path: &Path, grid: &Grid, style: &Style, sauce: &Sauce) -> io::Result<()>{
+    let extension = extension(path);
+    if extension == "png"{
+        let (width, height, pixels) = to_pixels(grid, style);
+        return image::save_buffer(path, &pixels, width, height, image::ColorType::RGBA(8));
+    }
+    let data = match &extension[..]{
+        "ans"          => to_ansi(grid, style, sauce),
+        "txt"          => to_text(grid, style).into_bytes(),
+        "html" | "htm" => to_html(grid, style, &sauce.caption()).into_bytes(),
+        "svg"          => to_svg(grid, style).into_bytes(),
+        "init"         => to_native(grid),
+        _ => return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                       format!("can't export to .{} files", extension)))
+    };
+    let mut file = match File::create(path){
+        Ok(f)  => f,
+        Err(e) => return Err(e)
+    };
+    file.write_all(&data)
+}
@@ -0,0 +1,713 @@
+// CPU-side model of a screen of tiles. A `Grid` knows nothing about OpenGL;
+// it is copied into a `TextBlock` when it needs to be drawn.
+
+use std::collections::{HashMap, VecDeque};
+
+use cp437;
+
+pub const BLANK_TILE:   u16 = 0;           // ' '
+pub const UNKNOWN_TILE: u16 = 0x3f - 0x20; // '?'
+pub const WIDE_RIGHT:   u16 = 0xffff;      // the right half of the wide character to its left
+
+// Cell colours are PC text-mode attributes: the low nibble is the foreground
+// and the high nibble the background, both indices into a `Palette`. Bit 7
+// makes the cell blink, unless iCE colours turn it into a bright background.
+pub const DEFAULT_ATTR: u8 = 0x07; // light grey on black
+
+// A tile id's top bits choose which of the atlas's pages it is drawn from,
+// each a sheet laid out like the regular one (see `Atlas`); the rest are the
+// tile on that page. Pages an atlas lacks fall back to one it has.
+pub const PAGE_SHIFT:       u32 = 13;
+pub const TILE_MASK:        u16 = (1 << PAGE_SHIFT) - 1;
+pub const PAGE_REGULAR:     u16 = 0;
+pub const PAGE_BOLD:        u16 = 1;
+pub const PAGE_ITALIC:      u16 = 2;
+pub const PAGE_BOLD_ITALIC: u16 = 3;
+pub const PAGE_GRAPHICS:    u16 = 4; // line drawing from the DEC special graphics set
+
+// `tile` on `page`.
+pub fn on_page(tile: u16, page: u16) -> u16{
+    (tile & TILE_MASK) | page << PAGE_SHIFT
+}
+
+// The tile without its page.
+pub fn tile_index(tile: u16) -> u16{
+    tile & TILE_MASK
+}
+
+pub fn tile_page(tile: u16) -> u16{
+    tile >> PAGE_SHIFT
+}
+
+// The default atlas starts at ' ' and follows ASCII order.
+pub fn char_to_tile(c: char) -> u16{
+    match c as u32{
+        0x20..=0x7e => (c as u32 - 0x20) as u16,
+        _           => UNKNOWN_TILE
+    }
+}
+
+// The inverse of `char_to_tile`.
+pub fn tile_to_char(tile: u16) -> char{
+    match tile_index(tile){
+        0..=0x5e => (tile as u8 + 0x20) as char,
+        _        => '?'
+    }
+}
+
+// What DEC special graphics shows for '_' to '~', in the order the atlas's
+// graphics page has it, and what stands in for it in atlases without that
+// page or the character.
+pub const DEC_GRAPHICS: [(char, char); 32] =
+    [(' ',        ' '), ('\u{25c6}', '*'), ('\u{2592}', '#'), ('\u{2409}', '?'),
+     ('\u{240c}', '?'), ('\u{240d}', '?'), ('\u{240a}', '?'), ('\u{b0}',   'o'),
+     ('\u{b1}',   '+'), ('\u{2424}', '?'), ('\u{240b}', '?'), ('\u{2518}', '+'),
+     ('\u{2510}', '+'), ('\u{250c}', '+'), ('\u{2514}', '+'), ('\u{253c}', '+'),
+     ('\u{23ba}', '-'), ('\u{23bb}', '-'), ('\u{2500}', '-'), ('\u{23bc}', '-'),
+     ('\u{23bd}', '_'), ('\u{251c}', '+'), ('\u{2524}', '+'), ('\u{2534}', '+'),
+     ('\u{252c}', '+'), ('\u{2502}', '|'), ('\u{2264}', '<'), ('\u{2265}', '>'),
+     ('\u{3c0}',  '*'), ('\u{2260}', '!'), ('\u{a3}',   '#'), ('\u{b7}',   '.')];
+
+// Which tile shows each character. Atlases with `tile_labels` name their
+// tiles; the rest follow the default ASCII layout of `char_to_tile`.
+#[derive(Clone)]
+pub struct TileMap{
+    tiles:   HashMap<char, u16>,
+    chars:   HashMap<u16, char>,
+    unknown: u16
+}
+
+impl TileMap{
+    pub fn ascii() -> TileMap{
+        TileMap{
+            tiles:   HashMap::new(),
+            chars:   HashMap::new(),
+            unknown: UNKNOWN_TILE
+        }
+    }
+
+    // Maps every label that is a single character to its tile. Without any
+    // such labels this is the same as `ascii`. Only tiles a tile id can
+    // reach (see `TILE_MASK`) are mapped.
+    pub fn from_labels(labels: &[String]) -> TileMap{
+        let mut tiles = HashMap::new();
+        let mut chars = HashMap::new();
+        for (i, label) in labels.iter().take(TILE_MASK as usize + 1).enumerate(){
+            let mut label_chars = label.chars();
+            if let (Some(c), None) = (label_chars.next(), label_chars.next()){
+                tiles.entry(c).or_insert(i as u16);
+                chars.insert(i as u16, c);
+            }
+        }
+        let unknown = tiles.get(&'?').cloned().unwrap_or(UNKNOWN_TILE);
+        TileMap{
+            tiles:   tiles,
+            chars:   chars,
+            unknown: unknown
+        }
+    }
+
+    // Like `from_labels`, but an atlas without labels gets a tile for every
+    // CP437 character, continuing the default layout past '~' and wrapping
+    // round to the control characters' glyphs, so art keeps characters the
+    // atlas can't show through loading and saving.
+    pub fn for_art(labels: &[String]) -> TileMap{
+        let map = TileMap::from_labels(labels);
+        if !map.tiles.is_empty(){
+            return map;
+        }
+        let mut tiles = HashMap::new();
+        let mut chars = HashMap::new();
+        for b in 0..256u16{
+            let (c, tile) = (cp437::to_char(b as u8), (b + 0xe0) % 0x100);
+            tiles.entry(c).or_insert(tile);
+            chars.insert(tile, c);
+        }
+        TileMap{
+            tiles:   tiles,
+            chars:   chars,
+            unknown: char_to_tile('?')
+        }
+    }
+
+    pub fn tile(&self, c: char) -> u16{
+        self.get(c).unwrap_or(self.unknown)
+    }
+
+    // The tile showing `c`, if there is one.
+    pub fn get(&self, c: char) -> Option<u16>{
+        if self.tiles.is_empty(){
+            return match c as u32{
+                0x20..=0x7e => Some(char_to_tile(c)),
+                _           => None
+            };
+        }
+        self.tiles.get(&c).cloned()
+    }
+
+    // The character a tile shows on any page, or '?' for tiles without one.
+    pub fn char(&self, tile: u16) -> char{
+        if tile_page(tile) == PAGE_GRAPHICS{
+            return DEC_GRAPHICS.get(tile_index(tile) as usize).map(|&(c, _)| c).unwrap_or('?');
+        }
+        if self.tiles.is_empty(){
+            return tile_to_char(tile);
+        }
+        self.chars.get(&tile_index(tile)).cloned().unwrap_or('?')
+    }
+
+    // The tile on the text pages drawing `tile`, for atlases without a
+    // graphics page: line drawing becomes the character if there's a tile
+    // for it and its stand-in if not. Other tiles are their own.
+    pub fn text_tile(&self, tile: u16) -> u16{
+        if tile_page(tile) != PAGE_GRAPHICS{
+            return tile;
+        }
+        match DEC_GRAPHICS.get(tile_index(tile) as usize){
+            Some(&(c, stand_in)) => self.get(c).unwrap_or(self.tile(stand_in)),
+            None                 => self.unknown
+        }
+    }
+}
+
+// How a row is drawn (DECSWL, DECDWL and DECDHL). Double rows show only
+// the first half of their cells, each two cells wide, and double height rows
+// come in pairs showing the top and bottom halves of the same text.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LineSize{
+    Single,
+    DoubleWidth,
+    DoubleTop,
+    DoubleBottom
+}
+
+// Rows `top` to `bottom` of a grid shown `offset` rows (a fraction, while
+// they glide) below where they are, part way through a smooth scroll, or
+// above for a negative offset as they scroll down.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ScrollShift{
+    pub top:    u32,
+    pub bottom: u32,
+    pub offset: f32
+}
+
+// A row that scrolled off the top of a grid.
+#[derive(Clone)]
+pub struct Row{
+    pub tiles: Vec<u16>,
+    pub attrs: Vec<u8>,
+    pub size:  LineSize
+}
+
+// Which part of its tile a screen cell shows, for the shader: the low two
+// bits are which of `parts` slices across it, the next two `parts` as a power
+// of two, and the two above those 1 for the top half or 2 for the bottom.
+// 0 is the whole tile.
+fn span(part: u32, parts: u32, size: LineSize) -> u8{
+    let half = match size{
+        LineSize::DoubleTop    => 1,
+        LineSize::DoubleBottom => 2,
+        _                      => 0
+    };
+    let log2 = match parts{ 1 => 0, 2 => 1, _ => 2 };
+    (part | log2 << 2 | half << 4) as u8
+}
+
+// The part a cell shows, how many parts its tile is sliced into across and
+// which half it shows (0 for both, 1 the top, 2 the bottom); see `span`.
+pub fn span_parts(span: u8) -> (u32, u32, u32){
+    ((span & 3) as u32, 1 << ((span >> 2) & 3), ((span >> 4) & 3) as u32)
+}
+
+// The tiles, attributes and spans (see `span`) of the screen cells showing
+// `cells`, `width` to a row, once wide characters and double size rows are
+// spread over the cells they cover. Rows missing from `lines` are single.
+pub fn layout(width: u32, cells: &[u16], attrs: &[u8], lines: &[LineSize]) -> (Vec<u16>, Vec<u8>, Vec<u8>){
+    let width = width as usize;
+    let mut out_cells = Vec::with_capacity(cells.len());
+    let mut out_attrs = Vec::with_capacity(cells.len());
+    let mut spans     = Vec::with_capacity(cells.len());
+    if width == 0{
+        return (out_cells, out_attrs, spans);
+    }
+    for (y, (row, row_attrs)) in cells.chunks(width).zip(attrs.chunks(width)).enumerate(){
+        let size  = lines.get(y).cloned().unwrap_or(LineSize::Single);
+        let scale = if size == LineSize::Single { 1 } else { 2 };
+        for x in 0..row.len(){
+            let (cell, sub) = (x / scale, (x % scale) as u32);
+            // the tile a cell shows and which half of a wide one it is
+            let (tile, part, parts) = match row.get(cell){
+                Some(&WIDE_RIGHT) if cell > 0 && row[cell - 1] != WIDE_RIGHT => (row[cell - 1], 1, 2),
+                Some(&WIDE_RIGHT)                                             => (BLANK_TILE, 0, 1),
+                Some(&t) if row.get(cell + 1) == Some(&WIDE_RIGHT)            => (t, 0, 2),
+                Some(&t)                                                      => (t, 0, 1),
+                None                                                          => (BLANK_TILE, 0, 1)
+            };
+            out_cells.push(tile);
+            out_attrs.push(row_attrs.get(cell).cloned().unwrap_or(DEFAULT_ATTR));
+            spans.push(span(part * scale as u32 + sub, parts * scale as u32, size));
+        }
+    }
+    (out_cells, out_attrs, spans)
+}
+
+#[derive(Clone)]
+pub struct Grid{
+    width:            u32,
+    height:           u32,
+    cells:            Vec<u16>,
+    attrs:            Vec<u8>,
+    lines:            Vec<LineSize>,
+    pen:              u8, // attribute given to what is written next
+    cursor_x:         u32,
+    cursor_y:         u32,
+    // the rows that scroll (DECSTBM), top and bottom included
+    region_top:       u32,
+    region_bottom:    u32,
+    scrolled:         i32, // rows the region has scrolled up, less those down; see `take_scrolled`
+    // rows that scrolled off the top, oldest first
+    scrollback:       VecDeque<Row>,
+    scrollback_limit: usize
+}
+
+impl Grid{
+    pub fn new(width: u32, height: u32) -> Grid{
+        Grid{
+            width:            width,
+            height:           height,
+            cells:            vec![BLANK_TILE; (width * height) as usize],
+            attrs:            vec![DEFAULT_ATTR; (width * height) as usize],
+            lines:            vec![LineSize::Single; height as usize],
+            pen:              DEFAULT_ATTR,
+            cursor_x:         0,
+            cursor_y:         0,
+            region_top:       0,
+            region_bottom:    height.saturating_sub(1),
+            scrolled:         0,
+            scrollback:       VecDeque::new(),
+            scrollback_limit: 0
+        }
+    }
+
+    pub fn from_cells(width: u32, height: u32, cells: Vec<u16>) -> Option<Grid>{
+        if cells.len() as u32 != width * height{
+            return None;
+        }
+        Some(Grid{
+            width:            width,
+            height:           height,
+            cells:            cells,
+            attrs:            vec![DEFAULT_ATTR; (width * height) as usize],
+            lines:            vec![LineSize::Single; height as usize],
+            pen:              DEFAULT_ATTR,
+            cursor_x:         0,
+            cursor_y:         0,
+            region_top:       0,
+            region_bottom:    height.saturating_sub(1),
+            scrolled:         0,
+            scrollback:       VecDeque::new(),
+            scrollback_limit: 0
+        })
+    }
+
+    pub fn width(&self) -> u32{
+        self.width
+    }
+
+    pub fn height(&self) -> u32{
+        self.height
+    }
+
+    pub fn cells(&self) -> &[u16]{
+        &self.cells
+    }
+
+    pub fn attrs(&self) -> &[u8]{
+        &self.attrs
+    }
+
+    pub fn lines(&self) -> &[LineSize]{
+        &self.lines
+    }
+
+    // How many cells of row `y` are shown: half of a double size row's.
+    pub fn row_width(&self, y: u32) -> u32{
+        match self.lines.get(y as usize){
+            Some(&LineSize::Single) | None => self.width,
+            Some(_)                        => self.width / 2
+        }
+    }
+
+    // Makes the cursor's row `size`. The cells a double size row doesn't
+    // show are lost, as on a VT100.
+    pub fn set_line_size(&mut self, size: LineSize){
+        let y = self.cursor_y;
+        if let Some(line) = self.lines.get_mut(y as usize){
+            *line = size;
+        }
+        if size != LineSize::Single{
+            let row = y * self.width;
+            self.fill(row + self.width / 2, row + self.width);
+        }
+        let x = self.cursor_x;
+        self.set_cursor(x, y);
+    }
+
+    // The rows that scroll, top and bottom included.
+    pub fn scroll_region(&self) -> (u32, u32){
+        (self.region_top, self.region_bottom)
+    }
+
+    // DECSTBM: confines scrolling to rows `top` to `bottom`, or lets the
+    // whole grid scroll if they don't make a region of at least two rows.
+    // Homes the cursor.
+    pub fn set_scroll_region(&mut self, top: u32, bottom: u32){
+        if top < bottom && bottom < self.height{
+            self.region_top    = top;
+            self.region_bottom = bottom;
+        }
+        else{
+            self.region_top    = 0;
+            self.region_bottom = self.height.saturating_sub(1);
+        }
+        self.cursor_x = 0;
+        self.cursor_y = 0;
+    }
+
+    // How many rows the scroll region has scrolled since the last call, up
+    // less down.
+    pub fn take_scrolled(&mut self) -> i32{
+        let scrolled = self.scrolled;
+        self.scrolled = 0;
+        scrolled
+    }
+
+    pub fn scrollback(&self) -> &VecDeque<Row>{
+        &self.scrollback
+    }
+
+    // How many rows that scroll off the top are kept. 0 keeps none.
+    pub fn set_scrollback_limit(&mut self, limit: usize){
+        self.scrollback_limit = limit;
+        while self.scrollback.len() > limit{
+            self.scrollback.pop_front();
+        }
+    }
+
+    pub fn pen(&self) -> u8{
+        self.pen
+    }
+
+    // Sets the attribute that written and erased cells get.
+    pub fn set_pen(&mut self, attr: u8){
+        self.pen = attr;
+    }
+
+    pub fn cursor(&self) -> (u32, u32){
+        (self.cursor_x, self.cursor_y)
+    }
+
+    pub fn set_cursor(&mut self, x: u32, y: u32){
+        self.cursor_y = if y < self.height { y } else { self.height.saturating_sub(1) };
+        let row_width = self.row_width(self.cursor_y);
+        self.cursor_x = if x < row_width { x } else { row_width.saturating_sub(1) };
+    }
+
+    pub fn get(&self, x: u32, y: u32) -> Option<u16>{
+        if x < self.width && y < self.height{
+            Some(self.cells[(y * self.width + x) as usize])
+        }
+        else{
+            None
+        }
+    }
+
+    pub fn get_attr(&self, x: u32, y: u32) -> Option<u8>{
+        if x < self.width && y < self.height{
+            Some(self.attrs[(y * self.width + x) as usize])
+        }
+        else{
+            None
+        }
+    }
+
+    // Changes the tile at `x`, `y`, leaving its attribute alone.
+    pub fn set(&mut self, x: u32, y: u32, tile: u16){
+        if x < self.width && y < self.height{
+            self.cells[(y * self.width + x) as usize] = tile;
+        }
+    }
+
+    pub fn set_cell(&mut self, x: u32, y: u32, tile: u16, attr: u8){
+        if x < self.width && y < self.height{
+            let i = (y * self.width + x) as usize;
+            self.cells[i] = tile;
+            self.attrs[i] = attr;
+        }
+    }
+
+    // Blanks the screen, resets the pen and scroll region and homes the
+    // cursor.
+    pub fn clear(&mut self){
+        self.pen = DEFAULT_ATTR;
+        let end = self.width * self.height;
+        self.fill(0, end);
+        for line in self.lines.iter_mut(){
+            *line = LineSize::Single;
+        }
+        self.set_scroll_region(0, 0);
+    }
+
+    // Writes `tile` at the cursor and advances it, wrapping at the right edge
+    // and scrolling at the bottom.
+    pub fn put_tile(&mut self, tile: u16){
+        if self.width == 0 || self.height == 0{
+            return;
+        }
+        if self.cursor_x >= self.row_width(self.cursor_y){
+            self.newline();
+        }
+        let (x, y, pen) = (self.cursor_x, self.cursor_y, self.pen);
+        self.split_wide(x, y);
+        self.set_cell(x, y, tile, pen);
+        self.cursor_x += 1;
+    }
+
+    // Like `put_tile` for a character two cells wide, such as CJK. One that
+    // doesn't fit in what's left of the row goes on the next.
+    pub fn put_wide_tile(&mut self, tile: u16){
+        if self.row_width(self.cursor_y) < 2{
+            return self.put_tile(tile);
+        }
+        if self.cursor_x + 1 >= self.row_width(self.cursor_y){
+            self.newline();
+        }
+        let (x, y, pen) = (self.cursor_x, self.cursor_y, self.pen);
+        self.split_wide(x, y);
+        self.split_wide(x + 1, y);
+        self.set_cell(x, y, tile, pen);
+        self.set_cell(x + 1, y, WIDE_RIGHT, pen);
+        self.cursor_x += 2;
+    }
+
+    // Before the cell at `x`, `y` is overwritten: blanks the other half of
+    // any wide character it is part of.
+    fn split_wide(&mut self, x: u32, y: u32){
+        if self.get(x, y) == Some(WIDE_RIGHT) && x > 0{
+            self.set(x - 1, y, BLANK_TILE);
+        }
+        if self.get(x + 1, y) == Some(WIDE_RIGHT){
+            self.set(x + 1, y, BLANK_TILE);
+        }
+    }
+
+    pub fn put_char(&mut self, c: char){
+        match c{
+            '\n'   => self.newline(),
+            '\r'   => self.cursor_x = 0,
+            '\x08' => self.backspace(),
+            '\t'   => self.tab(),
+            _      => self.put_tile(char_to_tile(c))
+        }
+    }
+
+    pub fn write_str(&mut self, s: &str){
+        for c in s.chars(){
+            self.put_char(c);
+        }
+    }
+
+    pub fn newline(&mut self){
+        self.cursor_x = 0;
+        self.line_feed();
+    }
+
+    // Moves down a row without returning to the first column, scrolling at
+    // the bottom of the scroll region.
+    pub fn line_feed(&mut self){
+        if self.cursor_y == self.region_bottom{
+            self.scroll_up();
+        }
+        else if self.cursor_y + 1 < self.height{
+            self.cursor_y += 1;
+        }
+    }
+
+    // RI: moves up a row, scrolling down at the top of the scroll region.
+    pub fn reverse_line_feed(&mut self){
+        if self.cursor_y == self.region_top{
+            self.scroll_down();
+        }
+        else if self.cursor_y > 0{
+            self.cursor_y -= 1;
+        }
+    }
+
+    // Advances to the next tab stop; stops are every 8 columns.
+    pub fn tab(&mut self){
+        let next = (self.cursor_x / 8 + 1) * 8;
+        self.cursor_x = ::std::cmp::min(next, self.row_width(self.cursor_y).saturating_sub(1));
+    }
+
+    pub fn backspace(&mut self){
+        if self.cursor_x > 0{
+            self.cursor_x -= 1;
+            let (x, y, pen) = (self.cursor_x, self.cursor_y, self.pen);
+            self.set_cell(x, y, BLANK_TILE, pen);
+        }
+    }
+
+    // Moves the scroll region up a row, adding a blank row at its bottom.
+    // Rows leaving the top of the grid go into the scrollback.
+    pub fn scroll_up(&mut self){
+        let width = self.width as usize;
+        if width == 0 || self.cells.is_empty(){
+            return;
+        }
+        let (top, bottom) = (self.region_top as usize, self.region_bottom as usize);
+        let start = top * width;
+        let row = Row{
+            tiles: self.cells.drain(start..start + width).collect(),
+            attrs: self.attrs.drain(start..start + width).collect(),
+            size:  self.lines.remove(top)
+        };
+        if top == 0 && self.scrollback_limit > 0{
+            if self.scrollback.len() >= self.scrollback_limit{
+                self.scrollback.pop_front();
+            }
+            self.scrollback.push_back(row);
+        }
+        let end = bottom * width;
+        self.cells.splice(end..end, ::std::iter::repeat(BLANK_TILE).take(width));
+        self.attrs.splice(end..end, ::std::iter::repeat(self.pen).take(width));
+        self.lines.insert(bottom, LineSize::Single);
+        self.scrolled += 1;
+    }
+
+    // Moves the scroll region down a row, adding a blank row at its top. Its
+    // bottom row is lost.
+    pub fn scroll_down(&mut self){
+        let (top, bottom) = (self.region_top, self.region_bottom);
+        self.shift_rows(top, bottom, 1, false);
+        self.scrolled -= 1;
+    }
+
+    // IL: adds `n` blank rows at the cursor's, pushing the rows below down
+    // the scroll region and off its bottom, and returns to the first column.
+    // Outside the region it does nothing.
+    pub fn insert_lines(&mut self, n: u32){
+        if self.cursor_y >= self.region_top && self.cursor_y <= self.region_bottom{
+            let (top, bottom) = (self.cursor_y, self.region_bottom);
+            self.shift_rows(top, bottom, n, false);
+            self.cursor_x = 0;
+        }
+    }
+
+    // DL: removes `n` rows from the cursor's down, pulling the rest of the
+    // scroll region up with blank rows below, and returns to the first
+    // column. Outside the region it does nothing.
+    pub fn delete_lines(&mut self, n: u32){
+        if self.cursor_y >= self.region_top && self.cursor_y <= self.region_bottom{
+            let (top, bottom) = (self.cursor_y, self.region_bottom);
+            self.shift_rows(top, bottom, n, true);
+            self.cursor_x = 0;
+        }
+    }
+
+    // Moves rows `top` to `bottom` up `n` rows, or down if not `up`, blanking
+    // the rows they leave in the pen's colours. Rows moved past either end
+    // are lost.
+    fn shift_rows(&mut self, top: u32, bottom: u32, n: u32, up: bool){
+        let width = self.width as usize;
+        if width == 0 || top > bottom || bottom >= self.height{
+            return;
+        }
+        let n = ::std::cmp::min(n, bottom - top + 1) as usize;
+        let (top, bottom) = (top as usize, bottom as usize + 1);
+        let (cells, attrs, lines) = (&mut self.cells[top * width..bottom * width],
+                                     &mut self.attrs[top * width..bottom * width],
+                                     &mut self.lines[top..bottom]);
+        let blank = if up{
+            cells.rotate_left(n * width);
+            attrs.rotate_left(n * width);
+            lines.rotate_left(n);
+            lines.len() - n..lines.len()
+        }
+        else{
+            cells.rotate_right(n * width);
+            attrs.rotate_right(n * width);
+            lines.rotate_right(n);
+            0..n
+        };
+        for row in blank{
+            for i in row * width..(row + 1) * width{
+                cells[i] = BLANK_TILE;
+                attrs[i] = self.pen;
+            }
+            lines[row] = LineSize::Single;
+        }
+    }
+
+    // Blanks cells `from` to `to`, in the pen's colours.
+    fn fill(&mut self, from: u32, to: u32){
+        let end = ::std::cmp::min(to, self.width * self.height);
+        for i in from..end{
+            self.cells[i as usize] = BLANK_TILE;
+            self.attrs[i as usize] = self.pen;
+        }
+    }
+
+    // ED: 0 clears from the cursor to the end of the screen, 1 from the start
+    // of the screen to the cursor and 2 the whole screen.
+    pub fn erase_in_display(&mut self, mode: u32){
+        let cursor = self.cursor_y * self.width + self.cursor_x;
+        let end    = self.width * self.height;
+        match mode{
+            0 => self.fill(cursor, end),
+            1 => self.fill(0, cursor + 1),
+            _ => {
+                self.fill(0, end);
+                for line in self.lines.iter_mut(){
+                    *line = LineSize::Single;
+                }
+            }
+        }
+    }
+
+    // EL: like `erase_in_display`, but limited to the cursor's row.
+    pub fn erase_in_line(&mut self, mode: u32){
+        let row    = self.cursor_y * self.width;
+        let cursor = row + self.cursor_x;
+        match mode{
+            0 => self.fill(cursor, row + self.width),
+            1 => self.fill(row, cursor + 1),
+            _ => self.fill(row, row + self.width)
+        }
+    }
+
+    // Changes the size of the grid, keeping whatever fits of the old contents
+    // anchored to the top left.
+    pub fn resize(&mut self, width: u32, height: u32){
+        if width == self.width && height == self.height{
+            return;
+        }
+        let mut cells = vec![BLANK_TILE; (width * height) as usize];
+        let mut attrs = vec![DEFAULT_ATTR; (width * height) as usize];
+        for y in 0..::std::cmp::min(height, self.height){
+            for x in 0..::std::cmp::min(width, self.width){
+                let (to, from) = ((y * width + x) as usize, (y * self.width + x) as usize);
+                cells[to] = self.cells[from];
+                attrs[to] = self.attrs[from];
+            }
+        }
+        self.lines.resize(height as usize, LineSize::Single);
+        self.cells  = cells;
+        self.attrs  = attrs;
+        self.width  = width;
+        self.height = height;
+        self.region_top    = 0;
+        self.region_bottom = height.saturating_sub(1);
+        let (x, y)  = (self.cursor_x, self.cursor_y);
+        self.set_cursor(x, y);
+    }
+}
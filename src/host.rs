@@ -0,0 +1,252 @@
+// BBS host mode: accepts telnet connections and gives each one its own
+// screen, driven by a `Board`. Whatever changes on a screen is sent to its
+// client as ANSI.
+
+use std::io;
+use std::net::{TcpListener, TcpStream};
+
+use ansi;
+use grid::Grid;
+use session::Channel;
+use telnet::TelnetChannel;
+use terminal::Terminal;
+
+// The application a connection talks to. `screen` is what the caller sees;
+// the host takes care of getting it to them.
+pub trait Board{
+    fn connected(&mut self, screen: &mut Grid);
+    // Returns false to hang up.
+    fn input(&mut self, screen: &mut Grid, bytes: &[u8]) -> bool;
+}
+
+// Shows a fixed screen and echoes whatever the caller types below it.
+pub struct ScreenBoard{
+    template: Grid,
+    terminal: Terminal
+}
+
+impl ScreenBoard{
+    pub fn new(template: Grid) -> ScreenBoard{
+        ScreenBoard{
+            template: template,
+            terminal: Terminal::new()
+        }
+    }
+}
+
+impl Board for ScreenBoard{
+    fn connected(&mut self, screen: &mut Grid){
+        let (width, height) = (screen.width(), screen.height());
+        let stride = self.template.width();
+        screen.clear();
+        for y in 0..height{
+            for x in 0..width{
+                if let Some(tile) = self.template.get(x, y){
+                    screen.set_cell(x, y, tile, self.template.attrs()[(y * stride + x) as usize]);
+                }
+            }
+        }
+        let (x, y) = self.template.cursor();
+        screen.set_cursor(x, y);
+    }
+
+    fn input(&mut self, screen: &mut Grid, bytes: &[u8]) -> bool{
+        for &b in bytes{
+            match b{
+                b'\r'        => self.terminal.feed(screen, b"\r\n"),
+                b'\n' | 0    => (), // the LF or NUL after a CR
+                0x08  | 0x7f => self.terminal.feed(screen, b"\x08 \x08"),
+                _            => self.terminal.feed(screen, &[b])
+            }
+        }
+        true
+    }
+}
+
+pub type BoardFactory = Box<dyn Fn() -> Box<dyn Board>>;
+
+// The largest screen a caller gets, however big a window they report; the
+// size comes from whoever connects.
+const MAX_SIDE:  u32 = 1024;
+const MAX_CELLS: u32 = 256 * 1024;
+
+// The screen size for a caller's reported window size, bounded, or None
+// for a size of 0.
+fn caller_size(width: u16, height: u16) -> Option<(u32, u32)>{
+    if width == 0 || height == 0{
+        return None;
+    }
+    let width  = (width as u32).min(MAX_SIDE);
+    let height = (height as u32).min(MAX_SIDE).min(MAX_CELLS / width);
+    Some((width, height))
+}
+
+struct Caller{
+    channel: TelnetChannel<TcpStream>,
+    board:   Box<dyn Board>,
+    screen:  Grid,
+    sent:    Option<Grid>, // what the caller's terminal shows
+    input:   Vec<u8>
+}
+
+// Sends whatever changed on `screen` since `sent`.
+fn send_changes(screen: &Grid, sent: &mut Option<Grid>, out: &mut Vec<u8>){
+    let unchanged = match *sent{
+        Some(ref s) => s.width() == screen.width() && s.height() == screen.height()
+                       && s.cells() == screen.cells() && s.attrs() == screen.attrs()
+                       && s.lines() == screen.lines() && s.cursor() == screen.cursor(),
+        None        => false
+    };
+    if !unchanged{
+        ansi::write_diff(sent.as_ref(), screen, out);
+        *sent = Some(screen.clone());
+    }
+}
+
+pub struct Host{
+    listener:     TcpListener,
+    factory:      BoardFactory,
+    callers:      Vec<Caller>,
+    // the board as seen from the local console
+    local:        Box<dyn Board>,
+    local_screen: Grid,
+    local_sent:   Option<Grid>
+}
+
+impl Host{
+    pub fn listen(port: u16, factory: BoardFactory) -> io::Result<Host>{
+        let listener = match TcpListener::bind(("0.0.0.0", port)){
+            Ok(l)  => l,
+            Err(e) => return Err(e)
+        };
+        match listener.set_nonblocking(true){
+            Ok(())  => (),
+            Err(e)  => return Err(e)
+        }
+        let mut local = factory();
+        let mut local_screen = Grid::new(80, 25);
+        local.connected(&mut local_screen);
+        Ok(Host{
+            listener:     listener,
+            factory:      factory,
+            callers:      Vec::new(),
+            local:        local,
+            local_screen: local_screen,
+            local_sent:   None
+        })
+    }
+
+    pub fn caller_count(&self) -> usize{
+        self.callers.len()
+    }
+
+    fn accept(&mut self){
+        loop{
+            let stream = match self.listener.accept(){
+                Ok((s, _)) => s,
+                Err(_)     => return // WouldBlock, or nothing we can do about it
+            };
+            let channel = match TelnetChannel::accept(stream, 80, 25){
+                Ok(c)  => c,
+                Err(e) => { eprintln!("textbbs: accepting a caller: {}", e); continue; }
+            };
+            let mut board  = (self.factory)();
+            let mut screen = Grid::new(80, 25);
+            board.connected(&mut screen);
+            self.callers.push(Caller{
+                channel: channel,
+                board:   board,
+                screen:  screen,
+                sent:    None,
+                input:   Vec::new()
+            });
+        }
+    }
+
+    // Accepts new callers, runs their input through their boards and sends
+    // them whatever changed on their screens.
+    pub fn poll(&mut self){
+        self.accept();
+        for caller in self.callers.iter_mut(){
+            caller.input.clear();
+            if !caller.channel.receive(&mut caller.input){
+                continue;
+            }
+            let (width, height) = caller.channel.telnet().window_size();
+            match caller_size(width, height){
+                Some((width, height)) if width != caller.screen.width() || height != caller.screen.height() => {
+                    caller.screen.resize(width, height);
+                    caller.board.connected(&mut caller.screen);
+                },
+                _ => ()
+            }
+            if !caller.input.is_empty() && !caller.board.input(&mut caller.screen, &caller.input){
+                caller.channel.close();
+                continue;
+            }
+            let mut out = Vec::new();
+            send_changes(&caller.screen, &mut caller.sent, &mut out);
+            caller.channel.send(&out);
+        }
+        self.callers.retain(|c| c.channel.is_open());
+    }
+}
+
+// The host as the channel of a local session: the viewer shows the board
+// exactly as a caller would see it and typing in the viewer drives it.
+impl Channel for Host{
+    fn send(&mut self, bytes: &[u8]){
+        self.local.input(&mut self.local_screen, bytes);
+    }
+
+    fn receive(&mut self, buf: &mut Vec<u8>) -> bool{
+        self.poll();
+        send_changes(&self.local_screen, &mut self.local_sent, buf);
+        true
+    }
+
+    fn resize(&mut self, width: u32, height: u32){
+        self.local_screen.resize(width, height);
+        self.local.connected(&mut self.local_screen);
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn sends_a_change_of_colour_alone(){
+        let mut screen = Grid::new(4, 2);
+        screen.set(0, 0, 1);
+        let (mut sent, mut out) = (None, Vec::new());
+        send_changes(&screen, &mut sent, &mut out);
+        out.clear();
+        send_changes(&screen, &mut sent, &mut out);
+        assert!(out.is_empty());
+
+        screen.set_cell(0, 0, 1, 0x1e);
+        send_changes(&screen, &mut sent, &mut out);
+        assert!(!out.is_empty());
+    }
+
+    #[test]
+    fn serves_the_screen_in_its_colours(){
+        let mut template = Grid::new(4, 2);
+        template.set_cell(1, 1, 5, 0x4f);
+        let mut board = ScreenBoard::new(template);
+        let mut screen = Grid::new(3, 3);
+        board.connected(&mut screen);
+        assert_eq!(screen.get(1, 1), Some(5));
+        assert_eq!(screen.attrs()[4], 0x4f);
+    }
+
+    #[test]
+    fn bounds_the_size_a_caller_reports(){
+        assert_eq!(caller_size(100, 40), Some((100, 40)));
+        assert_eq!(caller_size(0, 40), None);
+        assert_eq!(caller_size(100, 0), None);
+        assert_eq!(caller_size(0xffff, 0xffff), Some((MAX_SIDE, MAX_CELLS / MAX_SIDE)));
+        assert_eq!(caller_size(1, 0xffff), Some((1, MAX_SIDE)));
+    }
+}
@@ -0,0 +1,219 @@
+use glium::glutin::{Event, ElementState, VirtualKeyCode};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub struct Modifiers{
+    pub ctrl:  bool,
+    pub shift: bool,
+    pub alt:   bool
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Chord{
+    pub key:  VirtualKeyCode,
+    pub mods: Modifiers
+}
+
+impl Chord{
+    pub fn new(key: VirtualKeyCode, ctrl: bool, shift: bool, alt: bool) -> Chord{
+        Chord{
+            key:  key,
+            mods: Modifiers{ ctrl: ctrl, shift: shift, alt: alt }
+        }
+    }
+
+    // Reads a chord written like "ctrl+shift+pageup": any of ctrl, shift
+    // and alt, then a key (see `key_named`), in any case.
+    pub fn parse(text: &str) -> Option<Chord>{
+        let text = text.to_lowercase();
+        let mut parts: Vec<&str> = text.split('+').map(|p| p.trim()).collect();
+        let key = match parts.pop().and_then(key_named){
+            Some(k) => k,
+            None    => return None
+        };
+        let mut chord = Chord::new(key, false, false, false);
+        for part in parts{
+            match part{
+                "ctrl"  => chord.mods.ctrl  = true,
+                "shift" => chord.mods.shift = true,
+                "alt"   => chord.mods.alt   = true,
+                _       => return None
+            }
+        }
+        Some(chord)
+    }
+}
+
+// Keys by lower case name: letters, digits, f1 to f12, and the arrow,
+// editing and whitespace keys by the names on them.
+pub fn key_named(name: &str) -> Option<VirtualKeyCode>{
+    use self::VirtualKeyCode as K;
+    const LETTERS: [VirtualKeyCode; 26] =
+        [K::A, K::B, K::C, K::D, K::E, K::F, K::G, K::H, K::I, K::J, K::K, K::L, K::M,
+         K::N, K::O, K::P, K::Q, K::R, K::S, K::T, K::U, K::V, K::W, K::X, K::Y, K::Z];
+    const DIGITS: [VirtualKeyCode; 10] =
+        [K::Key0, K::Key1, K::Key2, K::Key3, K::Key4, K::Key5, K::Key6, K::Key7, K::Key8, K::Key9];
+    const FUNCTION: [VirtualKeyCode; 12] =
+        [K::F1, K::F2, K::F3, K::F4, K::F5, K::F6, K::F7, K::F8, K::F9, K::F10, K::F11, K::F12];
+    let mut chars = name.chars();
+    match (chars.next(), chars.next()){
+        (Some(c @ 'a'..='z'), None) => return Some(LETTERS[c as usize - 'a' as usize]),
+        (Some(c @ '0'..='9'), None) => return Some(DIGITS[c as usize - '0' as usize]),
+        (Some('f'), Some(_))        => if let Ok(n) = name[1..].parse::<usize>(){
+            return if n >= 1 && n <= 12 { Some(FUNCTION[n - 1]) } else { None };
+        },
+        _ => ()
+    }
+    match name{
+        "left"                 => Some(K::Left),
+        "right"                => Some(K::Right),
+        "up"                   => Some(K::Up),
+        "down"                 => Some(K::Down),
+        "home"                 => Some(K::Home),
+        "end"                  => Some(K::End),
+        "pageup"               => Some(K::PageUp),
+        "pagedown"             => Some(K::PageDown),
+        "insert"               => Some(K::Insert),
+        "delete"               => Some(K::Delete),
+        "backspace"            => Some(K::Back),
+        "tab"                  => Some(K::Tab),
+        "escape"   | "esc"     => Some(K::Escape),
+        "enter"    | "return"  => Some(K::Return),
+        "space"                => Some(K::Space),
+        _                      => None
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Direction{
+    Left, Right, Up, Down
+}
+
+// Things the viewer itself does in response to a key chord, as opposed to
+// keys that are passed through to whatever has input focus.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Action{
+    SplitColumns,
+    SplitRows,
+    ClosePane,
+    FocusNext,
+    FocusPrev,
+    MoveDivider(Direction),
+    NewTab,
+    CloseTab,
+    NextTab,
+    PrevTab,
+    ScrollBack,
+    ScrollForward,
+    NextFont
+}
+
+// Actions by the names config files bind them with.
+pub const ACTION_NAMES: [(&'static str, Action); 16] =
+    [("split_columns",      Action::SplitColumns),
+     ("split_rows",         Action::SplitRows),
+     ("close_pane",         Action::ClosePane),
+     ("focus_next",         Action::FocusNext),
+     ("focus_prev",         Action::FocusPrev),
+     ("move_divider_left",  Action::MoveDivider(Direction::Left)),
+     ("move_divider_right", Action::MoveDivider(Direction::Right)),
+     ("move_divider_up",    Action::MoveDivider(Direction::Up)),
+     ("move_divider_down",  Action::MoveDivider(Direction::Down)),
+     ("new_tab",            Action::NewTab),
+     ("close_tab",          Action::CloseTab),
+     ("next_tab",           Action::NextTab),
+     ("prev_tab",           Action::PrevTab),
+     ("scroll_back",        Action::ScrollBack),
+     ("scroll_forward",     Action::ScrollForward),
+     ("next_font",          Action::NextFont)];
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Input{
+    Action(Action),
+    Key(VirtualKeyCode, Modifiers),
+    Char(char)
+}
+
+// Default bindings follow Terminator's.
+pub fn default_bindings() -> Vec<(Chord, Action)>{
+    use self::VirtualKeyCode as K;
+    vec![(Chord::new(K::E,        true,  true,  false), Action::SplitColumns),
+         (Chord::new(K::O,        true,  true,  false), Action::SplitRows),
+         (Chord::new(K::W,        true,  true,  false), Action::ClosePane),
+         (Chord::new(K::N,        true,  true,  false), Action::FocusNext),
+         (Chord::new(K::P,        true,  true,  false), Action::FocusPrev),
+         (Chord::new(K::Left,     true,  true,  false), Action::MoveDivider(Direction::Left)),
+         (Chord::new(K::Right,    true,  true,  false), Action::MoveDivider(Direction::Right)),
+         (Chord::new(K::Up,       true,  true,  false), Action::MoveDivider(Direction::Up)),
+         (Chord::new(K::Down,     true,  true,  false), Action::MoveDivider(Direction::Down)),
+         (Chord::new(K::T,        true,  true,  false), Action::NewTab),
+         (Chord::new(K::Q,        true,  true,  false), Action::CloseTab),
+         (Chord::new(K::PageDown, true,  false, false), Action::NextTab),
+         (Chord::new(K::PageUp,   true,  false, false), Action::PrevTab),
+         (Chord::new(K::PageUp,   false, true,  false), Action::ScrollBack),
+         (Chord::new(K::PageDown, false, true,  false), Action::ScrollForward),
+         (Chord::new(K::F,        true,  true,  false), Action::NextFont)]
+}
+
+// Turns raw window events into `Input`s, tracking modifier state (glutin does
+// not report it with key events) and resolving key bindings.
+pub struct Keyboard{
+    mods:          Modifiers,
+    bindings:      Vec<(Chord, Action)>,
+    swallow_chars: bool
+}
+
+impl Keyboard{
+    pub fn new(bindings: Vec<(Chord, Action)>) -> Keyboard{
+        Keyboard{
+            mods:          Modifiers::default(),
+            bindings:      bindings,
+            swallow_chars: false
+        }
+    }
+
+    pub fn modifiers(&self) -> Modifiers{
+        self.mods
+    }
+
+    pub fn handle(&mut self, event: &Event) -> Option<Input>{
+        use self::VirtualKeyCode as K;
+        match *event{
+            Event::KeyboardInput(state, _, Some(key)) => {
+                let pressed = state == ElementState::Pressed;
+                match key{
+                    K::LControl | K::RControl => { self.mods.ctrl  = pressed; return None; },
+                    K::LShift   | K::RShift   => { self.mods.shift = pressed; return None; },
+                    K::LAlt     | K::RAlt     => { self.mods.alt   = pressed; return None; },
+                    _ => ()
+                }
+                if !pressed{
+                    return None;
+                }
+                self.swallow_chars = false;
+                let chord = Chord{ key: key, mods: self.mods };
+                match self.bindings.iter().find(|&&(c, _)| c == chord){
+                    Some(&(_, action)) => {
+                        // the chord's character is not meant for the focused pane
+                        self.swallow_chars = true;
+                        Some(Input::Action(action))
+                    },
+                    None => Some(Input::Key(key, self.mods))
+                }
+            },
+            Event::ReceivedCharacter(c) => {
+                if self.swallow_chars{
+                    None
+                }
+                else{
+                    Some(Input::Char(c))
+                }
+            },
+            Event::Focused(false) => {
+                // we won't see the releases of keys held while unfocused
+                self.mods = Modifiers::default();
+                None
+            },
+            _ => None
+        }
+    }
+}
@@ -4,21 +4,523 @@ extern crate image;
 extern crate rustc_serialize;
 extern crate nalgebra;
 extern crate time;
+extern crate libc;
+extern crate rand;
+extern crate toml;
+extern crate unicode_width;
 
+mod ansi;
+mod art;
+mod asciicast;
 mod atlas;
+mod audio;
+mod boot;
+mod cli;
+mod config;
+mod cp437;
+mod editor;
+mod error;
+mod export;
+mod grid;
+mod host;
+mod input;
+mod palette;
+mod packer;
+mod pane;
+mod player;
+mod pty;
+mod sauce;
+mod serial;
+mod session;
+mod sound;
+mod tabs;
+mod telnet;
+mod terminal;
 mod textblock;
+mod throttle;
+mod tileblock;
+mod transport;
+mod ttyrec;
+mod urgency;
+mod viewer;
 mod profiling_timers;
 
-use atlas::Atlas;
-use textblock::*;
+use art::Art;
+use asciicast::Recorder;
+use atlas::{Atlas, AtlasImage, AtlasLoader, AtlasWatcher};
+use audio::{AudioSink, WavFile};
+use boot::BootScript;
+use cli::{Cli, Command, Options};
+use config::{Config, Effects};
+use editor::Editor;
+use error::Error;
+use grid::{Grid, TileMap};
+use host::{Host, ScreenBoard};
+use input::{Action, Input, Keyboard};
+use palette::Palette;
+use pane::{BorderGlyphs, Pane, Split};
+use player::{Player, Recording};
+use sauce::{LetterSpacing, Sauce};
+use session::{Channel, Loopback, Session};
+use sound::{Sound, Speaker};
+use tabs::Tabs;
+use terminal::{Charset, Terminal};
+use throttle::Throttle;
+use viewer::{Request, Viewer};
 
 use std::fs::File;
 use std::io::Read;
+use std::path::{Path, PathBuf};
+
+fn handle_action(tabs: &mut Tabs, action: Action){
+    match action{
+        Action::SplitColumns   => tabs.active().split(Split::Columns),
+        Action::SplitRows      => tabs.active().split(Split::Rows),
+        Action::ClosePane      => if !tabs.active().close() { tabs.close_active() },
+        Action::FocusNext      => tabs.active().focus_next(),
+        Action::FocusPrev      => tabs.active().focus_prev(),
+        Action::MoveDivider(d) => tabs.active().move_divider(d),
+        Action::NewTab         => tabs.open(),
+        Action::CloseTab       => tabs.close_active(),
+        Action::NextTab        => tabs.next(),
+        Action::PrevTab        => tabs.prev(),
+        Action::ScrollBack | Action::ScrollForward => {
+            let session = &mut tabs.active().focused().session;
+            let page = session.grid().height() as isize / 2;
+            session.scroll_view(if action == Action::ScrollBack { page } else { -page });
+        },
+        Action::NextFont       => () // `open` owns the atlas, so switches it itself
+    }
+}
+
+// Rows of art shown at once by the viewer, and by the editor with its status
+// line.
+const VIEWER_ROWS: u32 = 25;
+
+enum ArtMode{
+    Viewing(Viewer),
+    Editing(Editor)
+}
+
+// Prints why a command failed and gives the exit code for it.
+fn fail(error: Error) -> i32{
+    eprintln!("textbbs: {}", error);
+    cli::FAILURE
+}
+
+fn read_file(path: &Path) -> Result<Vec<u8>, Error>{
+    let mut data = Vec::new();
+    match File::open(path).and_then(|mut f| f.read_to_end(&mut data)){
+        Ok(_)  => Ok(data),
+        Err(e) => Err(Error::Io(format!("reading {}", path.display()), e))
+    }
+}
+
+// Reads a piece of art (see `art::Format`) and the atlas image to draw it
+// with: its own font, or the atlas for its SAUCE font (the configured one
+// otherwise). With `new_ok`, a file that doesn't exist yet is blank art.
+fn load_art(path: &str, config: &Config, new_ok: bool) -> Result<(Art, AtlasImage), Error>{
+    let data = match read_file(Path::new(path)){
+        Ok(data) => data,
+        // a new file to draw
+        Err(Error::Io(_, ref e)) if new_ok && e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+        Err(e) => return Err(e)
+    };
+    let (_, sauce) = Sauce::split(&data);
+    let atlas_path = art::atlas_for_font(sauce.as_ref().and_then(|s| s.font()), &config.atlas);
+    let image = match AtlasImage::load_within(&atlas_path, &config.asset_roots){
+        Ok(ok) => ok,
+        Err(e) => return Err(Error::Atlas(atlas_path, e))
+    };
+    let art = if data.is_empty(){
+        Art::blank(art::DEFAULT_WIDTH, VIEWER_ROWS)
+    }
+    else{
+        let format = art::Format::detect(Path::new(path), &data);
+        match Art::load(format, &data, &TileMap::for_art(&image.labels)){
+            Ok(art) => art,
+            Err(e)  => return Err(Error::Art(PathBuf::from(path), e))
+        }
+    };
+    // art with its own font is drawn in it instead
+    let image = match art.font{
+        Some(ref font) => match AtlasImage::from_bitmap_font(font.height, &font.bitmap, cp437_labels()){
+            Ok(ok) => ok,
+            Err(e) => return Err(Error::Atlas(PathBuf::from(path), e))
+        },
+        None => image
+    };
+    Ok((art, image))
+}
+
+fn cp437_labels() -> Vec<String>{
+    (0..256).map(|b| cp437::to_char(b as u8).to_string()).collect()
+}
+
+// The screen file without the new lines it ends with, or nothing if there
+// isn't one.
+fn read_screen(config: &Config) -> Result<Vec<u8>, Error>{
+    match read_file(&config.screen){
+        Ok(mut bytes) => {
+            while bytes.last() == Some(&b'\n'){
+                bytes.pop();
+            }
+            Ok(bytes)
+        },
+        Err(Error::Io(_, ref e)) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e)
+    }
+}
+
+// What's shown when there's no screen file: a run of every glyph.
+fn placeholder(width: u32, height: u32) -> Grid{
+    let cells = (0..width * height).map(|x| (0x10 + x % 0x50) as u16).collect();
+    Grid::from_cells(width, height, cells).unwrap_or(Grid::new(width, height))
+}
+
+// Whether `path` is named like a recording rather than art.
+fn is_recording(path: &str) -> bool{
+    match Path::new(path).extension().and_then(|e| e.to_str()).map(|e| e.to_lowercase()){
+        Some(ref e) => e == "cast" || e == "ttyrec" || e == "rec",
+        None        => false
+    }
+}
+
+// Reads an asciicast or ttyrec recording, by its extension. Anything else is
+// read as asciicast if it is and as ttyrec if not, since the ttyrec header
+// has nothing to tell it by.
+fn load_recording(path: &str) -> Result<Recording, Error>{
+    let data = match read_file(Path::new(path)){
+        Ok(data) => data,
+        Err(e)   => return Err(e)
+    };
+    let cast = || match asciicast::parse(&String::from_utf8_lossy(&data)){
+        Ok(r)  => Ok(r),
+        Err(e) => Err(Error::Cast(PathBuf::from(path), e))
+    };
+    let ttyrec = || match ttyrec::parse(&data, path){
+        Ok(r)  => Ok(r),
+        Err(e) => Err(Error::Ttyrec(PathBuf::from(path), e))
+    };
+    match Path::new(path).extension().and_then(|e| e.to_str()).map(|e| e.to_lowercase()){
+        Some(ref e) if e == "cast"                 => cast(),
+        Some(ref e) if e == "ttyrec" || e == "rec" => ttyrec(),
+        _ => match cast(){
+            Ok(r)  => Ok(r),
+            Err(_) => ttyrec()
+        }
+    }
+}
+
+// Shows a piece of art sized to its width and letter spacing until the
+// window is closed. E switches to editing it and Escape back (see `editor`);
+// `edit` starts out editing, on blank art if the file doesn't exist yet.
+fn view(display: &glium::backend::glutin_backend::GlutinFacade,
+        program: &glium::Program,
+        config:  &Config,
+        path:    &str,
+        edit:    bool) -> Result<(), Error>{
+    use glium::Surface;
+    let (art, image) = match load_art(path, config, edit){
+        Ok(ok) => ok,
+        Err(e) => return Err(e)
+    };
+    let atl = match Atlas::upload(display, image){
+        Ok(ok) => ok,
+        Err(e) => return Err(Error::Atlas(PathBuf::from(path), e))
+    };
+
+    // 8 pixel wide fonts are stretched across 9 pixel wide cells when the art
+    // asks for VGA letter spacing; the window is widened to fit, and no
+    // separate ninth column is drawn
+    let tile_w = atl.dimensions.tile_w_u as u32;
+    let cell_w = match art.sauce.as_ref().map(|s| s.letter_spacing()){
+        Some(LetterSpacing::Nine) if tile_w == 8 => 9,
+        _                                        => tile_w
+    };
+    let fit_window = |width: u32, rows: u32, title: &str| if let Some(window) = display.get_window(){
+        window.set_inner_size(width * cell_w, rows * atl.dimensions.tile_h_u as u32);
+        window.set_title(title);
+    };
+
+    let mut mode = if edit{
+        ArtMode::Editing(Editor::new(art, PathBuf::from(path), &atl, VIEWER_ROWS - 1))
+    }
+    else{
+        ArtMode::Viewing(Viewer::new(art, VIEWER_ROWS))
+    };
+    let mut switched = true;
+    loop {
+        if switched{
+            match mode{
+                ArtMode::Viewing(ref v) => fit_window(v.art().width(), v.rows(), &v.art().caption()),
+                ArtMode::Editing(ref e) => fit_window(e.width(), e.rows(), &format!("editing {}", path))
+            }
+            switched = false;
+        }
+
+        let mut target = display.draw();
+        target.clear_color(0.0, 0.0, 0.0, 1.0);
+        let drawn = match mode{
+            ArtMode::Viewing(ref mut v) => v.draw(display, program, &mut target, &atl),
+            ArtMode::Editing(ref mut e) => e.draw(display, program, &mut target, &atl)
+        };
+        if let Err(e) = drawn{
+            return Err(Error::Draw("art", e));
+        }
+        if let Err(e) = target.finish(){
+            return Err(Error::SwapBuffers(e));
+        }
+
+        for ev in display.poll_events() {
+            let request = match mode{
+                ArtMode::Viewing(ref mut v) => v.handle(&ev),
+                ArtMode::Editing(ref mut e) => e.handle(&ev, &atl)
+            };
+            match request{
+                Request::Continue   => (),
+                Request::Close      => return Ok(()),
+                Request::SwitchMode => {
+                    mode = match mode{
+                        ArtMode::Viewing(v) =>
+                            ArtMode::Editing(Editor::new(v.into_art(), PathBuf::from(path), &atl, VIEWER_ROWS - 1)),
+                        ArtMode::Editing(e) =>
+                            ArtMode::Viewing(Viewer::new(e.into_art(), VIEWER_ROWS))
+                    };
+                    switched = true;
+                    break;
+                }
+            }
+        }
+    }
+}
+
+// Plays a recording in a window sized to it until it is closed (see
+// `player`), in `palette` and `charset` and starting paused at `frame` if
+// given.
+fn play(display: &glium::backend::glutin_backend::GlutinFacade,
+        program: &glium::Program,
+        atlas:   &Atlas,
+        palette: Palette,
+        charset: Charset,
+        path:    &str,
+        frame:   Option<usize>) -> Result<(), Error>{
+    use glium::Surface;
+    let recording = match load_recording(path){
+        Ok(r)  => r,
+        Err(e) => return Err(e)
+    };
+    let (width, height) = (recording.width, recording.height);
+    let mut player = Player::new(recording, TileMap::from_labels(&atlas.labels), charset);
+    player.set_palette(palette);
+    if let Some(frame) = frame{
+        player.seek_frame(frame);
+        player.pause();
+    }
+
+    if let Some(window) = display.get_window(){
+        window.set_inner_size(width * atlas.dimensions.tile_w_u as u32,
+                              height * atlas.dimensions.tile_h_u as u32);
+    }
+    let mut title = String::new();
+
+    loop {
+        player.update();
+        if player.status() != title{
+            title = player.status();
+            if let Some(window) = display.get_window(){
+                window.set_title(&title);
+            }
+        }
+
+        let mut target = display.draw();
+        target.clear_color(0.0, 0.0, 0.0, 1.0);
+        if let Err(e) = player.draw(display, program, &mut target, atlas){
+            return Err(Error::Draw("the recording", e));
+        }
+        if let Err(e) = target.finish(){
+            return Err(Error::SwapBuffers(e));
+        }
+
+        for ev in display.poll_events() {
+            if !player.handle(&ev){
+                return Ok(());
+            }
+        }
+    }
+}
+
+// Writes art, or a recording after `frame` frames or at its end, to `output`
+// (see `export::write`) without opening a window.
+fn render(config: &Config, path: &str, output: &str, frame: Option<usize>) -> Result<(), Error>{
+    let (grid, image, palette, ice_colours, sauce) = if is_recording(path){
+        let recording = match load_recording(path){
+            Ok(r)  => r,
+            Err(e) => return Err(e)
+        };
+        let image = match AtlasImage::load_within(&config.atlas, &config.asset_roots){
+            Ok(ok) => ok,
+            Err(e) => return Err(Error::Atlas(config.atlas.clone(), e))
+        };
+        let mut player = Player::new(recording, TileMap::from_labels(&image.labels), config.charset);
+        player.seek_frame(frame.unwrap_or(std::usize::MAX));
+        let mut sauce = Sauce::new();
+        sauce.title = format!("{} frame {}", path, player.frame());
+        (player.grid().clone(), image, config.palette, false, sauce)
+    }
+    else{
+        if frame.is_some(){
+            return Err(Error::Invalid(format!("{} is art, which has no frames", path)));
+        }
+        let (art, image) = match load_art(path, config, false){
+            Ok(ok) => ok,
+            Err(e) => return Err(e)
+        };
+        let sauce = art.sauce.clone().unwrap_or(Sauce::new());
+        (art.grid, image, art.palette, art.ice_colours, sauce)
+    };
+    let tiles = if is_recording(path) { TileMap::from_labels(&image.labels) } else { TileMap::for_art(&image.labels) };
+    let style = export::Style{
+        dimensions:  image.dimensions,
+        pixels:      &image.pixels,
+        tiles:       &tiles,
+        palette:     &palette,
+        ice_colours: ice_colours
+    };
+    match export::write(Path::new(output), &grid, &style, &sauce){
+        Ok(())  => Ok(()),
+        Err(e)  => Err(Error::Io(format!("writing {}", output), e))
+    }
+}
+
+// Where to write an atlas descriptor, so long as the sheet written beside it
+// won't overwrite it.
+fn descriptor_path(output: &str) -> Result<&Path, Error>{
+    let output = Path::new(output);
+    if output.extension().map(|e| e == "png").unwrap_or(false){
+        return Err(Error::Invalid(format!("{} would be overwritten by the sheet; name the descriptor .json",
+                                          output.display())));
+    }
+    Ok(output)
+}
+
+// Makes an atlas of the 256 CP437 glyphs in a raw bitmap font, `height`
+// bytes each or the file's size over 256.
+fn build_atlas(font: &str, height: Option<u16>, output: &str) -> Result<(), Error>{
+    let bitmap = match read_file(Path::new(font)){
+        Ok(data) => data,
+        Err(e)   => return Err(e)
+    };
+    let height = height.unwrap_or((bitmap.len() / 256) as u16);
+    if height == 0 || bitmap.len() < 256 * height as usize{
+        return Err(Error::Invalid(format!("{} is too small for 256 glyphs {} pixels high", font, height)));
+    }
+    let output = match descriptor_path(output){
+        Ok(p)  => p,
+        Err(e) => return Err(e)
+    };
+    let image = match AtlasImage::from_bitmap_font(height, &bitmap[..256 * height as usize], cp437_labels()){
+        Ok(ok) => ok,
+        Err(e) => return Err(Error::Atlas(PathBuf::from(font), e))
+    };
+    match image.save(output){
+        Ok(())  => Ok(()),
+        Err(e)  => Err(Error::Atlas(output.to_path_buf(), e))
+    }
+}
+
+// Packs a directory of glyph images into an atlas (see `packer`).
+fn pack_atlas(glyphs: &str, tile: Option<(u16, u16)>, columns: u32, output: &str) -> Result<(), Error>{
+    let output = match descriptor_path(output){
+        Ok(p)  => p,
+        Err(e) => return Err(e)
+    };
+    let image = match packer::pack(Path::new(glyphs), tile, columns){
+        Ok(ok) => ok,
+        Err(e) => return Err(Error::Pack(PathBuf::from(glyphs), e))
+    };
+    match image.save(output){
+        Ok(())  => Ok(()),
+        Err(e)  => Err(Error::Atlas(output.to_path_buf(), e))
+    }
+}
+
+// Puts the effect parameters into a shader's source as #defines.
+fn with_effects(source: &str, effects: &Effects) -> String{
+    let (brightness, glow) = if effects.scanlines { (effects.brightness, effects.glow) } else { (1.0, 0.0) };
+    let defines = format!("#version 140\n\
+                           #define JITTER     {:?}\n\
+                           #define BRIGHTNESS {:?}\n\
+                           #define GLOW       {:?}\n\
+                           #define DEFAULT_ATTR {}u\n",
+                          effects.jitter, brightness, glow, grid::DEFAULT_ATTR);
+    source.replacen("#version 140\n", &defines, 1)
+}
 
 fn main() {
+    std::process::exit(run());
+}
+
+fn run() -> i32{
+    let Cli{ command, options } = match cli::parse(std::env::args().skip(1)){
+        Ok(cli) => cli,
+        Err(e)  => {
+            eprintln!("textbbs: {}\nSee textbbs help for usage.", e);
+            return cli::USAGE;
+        }
+    };
+    if let Command::Help(ref topic) = command{
+        print!("{}", cli::help(topic.as_ref().map(|t| &t[..])));
+        return cli::SUCCESS;
+    }
+
+    // settings from the command line outdo the config file's
+    let mut config = match options.config{
+        Some(ref path) => {
+            let mut config = Config::new();
+            match config.load(Path::new(path)){
+                Ok(())  => config,
+                Err(e)  => return fail(Error::Config(e))
+            }
+        },
+        None => match Config::find(){
+            Ok(config) => config,
+            Err(e)     => return fail(Error::Config(e))
+        }
+    };
+    for setting in options.settings.iter(){
+        if let Err(e) = config.set(setting){
+            eprintln!("textbbs: bad setting {}", e);
+            return cli::USAGE;
+        }
+    }
+
+    // these don't need a window
+    let result = match command{
+        Command::Render{ ref path, ref output, frame } => render(&config, path, output, frame),
+        Command::AtlasBuild{ ref font, height, ref output } => build_atlas(font, height, output),
+        Command::AtlasPack{ ref glyphs, tile, columns, ref output } => pack_atlas(glyphs, tile, columns, output),
+        _ => open(command, &options, &config)
+    };
+    match result{
+        Ok(())  => cli::SUCCESS,
+        Err(e)  => fail(e)
+    }
+}
+
+// Opens the window and runs a command in it until it is closed.
+fn open(command: Command, options: &Options, config: &Config) -> Result<(), Error>{
     use glium::{DisplayBuild, Surface};
-    use std::path::Path;
-    let display = glium::glutin::WindowBuilder::new().build_glium().unwrap(); // XXX change to .expect()
+
+    let mut window = glium::glutin::WindowBuilder::new()
+        .with_dimensions(config.window_width, config.window_height);
+    if config.fullscreen{
+        window = window.with_fullscreen(glium::glutin::get_primary_monitor());
+    }
+    let display = match window.build_glium(){
+        Ok(d)  => d,
+        Err(e) => return Err(Error::Window(e))
+    };
 
     let vertex_shader_src = r#"
         #version 140
@@ -27,10 +529,20 @@ fn main() {
         in vec2   texcoord;
         out vec2  v_tex_coord;
         out float v_position_y;
+        flat out vec3 v_foreground;
+        flat out vec3 v_background;
+        flat out float v_phosphor;
 
         uniform mat3 matrix;
         uniform usampler1D tile_id;
+        uniform usampler1D tile_attr;
+        uniform usampler1D tile_span; // see `grid::layout`
+        uniform sampler1D  palette;
+        uniform bool ice_colours;
+        uniform bool phosphor; // see `TextBlock::set_phosphor`
         uniform uint atlas_columns;
+        uniform float page_height; // the share of the atlas each page takes, spare pixels and all
+        uniform uint page_map;   // where each page is in the atlas; see `Atlas::page_map`
         uniform float tile_width;
         uniform float tile_height;
         uniform float scanline_y;
@@ -43,16 +555,34 @@ fn main() {
         void main() {
             int this_tile       = gl_VertexID / 4; // 6 triangles per tile
                                                    // but 4 is what works so \_-(o_o)-_/
-            uint atlas_index    = texelFetch(tile_id, this_tile, 0).x;
+            uint tile           = texelFetch(tile_id, this_tile, 0).x;
+            uint page           = (page_map >> (tile / 8192u * 3u)) & 7u; // see `grid::PAGE_SHIFT`
+            uint atlas_index    = tile % 8192u;
             vec2 atlas_position = vec2(mod(atlas_index , atlas_columns) * tile_width,
-                                          (atlas_index / atlas_columns) * tile_height);
-            v_tex_coord         = vec2(texcoord.x + atlas_position.x,
-                                       texcoord.y - atlas_position.y);
+                                       float(page) * page_height + (atlas_index / atlas_columns) * tile_height);
+            // a cell of a wide or double size character shows only part of its tile
+            uint span           = texelFetch(tile_span, this_tile, 0).x;
+            uint half           = (span >> 4u) & 3u;
+            vec2 corner         = vec2(texcoord.x / tile_width,
+                                       (1.0 - texcoord.y) / tile_height);
+            corner.x            = (float(span & 3u) + corner.x) / float(1u << ((span >> 2u) & 3u));
+            if(half != 0u){
+                corner.y        = (float(half - 1u) + corner.y) / 2.0;
+            }
+            v_tex_coord         = vec2(atlas_position.x + corner.x * tile_width,
+                                       1.0 - atlas_position.y - corner.y * tile_height);
             vec2 seed           = v_tex_coord + vec2(scanline_y, scanline_y * atlas_position.x);
             gl_Position         = vec4(matrix * vec3(position.x,
-                                                     position.y + rand(seed) * 0.05,
+                                                     position.y + rand(seed) * JITTER,
                                                      1.0), 1.0);
             v_position_y        = gl_Position.y;
+
+            // without iCE colours the top bit means blink, which isn't shown
+            uint attr           = texelFetch(tile_attr, this_tile, 0).x;
+            uint background     = ice_colours ? attr / 16u : (attr / 16u) % 8u;
+            v_foreground        = texelFetch(palette, int(attr % 16u), 0).rgb;
+            v_background        = texelFetch(palette, int(background), 0).rgb;
+            v_phosphor          = (phosphor && attr == DEFAULT_ATTR) ? 1.0 : 0.0;
         }
     "#;
 
@@ -61,60 +591,250 @@ fn main() {
 
         in vec2  v_tex_coord;
         in float v_position_y;
+        flat in vec3 v_foreground;
+        flat in vec3 v_background;
+        flat in float v_phosphor;
         out vec4 color;
 
         uniform sampler2D tex;
         uniform float     scanline_y;
+        uniform float     flash; // the visual bell, reversing the colours
 
         void main() {
             float scantensity = max(0,1.0 - (distance(-v_position_y, scanline_y*2 - 1.0) * 8.0));
-            color = texture(tex, v_tex_coord) * max(0.5, scantensity);
-            float increase = (scantensity*scantensity) * 0.03;
+            vec4  glyph = texture(tex, v_tex_coord);
+            color = mix(vec4(mix(v_background, v_foreground, glyph.a), 1.0), glyph, v_phosphor)
+                    * max(BRIGHTNESS, scantensity);
+            float increase = (scantensity*scantensity) * GLOW;
             color.b = color.b + increase;
             color.g = color.g + increase;
+            color.rgb = mix(color.rgb, vec3(1.0) - color.rgb, flash);
         }
     "#;
 
-    let program = glium::Program::from_source(&display, vertex_shader_src, fragment_shader_src, None).unwrap();
+    let program = match glium::Program::from_source(&display,
+                                                    &with_effects(vertex_shader_src, &config.effects),
+                                                    &with_effects(fragment_shader_src, &config.effects),
+                                                    None){
+        Ok(p)  => p,
+        Err(e) => return Err(Error::Shader(e))
+    };
+
+    if let Command::View{ ref path, edit } = command{
+        return view(&display, &program, config, path, edit);
+    }
 
-    let atl = match Atlas::new_from_file_blocking(&display, Path::new("atlas.json")){
+    let mut atl = match Atlas::new_from_file_blocking(&display, &config.atlas, &config.asset_roots){
         Ok(ok) => ok,
-        Err(e) => return println!("Failed to load Atlas {:?}", e)
+        Err(e) => return Err(Error::Atlas(config.atlas.clone(), e))
     };
 
-    display
-        .get_window()
-        .unwrap()
-        .set_inner_size(800,
-                        600);
+    if let Command::Play{ ref path, frame } = command{
+        return play(&display, &program, &atl, config.palette, config.charset, path, frame);
+    }
+
+    let (width, height) = (config.grid_width, config.grid_height);
+
+    // started before connecting, so the modem is heard while it connects
+    let sounds = if config.sound.enabled{
+        match Speaker::start(&config.sound.player, config.sound.volumes){
+            Ok(s)  => Some(s),
+            Err(e) => { eprintln!("textbbs: no sound effects: {}", e); None }
+        }
+    }
+    else{
+        None
+    };
+    if let Some(ref s) = sounds{
+        s.play(Sound::Degauss);
+        if let Command::Connect(_) = command{
+            s.play(Sound::Handshake);
+        }
+    }
 
-    let mut bytevec: Vec<u8> = Vec::new();
-    let mut textvec: Vec<u16> = Vec::new();
-    match File::open("screen.init"){
-        Ok(mut file) => { file.read_to_end(&mut bytevec);
-                          textvec = bytevec.iter().map(|&x| (-0x20i32 + x as i32) as u16).collect(); },
-        Err(_) => { textvec = (0u32..(80u32*25u32)).map(|x| (0x10 + x % 0x50) as u16).collect(); }
+    let mut pattern = false; // no screen file; show the placeholder pattern
+    let (title, channel): (String, Box<dyn Channel>) = match command{
+        Command::Serve(port) => {
+            let template = match read_screen(config){
+                Ok(ref bytes) if bytes.is_empty() => placeholder(width, height),
+                Ok(bytes) => {
+                    let mut grid = Grid::new(width, height);
+                    let mut terminal = Terminal::new();
+                    terminal.set_charset(config.charset);
+                    terminal.set_tiles(TileMap::from_labels(&atl.labels));
+                    terminal.feed(&mut grid, &bytes);
+                    grid
+                },
+                Err(e) => return Err(e)
+            };
+            let factory: host::BoardFactory =
+                Box::new(move || Box::new(ScreenBoard::new(template.clone())));
+            match Host::listen(port, factory){
+                Ok(host) => (format!("serving :{}", port), Box::new(host)),
+                Err(e)   => return Err(Error::Io(format!("listening on port {}", port), e))
+            }
+        },
+        Command::Connect(ref url) => match transport::connect(url, width, height){
+            Ok(channel) => (url.clone(), channel),
+            Err(e)      => return Err(Error::Io(format!("connecting to {}", url), e))
+        },
+        Command::Shell(ref shell_command) => {
+            let url = format!("shell:{}", shell_command.as_ref().map(|c| &c[..]).unwrap_or(""));
+            match transport::connect(&url, width, height){
+                Ok(channel) => (shell_command.clone().unwrap_or(String::from("shell")), channel),
+                Err(e)      => return Err(Error::Io(format!("starting {}", url), e))
+            }
+        },
+        _ => match config.boot{
+            // a boot script plays out instead, in its own time
+            Some(ref path) => {
+                let script = match read_file(path){
+                    Ok(bytes) => bytes,
+                    Err(e)    => return Err(e)
+                };
+                match boot::parse(&String::from_utf8_lossy(&script)){
+                    Ok(steps) => (path.display().to_string(), Box::new(BootScript::new(steps))),
+                    Err(e)    => return Err(Error::Boot(path.clone(), e))
+                }
+            },
+            None => {
+                // the screen file is played through the terminal, so it paints
+                // in at the chosen baud rate
+                let bytes = match read_screen(config){
+                    Ok(b)  => b,
+                    Err(e) => return Err(e)
+                };
+                pattern = bytes.is_empty();
+                (config.screen.display().to_string(), Box::new(Loopback::with_output(&bytes)))
+            }
+        }
+    };
+    let channel: Box<dyn Channel> = match options.baud{
+        Some(rate) => Box::new(Throttle::new(channel, rate, options.noise)),
+        None       => channel
     };
+    let mut first =
+        if pattern{
+            Session::from_grid(&title, placeholder(width, height), channel)
+        }
+        else{
+            Session::new(&title, width, height, channel)
+        };
+    first.set_charset(config.charset);
+    first.set_tiles(TileMap::from_labels(&atl.labels));
+    if let Some(ref path) = options.record{
+        match Recorder::create(Path::new(path), width, height, &title){
+            Ok(recorder) => first.record(recorder),
+            Err(e)       => return Err(Error::Io(format!("recording to {}", path), e))
+        }
+    }
+    let mut tabs = Tabs::new(Pane::from_session(first), width, height,
+                             BorderGlyphs::from_atlas(&atl));
+    tabs.set_palette(config.palette);
+    tabs.set_scrolling(config.scrolling);
+    tabs.set_visual_bell(config.bell.visual);
+    let mut keyboard = Keyboard::new(config.bindings.clone());
 
+    // other fonts, and changes to the one showing, are read in the
+    // background and swapped in when ready
+    let fonts: Vec<PathBuf> = Some(config.atlas.clone()).into_iter().chain(config.fonts.iter().cloned()).collect();
+    let mut font = 0;
+    let mut watcher = AtlasWatcher::new(&config.atlas, &config.asset_roots);
+    let mut loader: Option<AtlasLoader> = None;
 
-    let mut tb = match TextBlock::new(&display, &atl, 80, 25, Some(&textvec)){
-        Ok(ok) => ok,
-        Err(e) => return println!("Failed to create TextBlock {:?}", e)
+    // the bell is also heard, and marks the window urgent if it rings while
+    // something else is focused
+    let beep = config.bell.beep.samples();
+    let mut speaker: Option<Box<dyn AudioSink>> = match config.bell.wav{
+        _ if !config.bell.audible => None,
+        Some(ref path)            => Some(Box::new(WavFile::new(path))),
+        None                      => Some(Box::new(audio::Player::new(&config.bell.player)))
     };
+    let (mut focused, mut urgent) = (true, false);
+    let click = || if let Some(ref s) = sounds { s.play(Sound::KeyClick) };
 
     loop {
+        if tabs.process(){
+            let played = match speaker{
+                Some(ref mut s) => s.play(&beep),
+                None            => Ok(())
+            };
+            if let Err(e) = played{
+                eprintln!("textbbs: stopped sounding the bell: {}", e);
+                speaker = None;
+            }
+            if config.bell.urgent && !focused && !urgent{
+                urgency::set_urgent(&display, true);
+                urgent = true;
+            }
+        }
+
+        if loader.is_none() && watcher.changed(){
+            loader = Some(AtlasLoader::spawn(watcher.path(), &config.asset_roots));
+        }
+        let loaded = match loader{
+            Some(ref l) => l.poll(&display).map(|result| (l.path().to_path_buf(), result)),
+            None        => None
+        };
+        if let Some((path, result)) = loaded{
+            loader = None;
+            match result{
+                Ok(new) => {
+                    tabs.set_glyphs(BorderGlyphs::from_atlas(&new));
+                    tabs.set_tiles(TileMap::from_labels(&new.labels));
+                    atl = new;
+                    if watcher.path() != path.as_path(){
+                        watcher = AtlasWatcher::new(&path, &config.asset_roots);
+                    }
+                },
+                // keep showing the old one
+                Err(e) => eprintln!("textbbs: {}", Error::Atlas(path, e))
+            }
+        }
+
         let mut target = display.draw();
         target.clear_color(0.0, 0.0, 0.0, 1.0);
 
-        tb.draw(&display, &program, &mut target, &atl);
+        if let Err(e) = tabs.draw(&display, &program, &mut target, &atl){
+            return Err(Error::Draw("the tabs", e));
+        }
 
-        target.finish().unwrap();
+        if let Err(e) = target.finish(){
+            return Err(Error::SwapBuffers(e));
+        }
 
         for ev in display.poll_events() {
+            if let glium::glutin::Event::Focused(now) = ev{
+                focused = now;
+                if focused && urgent{
+                    urgency::set_urgent(&display, false);
+                    urgent = false;
+                }
+            }
             match ev {
-                glium::glutin::Event::Closed => return,
-                _ => ()
+                glium::glutin::Event::Closed => return Ok(()),
+                ref ev => match keyboard.handle(ev){
+                    Some(Input::Action(Action::NextFont)) => if fonts.len() > 1 && loader.is_none(){
+                        font = (font + 1) % fonts.len();
+                        loader = Some(AtlasLoader::spawn(&fonts[font], &config.asset_roots));
+                    },
+                    Some(Input::Action(action)) => handle_action(&mut tabs, action),
+                    // a letter comes as both a key and a character, and
+                    // clicks just once
+                    Some(Input::Key(key, mods)) => if tabs.active().focused().session.send_key(key, mods){
+                        click();
+                    },
+                    Some(Input::Char(c)) => {
+                        click();
+                        tabs.active().focused().session.send_char(c)
+                    },
+                    None => ()
+                }
             }
         }
+
+        if tabs.is_empty(){
+            return Ok(());
+        }
     }
 }
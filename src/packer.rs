@@ -0,0 +1,198 @@
+// Builds an atlas from a directory of glyph images, a PNG per tile named for
+// what it shows: `U+2591.png` or `0x41.png` for a character by its code
+// point, or the label itself, like `A.png`. Characters come first in code
+// point order, then longer labels in name order, so a directory packs the
+// same way every time. The space always comes first, as empty cells show
+// tile 0, and is left empty if there's no glyph for it.
+
+use image;
+use std::cmp;
+use std::error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use atlas::{AtlasErr, AtlasImage};
+
+pub const DEFAULT_COLUMNS: u32 = 16;
+
+#[derive(Debug)]
+pub enum PackErr{
+    Io(io::Error),
+    Image(PathBuf, image::ImageError),
+    NoGlyphs,
+    WrongSize(PathBuf, u32, u32), // a glyph that isn't the tile size, and its size
+    Duplicate(String),            // a label two glyphs are named for
+    Atlas(AtlasErr)
+}
+
+impl fmt::Display for PackErr{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result{
+        match *self{
+            PackErr::Io(ref e)               => write!(f, "{}", e),
+            PackErr::Image(ref path, ref e)  => write!(f, "{}: {}", path.display(), e),
+            PackErr::NoGlyphs                => write!(f, "no .png glyphs"),
+            PackErr::WrongSize(ref path, w, h) =>
+                write!(f, "{} is {}x{}, not the tile size", path.display(), w, h),
+            PackErr::Duplicate(ref label)    => write!(f, "more than one glyph for {:?}", label),
+            PackErr::Atlas(ref e)            => write!(f, "{}", e)
+        }
+    }
+}
+
+impl error::Error for PackErr{}
+
+// The label a glyph file's name (without .png) stands for.
+pub fn label_for(stem: &str) -> Option<String>{
+    let hex = if stem.starts_with("U+") || stem.starts_with("u+") || stem.starts_with("0x"){
+        Some(&stem[2..])
+    }
+    else{
+        None
+    };
+    match hex{
+        Some(digits) => u32::from_str_radix(digits, 16).ok()
+            .and_then(::std::char::from_u32)
+            .map(|c| c.to_string()),
+        None if stem.is_empty() => None,
+        None                    => Some(String::from(stem))
+    }
+}
+
+fn single_char(label: &str) -> Option<char>{
+    let mut chars = label.chars();
+    match (chars.next(), chars.next()){
+        (Some(c), None) => Some(c),
+        _               => None
+    }
+}
+
+// Characters by code point, then everything else by name.
+fn label_order(a: &String, b: &String) -> cmp::Ordering{
+    match (single_char(a), single_char(b)){
+        (Some(x), Some(y)) => x.cmp(&y),
+        (Some(_), None)    => cmp::Ordering::Less,
+        (None, Some(_))    => cmp::Ordering::Greater,
+        (None, None)       => a.cmp(b)
+    }
+}
+
+// Packs the glyphs in `dir` into a sheet `columns` tiles wide. Every glyph
+// must be `tile_size`, or the size of the first if that isn't given.
+pub fn pack(dir: &Path, tile_size: Option<(u16, u16)>, columns: u32) -> Result<AtlasImage, PackErr>{
+    let entries = match fs::read_dir(dir){
+        Ok(e)  => e,
+        Err(e) => return Err(PackErr::Io(e))
+    };
+    let mut glyphs = Vec::new();
+    for entry in entries{
+        let path = match entry{
+            Ok(e)  => e.path(),
+            Err(e) => return Err(PackErr::Io(e))
+        };
+        let is_png = path.extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.eq_ignore_ascii_case("png"))
+            .unwrap_or(false);
+        let hidden = path.file_name().and_then(|n| n.to_str()).map(|n| n.starts_with('.')).unwrap_or(true);
+        let label = path.file_stem().and_then(|s| s.to_str()).and_then(label_for);
+        match label{
+            Some(label) if is_png && !hidden => glyphs.push((label, path)),
+            _ => ()
+        }
+    }
+    if glyphs.is_empty(){
+        return Err(PackErr::NoGlyphs);
+    }
+    glyphs.sort_by(|a, b| label_order(&a.0, &b.0));
+    if let Some(pair) = glyphs.windows(2).find(|pair| pair[0].0 == pair[1].0){
+        return Err(PackErr::Duplicate(pair[0].0.clone()));
+    }
+
+    if let Some(i) = glyphs.iter().position(|g| g.0 == " "){
+        let space = glyphs.remove(i);
+        glyphs.insert(0, space);
+    }
+
+    let mut tile_size = tile_size.map(|(w, h)| (w as u32, h as u32));
+    let mut raws = Vec::new();
+    for &(_, ref path) in glyphs.iter(){
+        let glyph = match image::open(path){
+            Ok(g)  => g.to_rgba(),
+            Err(e) => return Err(PackErr::Image(path.clone(), e))
+        };
+        let (w, h) = glyph.dimensions();
+        if (w, h) != *tile_size.get_or_insert((w, h)){
+            return Err(PackErr::WrongSize(path.clone(), w, h));
+        }
+        raws.push(glyph.into_raw());
+    }
+    let (tile_w, tile_h) = tile_size.unwrap_or((0, 0));
+    let mut labels: Vec<String> = glyphs.into_iter().map(|(label, _)| label).collect();
+    if labels[0] != " "{
+        labels.insert(0, String::from(" "));
+        raws.insert(0, vec![0u8; (tile_w * tile_h * 4) as usize]);
+    }
+
+    let columns = cmp::max(cmp::min(columns, labels.len() as u32), 1);
+    let rows    = (labels.len() as u32 + columns - 1) / columns;
+    let (sheet_w, sheet_h) = (columns as u64 * tile_w as u64, rows as u64 * tile_h as u64);
+    if sheet_w > ::std::u16::MAX as u64 || sheet_h > ::std::u16::MAX as u64{
+        return Err(PackErr::Atlas(AtlasErr::ImageTooLarge(sheet_w as u32, sheet_h as u32)));
+    }
+    let mut pixels = vec![0u8; (sheet_w * sheet_h * 4) as usize];
+    for (i, raw) in raws.iter().enumerate(){
+        // top row first, as the sheet is stored
+        let sheet_w = (columns * tile_w) as usize;
+        let (left, top) = ((i as u32 % columns * tile_w) as usize, (i as u32 / columns * tile_h) as usize);
+        for y in 0..tile_h as usize{
+            let from = y * tile_w as usize * 4;
+            let to   = ((top + y) * sheet_w + left) * 4;
+            pixels[to..to + tile_w as usize * 4].copy_from_slice(&raw[from..from + tile_w as usize * 4]);
+        }
+    }
+
+    match AtlasImage::new(columns * tile_w, rows * tile_h, tile_w as u16, tile_h as u16, labels, pixels){
+        Ok(atlas) => Ok(atlas),
+        Err(e)    => Err(PackErr::Atlas(e))
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use std::env;
+    use std::process;
+
+    // A directory of solid 2x3 glyphs, one per label, in the given grey.
+    fn glyph_dir(name: &str, glyphs: &[(&str, u8)]) -> PathBuf{
+        let dir = env::temp_dir().join(format!("textbbs-packer-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        for &(file, grey) in glyphs{
+            image::save_buffer(&dir.join(file), &[grey; 2 * 3 * 4], 2, 3, image::ColorType::RGBA(8)).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn leaves_tile_0_blank_without_a_space(){
+        let dir = glyph_dir("blank", &[("A.png", 255), ("0x42.png", 255)]);
+        let atlas = pack(&dir, None, DEFAULT_COLUMNS).unwrap();
+        let _ = fs::remove_dir_all(&dir);
+        assert_eq!(atlas.labels, vec![" ", "A", "B"]);
+        // the first tile's top row, in a sheet 3 tiles wide
+        assert!(atlas.pixels[..2 * 4].iter().all(|&p| p == 0));
+        assert!(atlas.pixels[2 * 4..6 * 4].iter().all(|&p| p == 255));
+    }
+
+    #[test]
+    fn packs_the_space_first(){
+        let dir = glyph_dir("space", &[("U+0001.png", 255), ("U+0020.png", 128), ("A.png", 255)]);
+        let atlas = pack(&dir, None, DEFAULT_COLUMNS).unwrap();
+        let _ = fs::remove_dir_all(&dir);
+        assert_eq!(atlas.labels, vec![" ", "\u{1}", "A"]);
+        assert!(atlas.pixels[..2 * 4].iter().all(|&p| p == 128));
+    }
+}
@@ -0,0 +1,25 @@
+// The 16 colours that cell attributes index, uploaded to the shader with each
+// `TextBlock`.
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Palette{
+    pub colours: [(u8, u8, u8); 16]
+}
+
+impl Palette{
+    // The standard VGA text-mode colours, in attribute order.
+    pub fn vga() -> Palette{
+        Palette{
+            colours: [
+                (0x00, 0x00, 0x00), (0x00, 0x00, 0xaa), (0x00, 0xaa, 0x00), (0x00, 0xaa, 0xaa),
+                (0xaa, 0x00, 0x00), (0xaa, 0x00, 0xaa), (0xaa, 0x55, 0x00), (0xaa, 0xaa, 0xaa),
+                (0x55, 0x55, 0x55), (0x55, 0x55, 0xff), (0x55, 0xff, 0x55), (0x55, 0xff, 0xff),
+                (0xff, 0x55, 0x55), (0xff, 0x55, 0xff), (0xff, 0xff, 0x55), (0xff, 0xff, 0xff)
+            ]
+        }
+    }
+}
+
+// ANSI colour numbers (SGR 30-37) in the order black, red, green, yellow,
+// blue, magenta, cyan, white, mapped to the attribute's blue-first order.
+pub const ANSI_TO_ATTR: [u8; 8] = [0, 4, 2, 6, 1, 5, 3, 7];
@@ -0,0 +1,505 @@
+use glium;
+use std::mem;
+use time::PreciseTime;
+
+use atlas::Atlas;
+use grid::{Grid, TileMap, char_to_tile};
+use input::Direction;
+use palette::Palette;
+use session::{Loopback, Scrolling, Session};
+use textblock::{GridView, TextBlockErr};
+
+// How far one `MoveDivider` moves a divider, as a fraction of the split.
+const DIVIDER_STEP: f32 = 0.05;
+
+// How long the visual bell takes to fade.
+const FLASH_MS: i64 = 150;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Split{
+    Columns, // side by side, divided by a vertical line
+    Rows     // stacked, divided by a horizontal line
+}
+
+// A rectangle measured in cells, origin at the top left.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct CellRect{
+    pub x: u32,
+    pub y: u32,
+    pub w: u32,
+    pub h: u32
+}
+
+pub struct Pane{
+    pub session: Session,
+    view:        GridView,
+    flashed:     Option<PreciseTime> // when the visual bell last went off
+}
+
+impl Pane{
+    // A pane with a fresh session that echoes its own input.
+    pub fn new(width: u32, height: u32) -> Pane{
+        Pane::from_session(Session::new("local", width, height, Box::new(Loopback::new())))
+    }
+
+    pub fn from_session(session: Session) -> Pane{
+        Pane{
+            session: session,
+            view:    GridView::new(),
+            flashed: None
+        }
+    }
+
+    // Sets off the visual bell.
+    pub fn flash(&mut self){
+        self.flashed = Some(PreciseTime::now());
+    }
+
+    fn draw<F>(&mut self,
+               display:  &F,
+               program:  &glium::Program,
+               target:   &mut glium::Frame,
+               atlas:    &Atlas,
+               viewport: Option<glium::Rect>) -> Result<(), TextBlockErr>
+        where F: glium::backend::Facade{
+        let (width, height) = (self.session.grid().width(), self.session.grid().height());
+        let (cells, attrs, lines) = self.session.visible();
+        self.view.set_shift(self.session.scroll_shift());
+        let flash = match self.flashed{
+            Some(at) => match at.to(PreciseTime::now()).num_milliseconds(){
+                ms if ms < FLASH_MS => 1.0 - ms as f32 / FLASH_MS as f32,
+                _                   => { self.flashed = None; 0.0 }
+            },
+            None => 0.0
+        };
+        self.view.set_flash(flash);
+        self.view.draw(display, program, target, atlas,
+                       width, height, &cells, &attrs, &lines, viewport)
+    }
+}
+
+enum Node{
+    Leaf(Pane),
+    Split{
+        split:  Split,
+        ratio:  f32, // fraction of the space given to `first`
+        first:  Box<Node>,
+        second: Box<Node>
+    }
+}
+
+impl Node{
+    fn is_leaf(&self) -> bool{
+        match *self{
+            Node::Leaf(_) => true,
+            _             => false
+        }
+    }
+}
+
+// Splits `rect` for a divider of one cell. Returns the rectangles of the first
+// child, the divider and the second child.
+fn split_rect(rect: CellRect, split: Split, ratio: f32) -> (CellRect, CellRect, CellRect){
+    let avail = match split{
+        Split::Columns => rect.w,
+        Split::Rows    => rect.h
+    }.saturating_sub(1);
+    let first = ((avail as f32 * ratio).round() as u32).min(avail);
+    let second = avail - first;
+    match split{
+        Split::Columns => (CellRect{ x: rect.x,             y: rect.y, w: first,  h: rect.h },
+                           CellRect{ x: rect.x + first,     y: rect.y, w: 1,      h: rect.h },
+                           CellRect{ x: rect.x + first + 1, y: rect.y, w: second, h: rect.h }),
+        Split::Rows    => (CellRect{ x: rect.x, y: rect.y,             w: rect.w, h: first  },
+                           CellRect{ x: rect.x, y: rect.y + first,     w: rect.w, h: 1      },
+                           CellRect{ x: rect.x, y: rect.y + first + 1, w: rect.w, h: second })
+    }
+}
+
+// Visits the leaves in order, calling `f` with each pane and its rectangle.
+fn each_leaf<T>(node: &mut Node, rect: CellRect, f: &mut T)
+    where T: FnMut(&mut Pane, CellRect){
+    match *node{
+        Node::Leaf(ref mut pane) => f(pane, rect),
+        Node::Split{split, ratio, ref mut first, ref mut second} => {
+            let (a, _, b) = split_rect(rect, split, ratio);
+            each_leaf(first,  a, f);
+            each_leaf(second, b, f);
+        }
+    }
+}
+
+fn each_divider<T>(node: &Node, rect: CellRect, f: &mut T)
+    where T: FnMut(Split, CellRect){
+    if let Node::Split{split, ratio, ref first, ref second} = *node{
+        let (a, divider, b) = split_rect(rect, split, ratio);
+        f(split, divider);
+        each_divider(first,  a, f);
+        each_divider(second, b, f);
+    }
+}
+
+fn leaf_count(node: &Node) -> usize{
+    match *node{
+        Node::Leaf(_)                          => 1,
+        Node::Split{ref first, ref second, ..} => leaf_count(first) + leaf_count(second)
+    }
+}
+
+fn nth_leaf<'a>(node: &'a mut Node, n: usize, seen: &mut usize) -> Option<&'a mut Node>{
+    if node.is_leaf(){
+        *seen += 1;
+        return if *seen - 1 == n { Some(node) } else { None };
+    }
+    match *node{
+        Node::Split{ref mut first, ref mut second, ..} => {
+            match nth_leaf(first, n, seen){
+                Some(leaf) => Some(leaf),
+                None       => nth_leaf(second, n, seen)
+            }
+        },
+        Node::Leaf(_) => None
+    }
+}
+
+// Removes leaf `n`, replacing its parent split with the sibling.
+fn close_leaf(node: &mut Node, n: usize, seen: &mut usize) -> bool{
+    let keep_first = match *node{
+        Node::Leaf(_) => { *seen += 1; return false; },
+        Node::Split{ref mut first, ref mut second, ..} => {
+            if first.is_leaf() && *seen == n{
+                false
+            }
+            else if close_leaf(first, n, seen){
+                return true;
+            }
+            else if second.is_leaf() && *seen == n{
+                true
+            }
+            else{
+                return close_leaf(second, n, seen);
+            }
+        }
+    };
+    let old = mem::replace(node, Node::Leaf(Pane::new(0, 0)));
+    if let Node::Split{first, second, ..} = old{
+        *node = if keep_first { *first } else { *second };
+    }
+    true
+}
+
+enum Found{
+    No,
+    Leaf, // the leaf is below, but no matching split has been found yet
+    Done
+}
+
+// Moves the nearest divider of orientation `split` enclosing leaf `n`.
+fn move_divider(node: &mut Node, n: usize, seen: &mut usize, split: Split, delta: f32) -> Found{
+    match *node{
+        Node::Leaf(_) => {
+            *seen += 1;
+            if *seen - 1 == n { Found::Leaf } else { Found::No }
+        },
+        Node::Split{split: s, ref mut ratio, ref mut first, ref mut second} => {
+            let found = match move_divider(first, n, seen, split, delta){
+                Found::No => move_divider(second, n, seen, split, delta),
+                other     => other
+            };
+            match found{
+                Found::Leaf if s == split => {
+                    *ratio = (*ratio + delta).max(0.0).min(1.0);
+                    Found::Done
+                },
+                other => other
+            }
+        }
+    }
+}
+
+// Glyphs used for the lines between panes. The dividers next to the focused
+// pane are drawn heavier.
+#[derive(Copy, Clone)]
+pub struct BorderGlyphs{
+    pub horizontal:       u16,
+    pub vertical:         u16,
+    pub cross:            u16,
+    pub focus_horizontal: u16,
+    pub focus_vertical:   u16
+}
+
+impl BorderGlyphs{
+    // Uses the box drawing characters if the atlas labels them, otherwise
+    // falls back to ASCII.
+    pub fn from_atlas(atlas: &Atlas) -> BorderGlyphs{
+        let pick = |label: &str, fallback: char|
+            atlas.tile_for_label(label).unwrap_or(char_to_tile(fallback));
+        BorderGlyphs{
+            horizontal:       pick("\u{2500}", '-'),
+            vertical:         pick("\u{2502}", '|'),
+            cross:            pick("\u{253c}", '+'),
+            focus_horizontal: pick("\u{2501}", '='),
+            focus_vertical:   pick("\u{2503}", '#')
+        }
+    }
+}
+
+// Tiles a screen of `width` x `height` cells into panes separated by
+// one-cell dividers. Exactly one pane has input focus.
+pub struct PaneManager{
+    root:        Node,
+    focus:       usize,
+    width:       u32,
+    height:      u32,
+    borders:     Grid,
+    border_view: GridView,
+    glyphs:      BorderGlyphs,
+    palette:     Palette,
+    scrolling:   Scrolling,
+    tiles:       TileMap,
+    visual_bell: bool
+}
+
+impl PaneManager{
+    pub fn new(first: Pane, width: u32, height: u32, glyphs: BorderGlyphs) -> PaneManager{
+        let mut pm = PaneManager{
+            root:        Node::Leaf(first),
+            focus:       0,
+            width:       width,
+            height:      height,
+            borders:     Grid::new(width, height),
+            border_view: GridView::new(),
+            glyphs:      glyphs,
+            palette:     Palette::vga(),
+            scrolling:   Scrolling::vt220(),
+            tiles:       TileMap::ascii(),
+            visual_bell: true
+        };
+        pm.relayout();
+        pm
+    }
+
+    fn screen(&self) -> CellRect{
+        CellRect{ x: 0, y: 0, w: self.width, h: self.height }
+    }
+
+    pub fn pane_count(&self) -> usize{
+        leaf_count(&self.root)
+    }
+
+    pub fn focused(&mut self) -> &mut Pane{
+        let focus = self.focus;
+        match nth_leaf(&mut self.root, focus, &mut 0){
+            Some(&mut Node::Leaf(ref mut pane)) => pane,
+            _ => unreachable!("focus always refers to an existing pane")
+        }
+    }
+
+    pub fn focused_rect(&mut self) -> CellRect{
+        let (focus, mut i, mut found) = (self.focus, 0, self.screen());
+        let screen = self.screen();
+        each_leaf(&mut self.root, screen, &mut |_, rect| {
+            if i == focus { found = rect; }
+            i += 1;
+        });
+        found
+    }
+
+    pub fn each_pane<T>(&mut self, mut f: T) where T: FnMut(&mut Pane){
+        let screen = self.screen();
+        each_leaf(&mut self.root, screen, &mut |pane, _| f(pane));
+    }
+
+    // Colours every pane, and any opened later, with `palette`.
+    pub fn set_palette(&mut self, palette: Palette){
+        self.palette = palette;
+        self.border_view.set_palette(palette);
+        self.each_pane(|pane| pane.view.set_palette(palette));
+    }
+
+    // Scrolls every pane's session, and any opened later, as `scrolling`
+    // says.
+    pub fn set_scrolling(&mut self, scrolling: Scrolling){
+        self.scrolling = scrolling;
+        self.each_pane(|pane| pane.session.set_scrolling(scrolling));
+    }
+
+    // Draws every pane's session, and any opened later, with `tiles`.
+    pub fn set_tiles(&mut self, tiles: TileMap){
+        self.each_pane(|pane| pane.session.set_tiles(tiles.clone()));
+        self.tiles = tiles;
+    }
+
+    // Whether panes flash when their bell rings.
+    pub fn set_visual_bell(&mut self, visual: bool){
+        self.visual_bell = visual;
+    }
+
+    // Redraws the dividers with `glyphs`, as for another atlas.
+    pub fn set_glyphs(&mut self, glyphs: BorderGlyphs){
+        self.glyphs = glyphs;
+        self.relayout();
+    }
+
+    // Splits the focused pane in two and focuses the new half.
+    pub fn split(&mut self, split: Split){
+        let focus = self.focus;
+        let mut pane = Pane::new(0, 0);
+        pane.view.set_palette(self.palette);
+        pane.session.set_scrolling(self.scrolling);
+        pane.session.set_tiles(self.tiles.clone());
+        if let Some(leaf) = nth_leaf(&mut self.root, focus, &mut 0){
+            let old = mem::replace(leaf, Node::Leaf(Pane::new(0, 0)));
+            *leaf = Node::Split{
+                split:  split,
+                ratio:  0.5,
+                first:  Box::new(old),
+                second: Box::new(Node::Leaf(pane))
+            };
+        }
+        self.focus += 1;
+        self.relayout();
+    }
+
+    // Closes the focused pane. The last pane can't be closed; returns whether
+    // anything happened.
+    pub fn close(&mut self) -> bool{
+        let focus = self.focus;
+        if !close_leaf(&mut self.root, focus, &mut 0){
+            return false;
+        }
+        self.focus = focus.saturating_sub(1);
+        self.relayout();
+        true
+    }
+
+    pub fn focus_next(&mut self){
+        self.focus = (self.focus + 1) % self.pane_count();
+    }
+
+    pub fn focus_prev(&mut self){
+        let count = self.pane_count();
+        self.focus = (self.focus + count - 1) % count;
+    }
+
+    pub fn move_divider(&mut self, direction: Direction){
+        let (split, delta) = match direction{
+            Direction::Left  => (Split::Columns, -DIVIDER_STEP),
+            Direction::Right => (Split::Columns,  DIVIDER_STEP),
+            Direction::Up    => (Split::Rows,    -DIVIDER_STEP),
+            Direction::Down  => (Split::Rows,     DIVIDER_STEP)
+        };
+        let focus = self.focus;
+        move_divider(&mut self.root, focus, &mut 0, split, delta);
+        self.relayout();
+    }
+
+    pub fn resize(&mut self, width: u32, height: u32){
+        self.width  = width;
+        self.height = height;
+        self.borders.resize(width, height);
+        self.relayout();
+    }
+
+    // Sizes every pane's grid to its rectangle and redraws the dividers.
+    fn relayout(&mut self){
+        let screen = self.screen();
+        each_leaf(&mut self.root, screen, &mut |pane, rect| pane.session.resize(rect.w, rect.h));
+
+        let glyphs = &self.glyphs;
+        let grid   = &mut self.borders;
+        grid.clear();
+        each_divider(&self.root, screen, &mut |split, rect| {
+            for y in rect.y..rect.y + rect.h{
+                for x in rect.x..rect.x + rect.w{
+                    let tile = match (split, grid.get(x, y)){
+                        (_, Some(t)) if t == glyphs.cross                  => glyphs.cross,
+                        (Split::Columns, Some(t)) if t == glyphs.horizontal => glyphs.cross,
+                        (Split::Rows,    Some(t)) if t == glyphs.vertical   => glyphs.cross,
+                        (Split::Columns, _) => glyphs.vertical,
+                        (Split::Rows,    _) => glyphs.horizontal
+                    };
+                    grid.set(x, y, tile);
+                }
+            }
+        });
+    }
+
+    fn mark_focus(&mut self){
+        let rect = self.focused_rect();
+        let glyphs = &self.glyphs;
+        let grid   = &mut self.borders;
+        for y in rect.y..rect.y + rect.h{
+            if rect.x > 0                  { grid.set(rect.x - 1,      y, glyphs.focus_vertical); }
+            if rect.x + rect.w < self.width{ grid.set(rect.x + rect.w, y, glyphs.focus_vertical); }
+        }
+        for x in rect.x..rect.x + rect.w{
+            if rect.y > 0                   { grid.set(x, rect.y - 1,      glyphs.focus_horizontal); }
+            if rect.y + rect.h < self.height{ grid.set(x, rect.y + rect.h, glyphs.focus_horizontal); }
+        }
+    }
+
+    // Runs pending output through every pane's session. Returns whether any
+    // of them rang the bell.
+    pub fn process(&mut self) -> bool{
+        let (visual, mut rang) = (self.visual_bell, false);
+        self.each_pane(|pane| {
+            pane.session.process();
+            if pane.session.take_bell(){
+                rang = true;
+                if visual{
+                    pane.flash();
+                }
+            }
+        });
+        rang
+    }
+
+    // Draws the dividers across `area` (in pixels), then each pane in its own
+    // viewport on top of them.
+    pub fn draw<F>(&mut self,
+                   display: &F,
+                   program: &glium::Program,
+                   target:  &mut glium::Frame,
+                   atlas:   &Atlas,
+                   area:    glium::Rect) -> Result<(), TextBlockErr>
+        where F: glium::backend::Facade{
+        self.relayout();
+        if self.pane_count() > 1{
+            self.mark_focus();
+            let (width, height) = (self.width, self.height);
+            match self.border_view.draw(display, program, target, atlas,
+                                        width, height, self.borders.cells(),
+                                        self.borders.attrs(), &[], Some(area)){
+                Ok(())  => (),
+                Err(e)  => return Err(e)
+            }
+        }
+
+        let (columns, rows) = (self.width, self.height);
+        let screen = self.screen();
+        let mut result = Ok(());
+        each_leaf(&mut self.root, screen, &mut |pane, rect| {
+            if rect.w == 0 || rect.h == 0 || result.is_err(){
+                return;
+            }
+            let viewport = cell_viewport(area, columns, rows, rect);
+            result = pane.draw(display, program, target, atlas, Some(viewport));
+        });
+        result
+    }
+}
+
+// The pixel rectangle covered by `rect` when `area` is divided into
+// `columns` x `rows` cells.
+pub fn cell_viewport(area: glium::Rect, columns: u32, rows: u32, rect: CellRect) -> glium::Rect{
+    let (cell_w, cell_h) = (area.width  as f32 / columns as f32,
+                            area.height as f32 / rows    as f32);
+    let top = ((rect.y + rect.h) as f32 * cell_h) as u32;
+    glium::Rect{
+        left:   area.left + (rect.x as f32 * cell_w) as u32,
+        bottom: area.bottom + area.height.saturating_sub(top),
+        width:  (rect.w as f32 * cell_w) as u32,
+        height: (rect.h as f32 * cell_h) as u32
+    }
+}
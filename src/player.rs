@@ -0,0 +1,214 @@
+// Plays a recorded session back through the terminal, with pausing, seeking
+// by time or frame and changes of speed.
+//
+// Keys:
+//     Space         pause and resume
+//     Left, Right   back and forward five seconds
+//     Comma, Period back and forward a frame, pausing
+//     Up, Down      twice and half the speed
+//     Home, End     the start and the end
+//     Escape, Q     close
+
+use glium;
+use glium::glutin::{ElementState, Event, VirtualKeyCode};
+use time::PreciseTime;
+
+use atlas::Atlas;
+use grid::{Grid, TileMap};
+use palette::Palette;
+use terminal::{Charset, Terminal};
+use textblock::{GridView, TextBlockErr};
+
+const SEEK_SECONDS: f64 = 5.0;
+const MIN_SPEED:    f64 = 1.0 / 16.0;
+const MAX_SPEED:    f64 = 16.0;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum FrameData{
+    Output(Vec<u8>),
+    Resize(u32, u32)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Frame{
+    pub time: f64, // seconds from the start
+    pub data: FrameData
+}
+
+// A session as recorded: the size it started at and what happened when.
+pub struct Recording{
+    pub width:  u32,
+    pub height: u32,
+    pub title:  String,
+    pub frames: Vec<Frame>
+}
+
+impl Recording{
+    pub fn duration(&self) -> f64{
+        self.frames.last().map(|f| f.time).unwrap_or(0.0)
+    }
+}
+
+fn clock_time(seconds: f64) -> String{
+    let seconds = seconds as u64;
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+pub struct Player{
+    recording: Recording,
+    tiles:     TileMap,
+    charset:   Charset,
+    grid:      Grid,
+    terminal:  Terminal,
+    view:      GridView,
+    next:      usize, // the first frame not yet played
+    clock:     f64,   // seconds into the recording
+    speed:     f64,
+    paused:    bool,
+    last:      PreciseTime
+}
+
+impl Player{
+    // A player reading output in `charset` and drawing it with `tiles`.
+    pub fn new(recording: Recording, tiles: TileMap, charset: Charset) -> Player{
+        let grid = Grid::new(recording.width, recording.height);
+        let terminal = Terminal::with_charset(charset, tiles.clone());
+        Player{
+            recording: recording,
+            tiles:     tiles,
+            charset:   charset,
+            grid:      grid,
+            terminal:  terminal,
+            view:      GridView::new(),
+            next:      0,
+            clock:     0.0,
+            speed:     1.0,
+            paused:    false,
+            last:      PreciseTime::now()
+        }
+    }
+
+    pub fn grid(&self) -> &Grid{
+        &self.grid
+    }
+
+    pub fn set_palette(&mut self, palette: Palette){
+        self.view.set_palette(palette);
+    }
+
+    // How many frames have been played.
+    pub fn frame(&self) -> usize{
+        self.next
+    }
+
+    // The title, position and speed, for the window title.
+    pub fn status(&self) -> String{
+        format!("{} {} / {} frame {} / {} x{}{}",
+                if self.recording.title.is_empty() { "recording" } else { &self.recording.title },
+                clock_time(self.clock), clock_time(self.recording.duration()),
+                self.next, self.recording.frames.len(), self.speed,
+                if self.paused { " (paused)" } else { "" })
+    }
+
+    fn rewind(&mut self){
+        self.grid     = Grid::new(self.recording.width, self.recording.height);
+        self.terminal = Terminal::with_charset(self.charset, self.tiles.clone());
+        self.next     = 0;
+        self.clock    = 0.0;
+    }
+
+    fn play_frame(&mut self){
+        if let Some(frame) = self.recording.frames.get(self.next){
+            match frame.data{
+                FrameData::Output(ref bytes)     => self.terminal.feed(&mut self.grid, bytes),
+                FrameData::Resize(width, height) => self.grid.resize(width, height)
+            }
+            self.next += 1;
+        }
+    }
+
+    // Moves to `time` seconds in. Going back replays from the start.
+    pub fn seek(&mut self, time: f64){
+        let time = time.max(0.0).min(self.recording.duration());
+        if time < self.clock{
+            self.rewind();
+        }
+        self.clock = time;
+        while self.recording.frames.get(self.next).map(|f| f.time <= time).unwrap_or(false){
+            self.play_frame();
+        }
+    }
+
+    // Moves to just after `frame` frames have been played, even when later
+    // frames were recorded at the same moment.
+    pub fn seek_frame(&mut self, frame: usize){
+        let frame = ::std::cmp::min(frame, self.recording.frames.len());
+        if frame < self.next{
+            self.rewind();
+        }
+        while self.next < frame{
+            self.play_frame();
+        }
+        self.clock = match self.next{
+            0    => 0.0,
+            next => self.recording.frames[next - 1].time
+        };
+    }
+
+    pub fn toggle_pause(&mut self){
+        self.paused = !self.paused;
+    }
+
+    pub fn pause(&mut self){
+        self.paused = true;
+    }
+
+    // Plays whatever falls due since the last update. Called every frame.
+    pub fn update(&mut self){
+        let now = PreciseTime::now();
+        let elapsed = match self.last.to(now).num_microseconds(){
+            Some(us) => us as f64 / 1_000_000.0,
+            None     => 0.0
+        };
+        self.last = now;
+        if !self.paused && self.clock < self.recording.duration(){
+            let time = self.clock + elapsed * self.speed;
+            self.seek(time);
+        }
+    }
+
+    // Controls playback from the keyboard. Returns false once the player is
+    // closed with Escape or Q.
+    pub fn handle(&mut self, event: &Event) -> bool{
+        let (clock, next) = (self.clock, self.next);
+        match *event{
+            Event::KeyboardInput(ElementState::Pressed, _, Some(key)) => match key{
+                VirtualKeyCode::Escape | VirtualKeyCode::Q => return false,
+                VirtualKeyCode::Space                      => self.toggle_pause(),
+                VirtualKeyCode::Left                       => self.seek(clock - SEEK_SECONDS),
+                VirtualKeyCode::Right                      => self.seek(clock + SEEK_SECONDS),
+                VirtualKeyCode::Comma                      => { self.pause(); self.seek_frame(next.saturating_sub(1)) },
+                VirtualKeyCode::Period                     => { self.pause(); self.seek_frame(next + 1) },
+                VirtualKeyCode::Up                         => self.speed = (self.speed * 2.0).min(MAX_SPEED),
+                VirtualKeyCode::Down                       => self.speed = (self.speed / 2.0).max(MIN_SPEED),
+                VirtualKeyCode::Home                       => self.seek(0.0),
+                VirtualKeyCode::End                        => self.seek(::std::f64::INFINITY),
+                _ => ()
+            },
+            Event::Closed => return false,
+            _ => ()
+        }
+        true
+    }
+
+    pub fn draw<F>(&mut self,
+                   display: &F,
+                   program: &glium::Program,
+                   target:  &mut glium::Frame,
+                   atlas:   &Atlas) -> Result<(), TextBlockErr>
+        where F: glium::backend::Facade{
+        let grid = &self.grid;
+        self.view.draw(display, program, target, atlas, grid.width(), grid.height(),
+                       grid.cells(), grid.attrs(), grid.lines(), None)
+    }
+}
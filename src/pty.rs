@@ -0,0 +1,150 @@
+// Runs a program on a pseudo terminal, for local shells.
+
+use libc;
+use std::env;
+use std::ffi::CStr;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::{Read, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::os::unix::process::CommandExt;
+use std::process::{Child, Command, Stdio};
+
+use transport::Transport;
+
+pub const TERM: &'static str = "ansi";
+
+pub struct Pty{
+    master: File,
+    child:  Child
+}
+
+fn set_size(fd: RawFd, width: u32, height: u32) -> io::Result<()>{
+    let size = libc::winsize{
+        ws_row:    height as libc::c_ushort,
+        ws_col:    width  as libc::c_ushort,
+        ws_xpixel: 0,
+        ws_ypixel: 0
+    };
+    if unsafe{ libc::ioctl(fd, libc::TIOCSWINSZ, &size) } != 0{
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+// Opens a new pseudo terminal, giving its master end and the path of its
+// slave; serial tests use the pair as a null modem.
+pub fn open_master() -> io::Result<(File, String)>{
+    unsafe{
+        let fd = libc::posix_openpt(libc::O_RDWR | libc::O_NOCTTY);
+        if fd < 0{
+            return Err(io::Error::last_os_error());
+        }
+        // owned from here on, so it is closed on every error path
+        let master = File::from_raw_fd(fd);
+        if libc::grantpt(fd) != 0 || libc::unlockpt(fd) != 0{
+            return Err(io::Error::last_os_error());
+        }
+        let mut name = [0 as libc::c_char; 128];
+        if libc::ptsname_r(fd, name.as_mut_ptr(), name.len()) != 0{
+            return Err(io::Error::last_os_error());
+        }
+        let slave = CStr::from_ptr(name.as_ptr()).to_string_lossy().into_owned();
+        Ok((master, slave))
+    }
+}
+
+impl Pty{
+    // Runs `command` through `/bin/sh -c`, or the user's shell if there is
+    // no command.
+    pub fn spawn(command: Option<&str>, width: u32, height: u32) -> io::Result<Pty>{
+        let (master, slave_path) = match open_master(){
+            Ok(m)  => m,
+            Err(e) => return Err(e)
+        };
+        match set_size(master.as_raw_fd(), width, height){
+            Ok(())  => (),
+            Err(e)  => return Err(e)
+        }
+        let slave = match OpenOptions::new()
+            .read(true)
+            .write(true)
+            .custom_flags(libc::O_NOCTTY)
+            .open(&slave_path)
+        {
+            Ok(f)  => f,
+            Err(e) => return Err(e)
+        };
+
+        let mut cmd = match command{
+            Some(c) => { let mut cmd = Command::new("/bin/sh"); cmd.arg("-c").arg(c); cmd },
+            None    => Command::new(env::var("SHELL").unwrap_or(String::from("/bin/sh")))
+        };
+        let (stdin, stdout) = match (slave.try_clone(), slave.try_clone()){
+            (Ok(a), Ok(b)) => (a, b),
+            (Err(e), _) | (_, Err(e)) => return Err(e)
+        };
+        cmd.stdin(Stdio::from(stdin))
+           .stdout(Stdio::from(stdout))
+           .stderr(Stdio::from(slave))
+           .env("TERM", TERM)
+           .env("COLUMNS", width.to_string())
+           .env("LINES", height.to_string());
+        unsafe{
+            // become a session leader with the slave as controlling terminal,
+            // so job control and ^C work
+            cmd.pre_exec(|| {
+                if libc::setsid() < 0 || libc::ioctl(0, libc::TIOCSCTTY as _, 0) < 0{
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+        let child = match cmd.spawn(){
+            Ok(c)  => c,
+            Err(e) => return Err(e)
+        };
+
+        unsafe{
+            let fd = master.as_raw_fd();
+            let flags = libc::fcntl(fd, libc::F_GETFL);
+            if flags < 0 || libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK) < 0{
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(Pty{
+            master: master,
+            child:  child
+        })
+    }
+}
+
+impl Read for Pty{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize>{
+        self.master.read(buf)
+    }
+}
+
+impl Write for Pty{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize>{
+        self.master.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()>{
+        self.master.flush()
+    }
+}
+
+impl Transport for Pty{
+    fn resize(&mut self, width: u32, height: u32){
+        let _ = set_size(self.master.as_raw_fd(), width, height);
+    }
+}
+
+impl Drop for Pty{
+    fn drop(&mut self){
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
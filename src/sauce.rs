@@ -0,0 +1,293 @@
+// SAUCE, the metadata record appended to scene art files: a 128 byte record
+// at the very end, optionally preceded by a block of comment lines, and
+// usually by an EOF (^Z) that stops DOS from typing it out.
+
+use cp437;
+
+const RECORD_LEN:  usize = 128;
+const COMMENT_LEN: usize = 64;
+const EOF:         u8    = 0x1a;
+
+// SAUCE data types
+pub const DATA_CHARACTER:   u8 = 1;
+pub const DATA_BINARY_TEXT: u8 = 5;
+pub const DATA_XBIN:        u8 = 6;
+
+// file types of `DATA_CHARACTER`
+pub const FILE_ANSI: u8 = 1;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LetterSpacing{
+    Legacy, // no preference given
+    Eight,
+    Nine    // VGA's 9 pixel wide cells
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Sauce{
+    pub title:     String,
+    pub author:    String,
+    pub group:     String,
+    pub date:      String, // CCYYMMDD
+    pub file_size: u32,
+    pub data_type: u8,
+    pub file_type: u8,
+    pub tinfo:     [u16; 4],
+    pub comments:  Vec<String>,
+    pub flags:     u8,
+    pub font:      String
+}
+
+fn field(bytes: &[u8]) -> String{
+    let end = bytes.iter().rposition(|&b| b != b' ' && b != 0).map(|i| i + 1).unwrap_or(0);
+    cp437::decode(&bytes[..end])
+}
+
+// Encodes `text` into a fixed width field, padded with `pad`.
+fn put_field(out: &mut Vec<u8>, text: &str, len: usize, pad: u8){
+    let start = out.len();
+    out.extend(text.chars().map(|c| cp437::from_char(c).unwrap_or(b'?')).take(len));
+    out.resize(start + len, pad);
+}
+
+fn le16(bytes: &[u8]) -> u16{
+    bytes[0] as u16 | (bytes[1] as u16) << 8
+}
+
+fn le32(bytes: &[u8]) -> u32{
+    le16(bytes) as u32 | (le16(&bytes[2..]) as u32) << 16
+}
+
+impl Sauce{
+    // A record with nothing filled in.
+    pub fn new() -> Sauce{
+        Sauce{
+            title:     String::new(),
+            author:    String::new(),
+            group:     String::new(),
+            date:      String::new(),
+            file_size: 0,
+            data_type: 0,
+            file_type: 0,
+            tinfo:     [0; 4],
+            comments:  Vec::new(),
+            flags:     0,
+            font:      String::new()
+        }
+    }
+
+    // The comment block, if there are comments, and the record, ready to go
+    // after the EOF that ends the content.
+    pub fn to_bytes(&self) -> Vec<u8>{
+        let mut out = Vec::with_capacity(RECORD_LEN);
+        let comments = ::std::cmp::min(self.comments.len(), 255);
+        if comments > 0{
+            out.extend_from_slice(b"COMNT");
+            for line in self.comments.iter().take(comments){
+                put_field(&mut out, line, COMMENT_LEN, b' ');
+            }
+        }
+        out.extend_from_slice(b"SAUCE00");
+        put_field(&mut out, &self.title,  35, b' ');
+        put_field(&mut out, &self.author, 20, b' ');
+        put_field(&mut out, &self.group,  20, b' ');
+        put_field(&mut out, &self.date,    8, b' ');
+        for i in 0..4{
+            out.push((self.file_size >> (i * 8)) as u8);
+        }
+        out.push(self.data_type);
+        out.push(self.file_type);
+        for &t in self.tinfo.iter(){
+            out.push(t as u8);
+            out.push((t >> 8) as u8);
+        }
+        out.push(comments as u8);
+        out.push(self.flags);
+        put_field(&mut out, &self.font, 22, 0);
+        out
+    }
+
+    // Splits `data` into the content and its SAUCE record, if it has one.
+    // The content stops at the first EOF either way.
+    pub fn split(data: &[u8]) -> (&[u8], Option<Sauce>){
+        let (mut content, sauce) = match Sauce::parse(data){
+            Some((sauce, len)) => (&data[..len], Some(sauce)),
+            None               => (data, None)
+        };
+        if let Some(eof) = content.iter().position(|&b| b == EOF){
+            content = &content[..eof];
+        }
+        (content, sauce)
+    }
+
+    // Like `split`, for binary formats where ^Z is just another byte: only
+    // an EOF right before the record is taken off.
+    pub fn split_binary(data: &[u8]) -> (&[u8], Option<Sauce>){
+        match Sauce::parse(data){
+            Some((sauce, len)) => {
+                let len = if len > 0 && data[len - 1] == EOF { len - 1 } else { len };
+                (&data[..len], Some(sauce))
+            },
+            None => (data, None)
+        }
+    }
+
+    // Reads the record at the end of `data`, also returning how many bytes
+    // come before it and its comments.
+    pub fn parse(data: &[u8]) -> Option<(Sauce, usize)>{
+        if data.len() < RECORD_LEN{
+            return None;
+        }
+        let start  = data.len() - RECORD_LEN;
+        let record = &data[start..];
+        if &record[0..7] != b"SAUCE00"{
+            return None;
+        }
+
+        let count = record[104] as usize;
+        let mut comments = Vec::new();
+        let mut content_len = start;
+        if count > 0 && start >= 5 + count * COMMENT_LEN{
+            let block = start - count * COMMENT_LEN - 5;
+            if &data[block..block + 5] == b"COMNT"{
+                for i in 0..count{
+                    let line = block + 5 + i * COMMENT_LEN;
+                    comments.push(field(&data[line..line + COMMENT_LEN]));
+                }
+                content_len = block;
+            }
+        }
+
+        Some((Sauce{
+            title:     field(&record[7..42]),
+            author:    field(&record[42..62]),
+            group:     field(&record[62..82]),
+            date:      field(&record[82..90]),
+            file_size: le32(&record[90..94]),
+            data_type: record[94],
+            file_type: record[95],
+            tinfo:     [le16(&record[96..]), le16(&record[98..]),
+                        le16(&record[100..]), le16(&record[102..])],
+            comments:  comments,
+            flags:     record[105],
+            font:      field(&record[106..128])
+        }, content_len))
+    }
+
+    // Width in characters, for the types that record one.
+    pub fn width(&self) -> Option<u32>{
+        let width = match self.data_type{
+            DATA_CHARACTER   => self.tinfo[0] as u32,
+            DATA_BINARY_TEXT => self.file_type as u32 * 2,
+            DATA_XBIN        => self.tinfo[0] as u32,
+            _                => 0
+        };
+        if width > 0 { Some(width) } else { None }
+    }
+
+    // Height in lines, for the types that record one.
+    pub fn height(&self) -> Option<u32>{
+        let height = match self.data_type{
+            DATA_CHARACTER | DATA_XBIN => self.tinfo[1] as u32,
+            _                          => 0
+        };
+        if height > 0 { Some(height) } else { None }
+    }
+
+    // Whether the blink bit selects a bright background instead.
+    pub fn ice_colours(&self) -> bool{
+        self.flags & 0x01 != 0
+    }
+
+    pub fn letter_spacing(&self) -> LetterSpacing{
+        match (self.flags >> 1) & 0x03{
+            1 => LetterSpacing::Eight,
+            2 => LetterSpacing::Nine,
+            _ => LetterSpacing::Legacy
+        }
+    }
+
+    // The font the art was drawn for, such as "IBM VGA".
+    pub fn font(&self) -> Option<&str>{
+        if self.font.is_empty() { None } else { Some(&self.font) }
+    }
+
+    // "title by author/group", leaving out what is blank.
+    pub fn caption(&self) -> String{
+        let mut caption = if self.title.is_empty() { String::from("untitled") } else { self.title.clone() };
+        let by = match (self.author.is_empty(), self.group.is_empty()){
+            (false, false) => format!("{}/{}", self.author, self.group),
+            (false, true)  => self.author.clone(),
+            (true,  false) => self.group.clone(),
+            (true,  true)  => String::new()
+        };
+        if !by.is_empty(){
+            caption.push_str(" by ");
+            caption.push_str(&by);
+        }
+        caption
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    fn record() -> Sauce{
+        let mut sauce = Sauce::new();
+        sauce.title     = String::from("Title");
+        sauce.author    = String::from("Someone");
+        sauce.data_type = DATA_CHARACTER;
+        sauce.file_type = FILE_ANSI;
+        sauce.tinfo     = [132, 50, 0, 0];
+        sauce.flags     = 0x05;
+        sauce.font      = String::from("IBM VGA");
+        sauce
+    }
+
+    #[test]
+    fn reads_the_record_and_its_comments(){
+        let mut sauce = record();
+        sauce.comments = vec![String::from("first"), String::from("second")];
+        let mut data = b"art\x1a".to_vec();
+        data.extend_from_slice(&sauce.to_bytes());
+        assert_eq!(data.len(), 4 + 5 + 2 * COMMENT_LEN + RECORD_LEN);
+
+        let (read, len) = Sauce::parse(&data).unwrap();
+        assert_eq!(read, sauce);
+        assert_eq!(len, 4);
+        assert_eq!((read.width(), read.height()), (Some(132), Some(50)));
+        assert!(read.ice_colours());
+        assert_eq!(read.letter_spacing(), LetterSpacing::Nine);
+        assert_eq!(read.caption(), "Title by Someone");
+    }
+
+    #[test]
+    fn keeps_the_record_without_a_comment_block_it_claims(){
+        let mut data = b"art".to_vec();
+        let mut bytes = record().to_bytes();
+        bytes[104] = 3;
+        data.extend_from_slice(&bytes);
+        let (read, len) = Sauce::parse(&data).unwrap();
+        assert!(read.comments.is_empty());
+        assert_eq!(len, 3);
+    }
+
+    #[test]
+    fn ignores_data_without_a_record(){
+        assert!(Sauce::parse(b"SAUCE00").is_none());
+        assert!(Sauce::parse(&[b' '; RECORD_LEN]).is_none());
+        assert_eq!(Sauce::split(b"text\x1ajunk"), (&b"text"[..], None));
+    }
+
+    #[test]
+    fn splits_binary_data_only_at_the_record(){
+        let mut data = b"\x1a\x07\x1a\x1a".to_vec();
+        data.extend_from_slice(&record().to_bytes());
+        let (content, sauce) = Sauce::split_binary(&data);
+        assert_eq!(content, b"\x1a\x07\x1a");
+        assert!(sauce.is_some());
+        assert_eq!(Sauce::split(&data).0, b"");
+        assert_eq!(Sauce::split_binary(b"\x1a\x07"), (&b"\x1a\x07"[..], None));
+    }
+}
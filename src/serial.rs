@@ -0,0 +1,283 @@
+// RS-232 serial ports (`/dev/ttyS*`, `/dev/ttyUSB*` or either end of a
+// socat-made pty pair), configured through termios.
+
+use libc;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::{Read, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+
+use transport::Transport;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Parity{
+    None,
+    Odd,
+    Even
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FlowControl{
+    None,
+    Hardware, // RTS/CTS
+    Software  // XON/XOFF
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SerialConfig{
+    pub baud:      u32,
+    pub data_bits: u8,
+    pub parity:    Parity,
+    pub stop_bits: u8,
+    pub flow:      FlowControl
+}
+
+fn invalid(message: String) -> io::Error{
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+impl SerialConfig{
+    pub fn new() -> SerialConfig{
+        SerialConfig{
+            baud:      9600,
+            data_bits: 8,
+            parity:    Parity::None,
+            stop_bits: 1,
+            flow:      FlowControl::None
+        }
+    }
+
+    // Parses comma separated settings in any order, e.g. "2400,7E1,xonxoff".
+    // Anything not given stays at 9600 8N1 without flow control.
+    pub fn parse(spec: &str) -> io::Result<SerialConfig>{
+        let mut config = SerialConfig::new();
+        for part in spec.split(',').map(|p| p.trim()).filter(|p| !p.is_empty()){
+            let bytes = part.as_bytes();
+            if let Ok(baud) = part.parse::<u32>(){
+                config.baud = baud;
+            }
+            else if bytes.len() == 3 && (bytes[0] as char).is_digit(10){
+                config.data_bits = bytes[0] - b'0';
+                config.parity = match bytes[1]{
+                    b'N' | b'n' => Parity::None,
+                    b'O' | b'o' => Parity::Odd,
+                    b'E' | b'e' => Parity::Even,
+                    _           => return Err(invalid(format!("unknown parity in {}", part)))
+                };
+                config.stop_bits = bytes[2].wrapping_sub(b'0');
+            }
+            else{
+                config.flow = match part{
+                    "none"                 => FlowControl::None,
+                    "rtscts"  | "hardware" => FlowControl::Hardware,
+                    "xonxoff" | "software" => FlowControl::Software,
+                    _ => return Err(invalid(format!("unknown serial setting {}", part)))
+                };
+            }
+        }
+        if config.data_bits < 5 || config.data_bits > 8{
+            return Err(invalid(format!("{} data bits is not supported", config.data_bits)));
+        }
+        if config.stop_bits != 1 && config.stop_bits != 2{
+            return Err(invalid(format!("{} stop bits is not supported", config.stop_bits)));
+        }
+        if baud_constant(config.baud).is_none(){
+            return Err(invalid(format!("{} baud is not supported", config.baud)));
+        }
+        Ok(config)
+    }
+}
+
+fn baud_constant(baud: u32) -> Option<libc::speed_t>{
+    Some(match baud{
+        50     => libc::B50,
+        75     => libc::B75,
+        110    => libc::B110,
+        134    => libc::B134,
+        150    => libc::B150,
+        200    => libc::B200,
+        300    => libc::B300,
+        600    => libc::B600,
+        1200   => libc::B1200,
+        1800   => libc::B1800,
+        2400   => libc::B2400,
+        4800   => libc::B4800,
+        9600   => libc::B9600,
+        19200  => libc::B19200,
+        38400  => libc::B38400,
+        57600  => libc::B57600,
+        115200 => libc::B115200,
+        230400 => libc::B230400,
+        _      => return None
+    })
+}
+
+pub struct Serial{
+    file: File
+}
+
+impl Serial{
+    pub fn open(path: &Path, config: &SerialConfig) -> io::Result<Serial>{
+        let file = match OpenOptions::new()
+            .read(true)
+            .write(true)
+            .custom_flags(libc::O_NOCTTY | libc::O_NONBLOCK)
+            .open(path)
+        {
+            Ok(f)  => f,
+            Err(e) => return Err(e)
+        };
+        let speed = match baud_constant(config.baud){
+            Some(s) => s,
+            None    => return Err(invalid(format!("{} baud is not supported", config.baud)))
+        };
+
+        let fd = file.as_raw_fd();
+        unsafe{
+            let mut tio: libc::termios = ::std::mem::zeroed();
+            if libc::tcgetattr(fd, &mut tio) != 0{
+                return Err(io::Error::last_os_error());
+            }
+            libc::cfmakeraw(&mut tio);
+
+            tio.c_cflag &= !(libc::CSIZE | libc::PARENB | libc::PARODD | libc::CSTOPB | libc::CRTSCTS);
+            tio.c_cflag |= libc::CLOCAL | libc::CREAD;
+            tio.c_cflag |= match config.data_bits{
+                5 => libc::CS5,
+                6 => libc::CS6,
+                7 => libc::CS7,
+                _ => libc::CS8
+            };
+            match config.parity{
+                Parity::None => (),
+                Parity::Odd  => tio.c_cflag |= libc::PARENB | libc::PARODD,
+                Parity::Even => tio.c_cflag |= libc::PARENB
+            }
+            if config.stop_bits == 2{
+                tio.c_cflag |= libc::CSTOPB;
+            }
+
+            tio.c_iflag &= !(libc::IXON | libc::IXOFF | libc::IXANY);
+            match config.flow{
+                FlowControl::None     => (),
+                FlowControl::Hardware => tio.c_cflag |= libc::CRTSCTS,
+                FlowControl::Software => tio.c_iflag |= libc::IXON | libc::IXOFF
+            }
+
+            // reads return whatever is there, without waiting
+            tio.c_cc[libc::VMIN]  = 0;
+            tio.c_cc[libc::VTIME] = 0;
+
+            if libc::cfsetispeed(&mut tio, speed) != 0 || libc::cfsetospeed(&mut tio, speed) != 0{
+                return Err(io::Error::last_os_error());
+            }
+            if libc::tcsetattr(fd, libc::TCSANOW, &tio) != 0{
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(Serial{ file: file })
+    }
+}
+
+impl Read for Serial{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize>{
+        match self.file.read(buf){
+            // with VMIN 0 an empty read means no data rather than hang-up
+            Ok(0)  => Err(io::Error::new(io::ErrorKind::WouldBlock, "no data")),
+            other  => other
+        }
+    }
+}
+
+impl Write for Serial{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize>{
+        self.file.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()>{
+        self.file.flush()
+    }
+}
+
+impl Transport for Serial{}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use pty;
+    use session::Channel;
+    use std::thread;
+    use std::time::Duration;
+    use transport;
+
+    #[test]
+    fn parses_settings_in_any_order(){
+        assert_eq!(SerialConfig::parse("").unwrap(), SerialConfig::new());
+        assert_eq!(SerialConfig::parse(" xonxoff, 7e2 ,2400").unwrap(),
+                   SerialConfig{ baud: 2400, data_bits: 7, parity: Parity::Even, stop_bits: 2, flow: FlowControl::Software });
+        assert_eq!(SerialConfig::parse("115200,5O1,rtscts").unwrap(),
+                   SerialConfig{ baud: 115200, data_bits: 5, parity: Parity::Odd, stop_bits: 1, flow: FlowControl::Hardware });
+        assert_eq!(SerialConfig::parse("hardware,none").unwrap().flow, FlowControl::None);
+    }
+
+    #[test]
+    fn rejects_what_termios_cant_do(){
+        for spec in &["9601", "9N1", "8X1", "8N3", "8N", "fast", "4N1"]{
+            match SerialConfig::parse(spec){
+                Err(ref e) if e.kind() == io::ErrorKind::InvalidInput => (),
+                other => panic!("{}: {:?}", spec, other)
+            }
+        }
+    }
+
+    // What the line was set to; a pty keeps the speed, stop bits and flow
+    // control, though not the character size or parity.
+    fn line(spec: &str) -> libc::termios{
+        let (_master, slave) = pty::open_master().unwrap();
+        let serial = Serial::open(Path::new(&slave), &SerialConfig::parse(spec).unwrap()).unwrap();
+        let mut tio: libc::termios = unsafe{ ::std::mem::zeroed() };
+        assert_eq!(unsafe{ libc::tcgetattr(serial.file.as_raw_fd(), &mut tio) }, 0);
+        tio
+    }
+
+    #[test]
+    fn sets_up_the_line(){
+        let tio = line("2400,8N2,rtscts");
+        assert_eq!(unsafe{ libc::cfgetospeed(&tio) }, libc::B2400);
+        assert!(tio.c_cflag & libc::CSTOPB != 0);
+        assert!(tio.c_cflag & libc::CRTSCTS != 0);
+        assert_eq!(tio.c_iflag & (libc::IXON | libc::IXOFF), 0);
+
+        let tio = line("300,xonxoff");
+        assert_eq!(unsafe{ libc::cfgetospeed(&tio) }, libc::B300);
+        assert_eq!(tio.c_cflag & (libc::CSTOPB | libc::CRTSCTS), 0);
+        assert_eq!(tio.c_iflag & (libc::IXON | libc::IXOFF), libc::IXON | libc::IXOFF);
+    }
+
+    // A pty pair stands in for two serial ports joined by a null modem, as
+    // socat makes them.
+    #[test]
+    fn talks_over_a_pty_pair(){
+        let (mut master, slave) = pty::open_master().unwrap();
+        let mut channel = transport::connect(&format!("serial:{},19200,8N1", slave), 80, 25).unwrap();
+
+        master.write_all(b"CONNECT 19200\r\n").unwrap();
+        let mut data = Vec::new();
+        for _ in 0..200{
+            assert!(channel.receive(&mut data));
+            if data.len() >= 15{
+                break;
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+        assert_eq!(data, b"CONNECT 19200\r\n");
+
+        // raw, so nothing is echoed or translated on the way
+        channel.send(b"ATZ\r");
+        let mut buf = [0u8; 16];
+        let n = master.read(&mut buf).unwrap();
+        assert_eq!(&buf[..n], b"ATZ\r");
+    }
+}
@@ -0,0 +1,306 @@
+use glium::glutin::VirtualKeyCode;
+use time::PreciseTime;
+
+use asciicast::Recorder;
+use grid::{Grid, LineSize, ScrollShift, TileMap, BLANK_TILE, DEFAULT_ATTR};
+use input::Modifiers;
+use terminal::{Charset, Terminal};
+
+pub const SCROLLBACK_LINES: usize = 1000;
+
+// How smooth scrolling (DECSCLM) goes: like a VT220's, output waits while
+// each row glides into place.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Scrolling{
+    pub smooth: bool, // whether sessions start in smooth scroll mode
+    pub speed:  f32   // rows a second
+}
+
+impl Scrolling{
+    pub fn vt220() -> Scrolling{
+        Scrolling{ smooth: false, speed: 6.0 }
+    }
+}
+
+// The other end of a session: the sink for keyboard input and the source of
+// the output shown on the session's grid.
+pub trait Channel{
+    fn send(&mut self, bytes: &[u8]);
+    // Appends whatever output has arrived since the last call. Returns false
+    // once the channel is closed and nothing more will arrive.
+    fn receive(&mut self, buf: &mut Vec<u8>) -> bool;
+    // Called when the session's grid changes size.
+    fn resize(&mut self, _width: u32, _height: u32){}
+}
+
+// Echoes input back as output, like a terminal with nothing attached in
+// local echo mode.
+pub struct Loopback{
+    pending: Vec<u8>
+}
+
+impl Loopback{
+    pub fn new() -> Loopback{
+        Loopback{ pending: Vec::new() }
+    }
+
+    // A loopback that starts out with `output` waiting to be received, as if
+    // a host had already sent it.
+    pub fn with_output(output: &[u8]) -> Loopback{
+        Loopback{ pending: output.to_vec() }
+    }
+}
+
+impl Channel for Loopback{
+    fn send(&mut self, bytes: &[u8]){
+        for &b in bytes{
+            match b{
+                b'\r' => self.pending.extend_from_slice(b"\r\n"),
+                0x08  => self.pending.extend_from_slice(b"\x08 \x08"),
+                _     => self.pending.push(b)
+            }
+        }
+    }
+
+    fn receive(&mut self, buf: &mut Vec<u8>) -> bool{
+        buf.extend(self.pending.drain(..));
+        true
+    }
+}
+
+// Bytes sent for keys that don't produce a character.
+fn key_sequence(key: VirtualKeyCode) -> Option<&'static [u8]>{
+    use self::VirtualKeyCode as K;
+    match key{
+        K::Up       => Some(b"\x1b[A"),
+        K::Down     => Some(b"\x1b[B"),
+        K::Right    => Some(b"\x1b[C"),
+        K::Left     => Some(b"\x1b[D"),
+        K::Home     => Some(b"\x1b[H"),
+        K::End      => Some(b"\x1b[F"),
+        K::Insert   => Some(b"\x1b[2~"),
+        K::Delete   => Some(b"\x1b[3~"),
+        K::PageUp   => Some(b"\x1b[5~"),
+        K::PageDown => Some(b"\x1b[6~"),
+        _           => None
+    }
+}
+
+// One terminal: a grid with its scrollback, the parser that draws on it and
+// the channel it talks to.
+pub struct Session{
+    pub title:     String,
+    grid:          Grid,
+    terminal:      Terminal,
+    channel:       Box<dyn Channel>,
+    open:          bool,
+    scroll_offset: usize, // rows of scrollback shown above the grid
+    received:      Vec<u8>,
+    pending:       Vec<u8>, // received but held back by a smooth scroll
+    scrolling:     Scrolling,
+    glide:         f32,  // rows the scroll region still has to move
+    falling:       bool, // and that it moves down, as after a reverse index
+    last:          PreciseTime,
+    recorder:      Option<Recorder>
+}
+
+impl Session{
+    pub fn new(title: &str, width: u32, height: u32, channel: Box<dyn Channel>) -> Session{
+        Session::from_grid(title, Grid::new(width, height), channel)
+    }
+
+    pub fn from_grid(title: &str, mut grid: Grid, channel: Box<dyn Channel>) -> Session{
+        grid.set_scrollback_limit(SCROLLBACK_LINES);
+        Session{
+            title:         String::from(title),
+            grid:          grid,
+            terminal:      Terminal::new(),
+            channel:       channel,
+            open:          true,
+            scroll_offset: 0,
+            received:      Vec::new(),
+            pending:       Vec::new(),
+            scrolling:     Scrolling::vt220(),
+            glide:         0.0,
+            falling:       false,
+            last:          PreciseTime::now(),
+            recorder:      None
+        }
+    }
+
+    pub fn grid(&self) -> &Grid{
+        &self.grid
+    }
+
+    // False once the channel has closed and all its output has been shown.
+    pub fn is_open(&self) -> bool{
+        self.open || !self.pending.is_empty()
+    }
+
+    // Sets how fast smooth scrolling goes, and puts the terminal in or out
+    // of smooth scroll mode until the host says otherwise.
+    pub fn set_scrolling(&mut self, scrolling: Scrolling){
+        self.scrolling = scrolling;
+        self.terminal.set_smooth_scroll(scrolling.smooth);
+    }
+
+    // How the host's bytes are read.
+    pub fn set_charset(&mut self, charset: Charset){
+        self.terminal.set_charset(charset);
+    }
+
+    // Which tile shows each character, for the atlas being drawn with.
+    pub fn set_tiles(&mut self, tiles: TileMap){
+        self.terminal.set_tiles(tiles);
+    }
+
+    // Records everything the terminal is fed from now on, and resizes.
+    pub fn record(&mut self, recorder: Recorder){
+        self.recorder = Some(recorder);
+    }
+
+    fn stop_recording_on_err(&mut self, result: ::std::io::Result<()>){
+        if let Err(e) = result{
+            eprintln!("textbbs: stopped recording {}: {}", self.title, e);
+            self.recorder = None;
+        }
+    }
+
+    pub fn resize(&mut self, width: u32, height: u32){
+        if width == self.grid.width() && height == self.grid.height(){
+            return;
+        }
+        self.grid.resize(width, height);
+        self.channel.resize(width, height);
+        let result = match self.recorder{
+            Some(ref mut r) => r.resize(width, height),
+            None            => Ok(())
+        };
+        self.stop_recording_on_err(result);
+    }
+
+    // Runs any output that has arrived through the terminal. Called every
+    // frame for every session, shown or not. In smooth scroll mode output
+    // stops each time the scroll region scrolls, until the glide is over.
+    pub fn process(&mut self){
+        let now = PreciseTime::now();
+        let elapsed = match self.last.to(now).num_microseconds(){
+            Some(us) => us as f32 / 1_000_000.0,
+            None     => 0.0
+        };
+        self.last = now;
+        if self.glide > 0.0{
+            self.glide -= elapsed * self.scrolling.speed;
+        }
+
+        if self.open{
+            self.received.clear();
+            self.open = self.channel.receive(&mut self.received);
+            if !self.received.is_empty(){
+                self.pending.extend_from_slice(&self.received);
+                let result = match self.recorder{
+                    Some(ref mut r) => r.output(&self.received),
+                    None            => Ok(())
+                };
+                self.stop_recording_on_err(result);
+            }
+        }
+        if self.pending.is_empty(){
+            self.glide = self.glide.max(0.0);
+            return;
+        }
+
+        // a byte at a time, as any of them could start a scroll; a glide
+        // that overshot in a slow frame lets the next rows start at once
+        let smooth = self.scrolling.speed > 0.0;
+        let mut fed = 0;
+        while fed < self.pending.len() && self.glide <= 0.0{
+            self.terminal.feed(&mut self.grid, &self.pending[fed..fed + 1]);
+            fed += 1;
+            let scrolled = self.grid.take_scrolled();
+            if scrolled != 0 && smooth && self.terminal.smooth_scroll(){
+                self.glide  += scrolled.abs() as f32;
+                self.falling = scrolled < 0;
+            }
+        }
+        self.pending.drain(..fed);
+        if self.pending.is_empty(){
+            self.glide = self.glide.max(0.0);
+        }
+        if fed > 0{
+            self.scroll_offset = 0;
+        }
+    }
+
+    pub fn send(&mut self, bytes: &[u8]){
+        if self.open{
+            self.channel.send(bytes);
+        }
+    }
+
+    pub fn send_char(&mut self, c: char){
+        let mut buf = [0u8; 4];
+        let len = c.encode_utf8(&mut buf).len();
+        self.send(&buf[..len]);
+    }
+
+    // Sends what `key` types, if anything, and says whether it did; keys
+    // that type characters come again as those, through `send_char`.
+    pub fn send_key(&mut self, key: VirtualKeyCode, _mods: Modifiers) -> bool{
+        match key_sequence(key){
+            Some(seq) => { self.send(seq); true },
+            None      => false
+        }
+    }
+
+    // Whether the host has rung the bell since the last call.
+    pub fn take_bell(&mut self) -> bool{
+        self.terminal.take_bell()
+    }
+
+    // The scroll region part way through a smooth scroll, if it is shown.
+    pub fn scroll_shift(&self) -> Option<ScrollShift>{
+        if self.glide <= 0.0 || self.scroll_offset != 0{
+            return None;
+        }
+        let (top, bottom) = self.grid.scroll_region();
+        let offset = self.glide.min(1.0);
+        Some(ScrollShift{ top: top, bottom: bottom, offset: if self.falling { -offset } else { offset } })
+    }
+
+    // Moves the view `lines` rows back into (positive) or forward out of
+    // (negative) the scrollback.
+    pub fn scroll_view(&mut self, lines: isize){
+        let max = self.grid.scrollback().len() as isize;
+        let offset = (self.scroll_offset as isize + lines).max(0).min(max);
+        self.scroll_offset = offset as usize;
+    }
+
+    // The tiles, attributes and row sizes currently visible: the grid,
+    // pushed down by however much scrollback is being viewed.
+    pub fn visible(&self) -> (Vec<u16>, Vec<u8>, Vec<LineSize>){
+        if self.scroll_offset == 0{
+            return (self.grid.cells().to_vec(), self.grid.attrs().to_vec(), self.grid.lines().to_vec());
+        }
+        let (width, height) = (self.grid.width() as usize, self.grid.height() as usize);
+        let scrollback = self.grid.scrollback();
+        let mut cells = Vec::with_capacity(width * height);
+        let mut attrs = Vec::with_capacity(width * height);
+        let mut lines = Vec::with_capacity(height);
+        for row in 0..height{
+            if row < self.scroll_offset{
+                let line = &scrollback[scrollback.len() - self.scroll_offset + row];
+                cells.extend(line.tiles.iter().cloned().chain(::std::iter::repeat(BLANK_TILE)).take(width));
+                attrs.extend(line.attrs.iter().cloned().chain(::std::iter::repeat(DEFAULT_ATTR)).take(width));
+                lines.push(line.size);
+            }
+            else{
+                let start = (row - self.scroll_offset) * width;
+                cells.extend_from_slice(&self.grid.cells()[start..start + width]);
+                attrs.extend_from_slice(&self.grid.attrs()[start..start + width]);
+                lines.push(self.grid.lines()[row - self.scroll_offset]);
+            }
+        }
+        (cells, attrs, lines)
+    }
+}
@@ -0,0 +1,343 @@
+// Sound effects, as an old terminal and its modem made them: keys clicking,
+// the CRT's hum and flyback whine, the thunk of degaussing as it warms up and
+// the modem dialling and handshaking as a connection starts.
+//
+// A `Mixer` renders them into memory; a `Speaker` streams what it renders to
+// the system's audio player from a thread of its own.
+
+use audio;
+use audio::SAMPLE_RATE;
+use rand;
+use rand::Rng;
+use std::f32::consts::PI;
+use std::io;
+use std::io::Write;
+use std::process::Child;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Samples rendered at a time when streaming, about 23 milliseconds' worth.
+const BLOCK: usize = 1024;
+// How far the stream is kept ahead of the clock, so the player doesn't run
+// dry; a sound starts at most this late.
+const LEAD: usize = 2 * BLOCK;
+
+// The mains frequency, and the horizontal scan rate of an NTSC set, which
+// the flyback transformer whines at.
+const MAINS:   f32 = 60.0;
+const FLYBACK: f32 = 15734.0;
+
+// The number the modem dials.
+const DIGITS: &'static str = "5551234";
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Sound{
+    KeyClick,
+    Degauss,
+    Handshake
+}
+
+// How loud each sound is, from 0 (not made at all) to 1.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Volumes{
+    pub keys:    f32,
+    pub hum:     f32,
+    pub degauss: f32,
+    pub modem:   f32
+}
+
+impl Volumes{
+    pub fn standard() -> Volumes{
+        Volumes{ keys: 0.3, hum: 0.05, degauss: 0.5, modem: 0.4 }
+    }
+}
+
+fn count(ms: u32) -> usize{
+    (SAMPLE_RATE as u64 * ms as u64 / 1000) as usize
+}
+
+// Appends `ms` of `freqs` sounding together, each at `level`, keeping the
+// phases in `phases` going so consecutive pieces join without a click.
+fn tones(out: &mut Vec<f32>, phases: &mut [f32], freqs: &[f32], ms: u32, level: f32){
+    for _ in 0..count(ms){
+        let mut s = 0.0;
+        for (phase, &freq) in phases.iter_mut().zip(freqs){
+            s += phase.sin() * level;
+            *phase = (*phase + 2.0 * PI * freq / SAMPLE_RATE as f32) % (2.0 * PI);
+        }
+        out.push(s);
+    }
+}
+
+fn silence(out: &mut Vec<f32>, ms: u32){
+    let n = out.len() + count(ms);
+    out.resize(n, 0.0);
+}
+
+// A short burst of noise with a resonant ping, like a buckling spring.
+fn key_click() -> Vec<f32>{
+    let mut rng = rand::thread_rng();
+    let ping = rng.gen_range(1800.0, 2600.0);
+    (0..count(12)).map(|i| {
+        let t = i as f32 / SAMPLE_RATE as f32;
+        let noise = rng.gen_range(-1.0, 1.0) * (-t * 900.0).exp();
+        let tone = (2.0 * PI * ping * t).sin() * (-t * 400.0).exp();
+        noise * 0.6 + tone * 0.4
+    }).collect()
+}
+
+// A low thunk as the coil kicks in, then a buzz at the mains frequency that
+// wobbles and dies away as the field collapses.
+fn degauss() -> Vec<f32>{
+    (0..count(1500)).map(|i| {
+        let t = i as f32 / SAMPLE_RATE as f32;
+        let thunk = (2.0 * PI * 45.0 * t).sin() * (-t * 12.0).exp();
+        let attack = (t / 0.05).min(1.0);
+        let wobble = 1.0 + 0.3 * (2.0 * PI * 7.0 * t).sin();
+        let buzz = (1..5).map(|h| (2.0 * PI * MAINS * h as f32 * t).sin() / h as f32).sum::<f32>();
+        thunk * 0.8 + buzz * 0.3 * attack * wobble * (-t * 2.5).exp()
+    }).collect()
+}
+
+// Frequency shift keying at 300 baud: random bits as one of two tones.
+fn chatter(out: &mut Vec<f32>, phase: &mut f32, space: f32, mark: f32, ms: u32, level: f32){
+    let mut rng = rand::thread_rng();
+    let bit = count(1000) / 300;
+    let end = out.len() + count(ms);
+    while out.len() < end{
+        let freq = if rng.gen() { mark } else { space };
+        for _ in 0..bit{
+            out.push(phase.sin() * level);
+            *phase = (*phase + 2.0 * PI * freq / SAMPLE_RATE as f32) % (2.0 * PI);
+        }
+    }
+}
+
+// Dial tone, the number dialled in touch tones, the answering modem's tone
+// with its phase reversals, the two modems' chatter, then the hiss of
+// training before the line goes quiet.
+fn handshake() -> Vec<f32>{
+    let mut out = Vec::new();
+    let mut phases = [0.0f32; 2];
+    tones(&mut out, &mut phases, &[350.0, 440.0], 1000, 0.4);
+    silence(&mut out, 200);
+
+    for digit in DIGITS.chars(){
+        let key = "123456789*0#".find(digit).unwrap_or(10);
+        let (row, column) = ([697.0, 770.0, 852.0, 941.0][key / 3], [1209.0, 1336.0, 1477.0][key % 3]);
+        tones(&mut out, &mut [0.0, 0.0], &[row, column], 90, 0.4);
+        silence(&mut out, 70);
+    }
+    silence(&mut out, 1200);
+
+    let mut phase = [0.0f32];
+    for _ in 0..7{
+        tones(&mut out, &mut phase, &[2100.0], 450, 0.6);
+        phase[0] += PI;
+    }
+
+    let (mut low, mut high) = (0.0, 0.0);
+    chatter(&mut out, &mut low, 1180.0, 980.0, 400, 0.5);
+    let start = out.len();
+    chatter(&mut out, &mut high, 1850.0, 1650.0, 600, 0.3);
+    let mut answer = Vec::new();
+    chatter(&mut answer, &mut low, 1180.0, 980.0, 600, 0.3);
+    for (s, a) in out[start..].iter_mut().zip(answer){
+        *s += a;
+    }
+
+    let mut rng = rand::thread_rng();
+    let mut last = 0.0;
+    for i in 0..count(1500){
+        // noise, roughly band-limited so it hisses rather than crackles
+        last = last * 0.5 + rng.gen_range(-1.0, 1.0) * 0.5;
+        let fade = match count(1500) - i{
+            left if left < count(100) => left as f32 / count(100) as f32,
+            _                         => 1.0
+        };
+        out.push(last * 0.5 * fade);
+    }
+    out
+}
+
+impl Sound{
+    // One of these, as samples from -1 to 1 at `audio::SAMPLE_RATE`.
+    pub fn samples(&self) -> Vec<f32>{
+        match *self{
+            Sound::KeyClick  => key_click(),
+            Sound::Degauss   => degauss(),
+            Sound::Handshake => handshake()
+        }
+    }
+}
+
+struct Voice{
+    samples: Vec<f32>,
+    at:      usize,
+    volume:  f32
+}
+
+// Mixes the sounds playing over the hum, into memory.
+pub struct Mixer{
+    volumes: Volumes,
+    voices:  Vec<Voice>,
+    hum_at:  u64
+}
+
+impl Mixer{
+    pub fn new(volumes: Volumes) -> Mixer{
+        Mixer{
+            volumes: volumes,
+            voices:  Vec::new(),
+            hum_at:  0
+        }
+    }
+
+    pub fn trigger(&mut self, sound: Sound){
+        let volume = match sound{
+            Sound::KeyClick  => self.volumes.keys,
+            Sound::Degauss   => self.volumes.degauss,
+            Sound::Handshake => self.volumes.modem
+        };
+        if volume > 0.0{
+            self.voices.push(Voice{ samples: sound.samples(), at: 0, volume: volume.min(1.0) });
+        }
+    }
+
+    // Whether anything besides the hum is sounding.
+    pub fn is_playing(&self) -> bool{
+        !self.voices.is_empty()
+    }
+
+    // Appends the next `count` samples to `out`.
+    pub fn render(&mut self, count: usize, out: &mut Vec<i16>){
+        let hum = self.volumes.hum.max(0.0).min(1.0);
+        for _ in 0..count{
+            let mut s = 0.0;
+            if hum > 0.0{
+                // the sample count keeps the phase exact however long it runs
+                let t = (self.hum_at % SAMPLE_RATE as u64) as f32 / SAMPLE_RATE as f32;
+                s += ((2.0 * PI * MAINS * t).sin() * 0.6 +
+                      (2.0 * PI * MAINS * 2.0 * t).sin() * 0.3 +
+                      (2.0 * PI * MAINS * 3.0 * t).sin() * 0.1 +
+                      (2.0 * PI * FLYBACK * t).sin() * 0.2) * hum;
+                self.hum_at += 1;
+            }
+            for voice in self.voices.iter_mut().filter(|v| v.at < v.samples.len()){
+                s += voice.samples[voice.at] * voice.volume;
+                voice.at += 1;
+            }
+            out.push((s.max(-1.0).min(1.0) * i16::max_value() as f32) as i16);
+        }
+        self.voices.retain(|v| v.at < v.samples.len());
+    }
+}
+
+// Streams a `Mixer` to an audio player, like "aplay -q", as one endless WAV
+// file on its standard input.
+pub struct Speaker{
+    triggers: mpsc::Sender<Sound>,
+    child:    Child
+}
+
+impl Speaker{
+    pub fn start(command: &str, volumes: Volumes) -> io::Result<Speaker>{
+        let mut child = match audio::spawn_player(command){
+            Ok(c)  => c,
+            Err(e) => return Err(e)
+        };
+        let mut stdin = match child.stdin.take(){
+            Some(s) => s,
+            None    => return Err(io::Error::new(io::ErrorKind::BrokenPipe, "the audio player has no input"))
+        };
+        let (triggers, pending) = mpsc::channel();
+        let spawned = thread::Builder::new().name(String::from("speaker")).spawn(move || {
+            let mut mixer = Mixer::new(volumes);
+            if stdin.write_all(&audio::wav_header(u32::max_value())).is_err(){
+                return;
+            }
+            // kept just ahead of the clock, so sounds start soon after
+            // they're asked for rather than after a pipe full of hum
+            let start = Instant::now();
+            let mut written = 0;
+            let mut block = Vec::with_capacity(BLOCK);
+            loop{
+                loop{
+                    match pending.try_recv(){
+                        Ok(sound)                             => mixer.trigger(sound),
+                        Err(mpsc::TryRecvError::Empty)        => break,
+                        Err(mpsc::TryRecvError::Disconnected) => return
+                    }
+                }
+                let elapsed = start.elapsed();
+                let due = (elapsed.as_secs() * SAMPLE_RATE as u64 +
+                           elapsed.subsec_nanos() as u64 * SAMPLE_RATE as u64 / 1_000_000_000) as usize + LEAD;
+                if written >= due{
+                    thread::sleep(Duration::from_millis(5));
+                    continue;
+                }
+                block.clear();
+                mixer.render(BLOCK, &mut block);
+                let mut bytes = Vec::with_capacity(BLOCK * 2);
+                audio::put_samples(&mut bytes, &block);
+                if stdin.write_all(&bytes).is_err(){
+                    return;
+                }
+                written += BLOCK;
+            }
+        });
+        match spawned{
+            Ok(_)  => Ok(Speaker{ triggers: triggers, child: child }),
+            Err(e) => {
+                let _ = child.kill();
+                Err(e)
+            }
+        }
+    }
+
+    pub fn play(&self, sound: Sound){
+        let _ = self.triggers.send(sound);
+    }
+}
+
+impl Drop for Speaker{
+    fn drop(&mut self){
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    fn quiet() -> Volumes{
+        Volumes{ keys: 0.5, hum: 0.0, degauss: 0.0, modem: 0.0 }
+    }
+
+    #[test]
+    fn mixes_a_click_then_silence(){
+        let mut mixer = Mixer::new(quiet());
+        mixer.trigger(Sound::KeyClick);
+        assert!(mixer.is_playing());
+        let mut out = Vec::new();
+        mixer.render(count(12) + 100, &mut out);
+        assert_eq!(out.len(), count(12) + 100);
+        assert!(!mixer.is_playing());
+        // at the click's volume, and nothing once it's over
+        let peak = out.iter().map(|&s| (s as i32).abs()).max().unwrap_or(0);
+        assert!(peak > 0 && peak <= i16::max_value() as i32 / 2);
+        assert!(out[count(12)..].iter().all(|&s| s == 0));
+    }
+
+    #[test]
+    fn leaves_out_sounds_turned_all_the_way_down(){
+        let mut mixer = Mixer::new(Volumes{ keys: 0.0, .. quiet() });
+        mixer.trigger(Sound::KeyClick);
+        assert!(!mixer.is_playing());
+        let mut out = Vec::new();
+        mixer.render(100, &mut out);
+        assert!(out.iter().all(|&s| s == 0));
+    }
+}
@@ -0,0 +1,234 @@
+use glium;
+
+use atlas::Atlas;
+use grid::{Grid, TileMap};
+use palette::Palette;
+use pane::{BorderGlyphs, CellRect, Pane, PaneManager, cell_viewport};
+use session::Scrolling;
+use textblock::{GridView, TextBlockErr};
+
+// `text` cut to at most `room` characters, ending in "..." if it was cut.
+fn shorten(text: &str, room: usize) -> String{
+    if text.chars().count() <= room{
+        String::from(text)
+    }
+    else if room > 3{
+        text.chars().take(room - 3).chain("...".chars()).collect()
+    }
+    else{
+        text.chars().take(room).collect()
+    }
+}
+
+// A list of full-window tabs, each with its own panes and sessions. While
+// there is more than one tab, the top row of the window lists them.
+pub struct Tabs{
+    tabs:     Vec<PaneManager>,
+    active:   usize,
+    width:    u32,
+    height:   u32,
+    bar:      Grid,
+    bar_view: GridView,
+    glyphs:   BorderGlyphs,
+    palette:  Palette,
+    scroll:   Scrolling,
+    tiles:    TileMap,
+    flash:    bool // see `set_visual_bell`
+}
+
+impl Tabs{
+    pub fn new(first: Pane, width: u32, height: u32, glyphs: BorderGlyphs) -> Tabs{
+        Tabs{
+            tabs:     vec![PaneManager::new(first, width, height, glyphs)],
+            active:   0,
+            width:    width,
+            height:   height,
+            bar:      Grid::new(width, 1),
+            bar_view: GridView::new(),
+            glyphs:   glyphs,
+            palette:  Palette::vga(),
+            scroll:   Scrolling::vt220(),
+            tiles:    TileMap::ascii(),
+            flash:    true
+        }
+    }
+
+    // Colours every tab, and any opened later, with `palette`.
+    pub fn set_palette(&mut self, palette: Palette){
+        self.palette = palette;
+        self.bar_view.set_palette(palette);
+        for tab in self.tabs.iter_mut(){
+            tab.set_palette(palette);
+        }
+    }
+
+    // Scrolls every session, and any opened later, as `scrolling` says.
+    pub fn set_scrolling(&mut self, scrolling: Scrolling){
+        self.scroll = scrolling;
+        for tab in self.tabs.iter_mut(){
+            tab.set_scrolling(scrolling);
+        }
+    }
+
+    // Draws the sessions of every tab, and any opened later, with `tiles`.
+    pub fn set_tiles(&mut self, tiles: TileMap){
+        for tab in self.tabs.iter_mut(){
+            tab.set_tiles(tiles.clone());
+        }
+        self.tiles = tiles;
+    }
+
+    // Whether panes in every tab, and any opened later, flash when their
+    // bell rings.
+    pub fn set_visual_bell(&mut self, visual: bool){
+        self.flash = visual;
+        for tab in self.tabs.iter_mut(){
+            tab.set_visual_bell(visual);
+        }
+    }
+
+    // Draws the dividers of every tab, and any opened later, with `glyphs`.
+    pub fn set_glyphs(&mut self, glyphs: BorderGlyphs){
+        self.glyphs = glyphs;
+        for tab in self.tabs.iter_mut(){
+            tab.set_glyphs(glyphs);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool{
+        self.tabs.is_empty()
+    }
+
+    fn show_bar(&self) -> bool{
+        self.tabs.len() > 1
+    }
+
+    fn content_height(&self) -> u32{
+        if self.show_bar() { self.height.saturating_sub(1) } else { self.height }
+    }
+
+    pub fn active(&mut self) -> &mut PaneManager{
+        &mut self.tabs[self.active]
+    }
+
+    // Opens a tab with a fresh pane after the active one and switches to it.
+    pub fn open(&mut self){
+        let (width, height) = (self.width, self.height);
+        let mut panes = PaneManager::new(Pane::new(width, height), width, height, self.glyphs);
+        panes.set_palette(self.palette);
+        panes.set_scrolling(self.scroll);
+        panes.set_tiles(self.tiles.clone());
+        panes.set_visual_bell(self.flash);
+        let at = if self.tabs.is_empty() { 0 } else { self.active + 1 };
+        self.tabs.insert(at, panes);
+        self.active = at;
+        self.relayout();
+    }
+
+    pub fn close_active(&mut self){
+        if self.tabs.is_empty(){
+            return;
+        }
+        self.tabs.remove(self.active);
+        if self.active >= self.tabs.len(){
+            self.active = self.tabs.len().saturating_sub(1);
+        }
+        self.relayout();
+    }
+
+    pub fn next(&mut self){
+        if !self.tabs.is_empty(){
+            self.active = (self.active + 1) % self.tabs.len();
+        }
+    }
+
+    pub fn prev(&mut self){
+        if !self.tabs.is_empty(){
+            self.active = (self.active + self.tabs.len() - 1) % self.tabs.len();
+        }
+    }
+
+    pub fn resize(&mut self, width: u32, height: u32){
+        self.width  = width;
+        self.height = height;
+        self.relayout();
+    }
+
+    fn relayout(&mut self){
+        let (width, height) = (self.width, self.content_height());
+        for tab in self.tabs.iter_mut(){
+            tab.resize(width, height);
+        }
+        self.bar.resize(width, 1);
+    }
+
+    // Runs pending output through the sessions of every tab, including the
+    // ones that aren't shown. Returns whether any of them rang the bell.
+    pub fn process(&mut self) -> bool{
+        let mut rang = false;
+        for tab in self.tabs.iter_mut(){
+            rang |= tab.process();
+        }
+        rang
+    }
+
+    fn update_bar(&mut self){
+        let active = self.active;
+        // each tab gets an equal share of the bar, so none wraps onto the
+        // next row
+        let share = self.width as usize / ::std::cmp::max(self.tabs.len(), 1);
+        let labels: Vec<String> =
+            self.tabs.iter_mut()
+            .enumerate()
+            .map(|(i, tab)| {
+                let number = (i + 1).to_string();
+                let room = share.saturating_sub(number.len() + 3);
+                let title = shorten(&tab.focused().session.title, room);
+                let label = if i == active { format!("[{}:{}]", number, title) }
+                            else           { format!(" {}:{} ", number, title) };
+                label.chars().take(share).collect()
+            })
+            .collect();
+        self.bar.clear();
+        for label in labels{
+            self.bar.write_str(&label);
+        }
+    }
+
+    pub fn draw<F>(&mut self,
+                   display: &F,
+                   program: &glium::Program,
+                   target:  &mut glium::Frame,
+                   atlas:   &Atlas) -> Result<(), TextBlockErr>
+        where F: glium::backend::Facade{
+        use glium::Surface;
+
+        if self.tabs.is_empty(){
+            return Ok(());
+        }
+        let (frame_w, frame_h) = target.get_dimensions();
+        let frame = glium::Rect{ left: 0, bottom: 0, width: frame_w, height: frame_h };
+        let (width, height) = (self.width, self.height);
+
+        let content =
+            if self.show_bar(){
+                self.update_bar();
+                let bar_area = cell_viewport(frame, width, height,
+                                             CellRect{ x: 0, y: 0, w: width, h: 1 });
+                match self.bar_view.draw(display, program, target, atlas,
+                                         width, 1, self.bar.cells(), self.bar.attrs(), &[],
+                                         Some(bar_area)){
+                    Ok(())  => (),
+                    Err(e)  => return Err(e)
+                }
+                cell_viewport(frame, width, height,
+                              CellRect{ x: 0, y: 1, w: width, h: height - 1 })
+            }
+            else{
+                frame
+            };
+
+        let active = self.active;
+        self.tabs[active].draw(display, program, target, atlas, content)
+    }
+}
@@ -0,0 +1,532 @@
+// Telnet (RFC 854) with the options a BBS cares about: BINARY (RFC 856),
+// ECHO (RFC 857), SUPPRESS-GO-AHEAD (RFC 858), TERMINAL-TYPE (RFC 1091) and
+// NAWS (RFC 1073).
+
+use std::io;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+
+use session::Channel;
+use transport::write_pending;
+
+pub const IAC:  u8 = 255;
+pub const DONT: u8 = 254;
+pub const DO:   u8 = 253;
+pub const WONT: u8 = 252;
+pub const WILL: u8 = 251;
+pub const SB:   u8 = 250;
+pub const GA:   u8 = 249;
+pub const SE:   u8 = 240;
+
+pub const OPT_BINARY: u8 = 0;
+pub const OPT_ECHO:   u8 = 1;
+pub const OPT_SGA:    u8 = 3;
+pub const OPT_TTYPE:  u8 = 24;
+pub const OPT_NAWS:   u8 = 31;
+
+pub const TTYPE_IS:   u8 = 0;
+pub const TTYPE_SEND: u8 = 1;
+
+pub const DEFAULT_PORT: u16 = 23;
+pub const TERMINAL_TYPE: &'static str = "ANSI";
+
+// The longest subnegotiation kept; NAWS and TTYPE need far less, and one
+// that goes on longer is dropped rather than buffered without end.
+const MAX_SUB: usize = 256;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum State{
+    Data,
+    Iac,
+    Negotiate(u8), // the WILL/WONT/DO/DONT byte
+    Sub,
+    SubIac
+}
+
+// Which side an option is enabled on. `local` options are ones we perform
+// (we said WILL), `remote` ones the other end performs (we said DO).
+#[derive(Copy, Clone)]
+struct Options{
+    local:  [bool; 256],
+    remote: [bool; 256]
+}
+
+impl Options{
+    fn new() -> Options{
+        Options{ local: [false; 256], remote: [false; 256] }
+    }
+}
+
+// The telnet protocol state for one connection, independent of the stream
+// it runs over. `server` decides which options it asks for and agrees to.
+pub struct Telnet{
+    state:   State,
+    options: Options,
+    sub:     Vec<u8>,
+    dropped: bool, // the subnegotiation ran past `MAX_SUB`
+    server:  bool,
+    width:   u16,
+    height:  u16,
+    // what the client reported, on the server side
+    pub terminal_type: Option<String>
+}
+
+fn command(out: &mut Vec<u8>, verb: u8, option: u8){
+    out.extend_from_slice(&[IAC, verb, option]);
+}
+
+// Appends `byte`, doubling it if it is IAC.
+fn push_escaped(out: &mut Vec<u8>, byte: u8){
+    if byte == IAC{
+        out.push(IAC);
+    }
+    out.push(byte);
+}
+
+impl Telnet{
+    pub fn client(width: u16, height: u16) -> Telnet{
+        Telnet{
+            state:         State::Data,
+            options:       Options::new(),
+            sub:           Vec::new(),
+            dropped:       false,
+            server:        false,
+            width:         width,
+            height:        height,
+            terminal_type: None
+        }
+    }
+
+    // `width` and `height` are used until the client reports its own size.
+    pub fn server(width: u16, height: u16) -> Telnet{
+        let mut t = Telnet::client(width, height);
+        t.server = true;
+        t
+    }
+
+    pub fn is_server(&self) -> bool{
+        self.server
+    }
+
+    // What a server sends when a client connects: it will echo and suppress
+    // go-ahead (character at a time mode) and wants the client's window size
+    // and terminal type. The options are marked as agreed straight away so
+    // the client's acknowledgements don't get acknowledged in turn.
+    pub fn server_greeting(&mut self, out: &mut Vec<u8>){
+        for &(verb, option) in [(WILL, OPT_ECHO), (WILL, OPT_SGA), (WILL, OPT_BINARY),
+                                (DO, OPT_BINARY), (DO, OPT_NAWS), (DO, OPT_TTYPE)].iter(){
+            command(out, verb, option);
+            match verb{
+                WILL => self.options.local[option as usize]  = true,
+                _    => self.options.remote[option as usize] = true
+            }
+        }
+    }
+
+    pub fn window_size(&self) -> (u16, u16){
+        (self.width, self.height)
+    }
+
+    // Whether the other end echoes what we send.
+    pub fn remote_echo(&self) -> bool{
+        self.options.remote[OPT_ECHO as usize]
+    }
+
+    fn binary(&self) -> bool{
+        self.options.local[OPT_BINARY as usize]
+    }
+
+    // Options we agree to perform when asked to.
+    fn supports_local(&self, option: u8) -> bool{
+        match option{
+            OPT_BINARY | OPT_SGA   => true,
+            OPT_NAWS   | OPT_TTYPE => !self.server,
+            OPT_ECHO               => self.server,
+            _                      => false
+        }
+    }
+
+    // Options we agree to let the other end perform.
+    fn supports_remote(&self, option: u8) -> bool{
+        match option{
+            OPT_BINARY | OPT_SGA   => true,
+            OPT_NAWS   | OPT_TTYPE => self.server,
+            OPT_ECHO               => !self.server,
+            _                      => false
+        }
+    }
+
+    fn send_naws(&self, out: &mut Vec<u8>){
+        out.extend_from_slice(&[IAC, SB, OPT_NAWS]);
+        for &b in [(self.width >> 8) as u8, self.width as u8,
+                   (self.height >> 8) as u8, self.height as u8].iter(){
+            push_escaped(out, b);
+        }
+        out.extend_from_slice(&[IAC, SE]);
+    }
+
+    // Replies are only sent when an option actually changes state, so two
+    // ends can't get into a negotiation loop.
+    fn negotiate(&mut self, verb: u8, option: u8, reply: &mut Vec<u8>){
+        let i = option as usize;
+        match verb{
+            WILL => {
+                if !self.options.remote[i]{
+                    if self.supports_remote(option){
+                        self.options.remote[i] = true;
+                        command(reply, DO, option);
+                    }
+                    else{
+                        command(reply, DONT, option);
+                    }
+                }
+                if self.server && option == OPT_TTYPE && self.options.remote[i]{
+                    reply.extend_from_slice(&[IAC, SB, OPT_TTYPE, TTYPE_SEND, IAC, SE]);
+                }
+            },
+            WONT => if self.options.remote[i]{
+                self.options.remote[i] = false;
+                command(reply, DONT, option);
+            },
+            DO => if !self.options.local[i]{
+                if self.supports_local(option){
+                    self.options.local[i] = true;
+                    command(reply, WILL, option);
+                    if option == OPT_NAWS{
+                        self.send_naws(reply);
+                    }
+                }
+                else{
+                    command(reply, WONT, option);
+                }
+            },
+            DONT => if self.options.local[i]{
+                self.options.local[i] = false;
+                command(reply, WONT, option);
+            },
+            _ => ()
+        }
+    }
+
+    // Keeps a byte of a subnegotiation, unless it is already too long.
+    fn sub_push(&mut self, b: u8){
+        if self.sub.len() < MAX_SUB{
+            self.sub.push(b);
+        }
+        else{
+            self.dropped = true;
+        }
+    }
+
+    fn subnegotiation(&mut self, reply: &mut Vec<u8>){
+        if self.dropped{
+            self.dropped = false;
+            self.sub.clear();
+            return;
+        }
+        match (self.sub.get(0).cloned(), self.sub.get(1).cloned()){
+            (Some(OPT_TTYPE), Some(TTYPE_SEND)) if !self.server => {
+                reply.extend_from_slice(&[IAC, SB, OPT_TTYPE, TTYPE_IS]);
+                reply.extend_from_slice(TERMINAL_TYPE.as_bytes());
+                reply.extend_from_slice(&[IAC, SE]);
+            },
+            (Some(OPT_TTYPE), Some(TTYPE_IS)) if self.server => {
+                self.terminal_type = Some(String::from_utf8_lossy(&self.sub[2..]).into_owned());
+            },
+            (Some(OPT_NAWS), _) if self.server && self.sub.len() >= 5 => {
+                let width  = (self.sub[1] as u16) << 8 | self.sub[2] as u16;
+                let height = (self.sub[3] as u16) << 8 | self.sub[4] as u16;
+                // 0 means the client doesn't know; keep what we have
+                if width > 0 && height > 0{
+                    self.width  = width;
+                    self.height = height;
+                }
+            },
+            _ => ()
+        }
+        self.sub.clear();
+    }
+
+    // Splits what arrived from the other end into data, appended to `data`,
+    // and telnet commands, whose answers are appended to `reply`.
+    pub fn receive(&mut self, input: &[u8], data: &mut Vec<u8>, reply: &mut Vec<u8>){
+        for &b in input{
+            self.state = match (self.state, b){
+                (State::Data, IAC)          => State::Iac,
+                (State::Data, _)            => { data.push(b); State::Data },
+                (State::Iac, IAC)           => { data.push(IAC); State::Data },
+                (State::Iac, WILL) | (State::Iac, WONT) |
+                (State::Iac, DO)   | (State::Iac, DONT) => State::Negotiate(b),
+                (State::Iac, SB)            => State::Sub,
+                (State::Iac, _)             => State::Data, // GA, NOP, AYT...
+                (State::Negotiate(verb), _) => {
+                    self.negotiate(verb, b, reply);
+                    State::Data
+                },
+                (State::Sub, IAC)           => State::SubIac,
+                (State::Sub, _)             => { self.sub_push(b); State::Sub },
+                (State::SubIac, SE)         => { self.subnegotiation(reply); State::Data },
+                (State::SubIac, _)          => { self.sub_push(b); State::Sub }
+            };
+        }
+    }
+
+    // Prepares data to go to the other end: IAC is doubled and, outside
+    // binary mode, a bare CR becomes CR NUL.
+    pub fn encode(&self, input: &[u8], out: &mut Vec<u8>){
+        let binary = self.binary();
+        for (i, &b) in input.iter().enumerate(){
+            push_escaped(out, b);
+            if b == b'\r' && !binary && input.get(i + 1) != Some(&b'\n'){
+                out.push(0);
+            }
+        }
+    }
+
+    // Records a new window size, telling the other end if it asked for it.
+    pub fn resize(&mut self, width: u16, height: u16, out: &mut Vec<u8>){
+        self.width  = width;
+        self.height = height;
+        if self.options.local[OPT_NAWS as usize]{
+            self.send_naws(out);
+        }
+    }
+}
+
+// Parses `telnet://host[:port]` (or a bare `host[:port]`) into an address
+// suitable for `TcpStream::connect`.
+pub fn parse_address(url: &str) -> String{
+    let rest = if url.starts_with("telnet://") { &url["telnet://".len()..] } else { url };
+    let rest = rest.trim_end_matches('/');
+    if rest.contains(':'){
+        String::from(rest)
+    }
+    else{
+        format!("{}:{}", rest, DEFAULT_PORT)
+    }
+}
+
+// A telnet connection as a session channel. The stream must be non-blocking;
+// `WouldBlock` just means nothing has arrived yet.
+pub struct TelnetChannel<S>{
+    stream: S,
+    telnet: Telnet,
+    open:   bool,
+    buf:    Vec<u8>,
+    echo:   Vec<u8>, // local echo, while the other end doesn't echo
+    unsent: Vec<u8>  // what the stream hasn't taken yet
+}
+
+impl TelnetChannel<TcpStream>{
+    pub fn connect(url: &str, width: u16, height: u16) -> io::Result<TelnetChannel<TcpStream>>{
+        let stream = match TcpStream::connect(&*parse_address(url)){
+            Ok(s)  => s,
+            Err(e) => return Err(e)
+        };
+        match stream.set_nonblocking(true){
+            Ok(())  => (),
+            Err(e)  => return Err(e)
+        }
+        match stream.set_nodelay(true){
+            Ok(())  => (),
+            Err(e)  => return Err(e)
+        }
+        Ok(TelnetChannel::new(stream, Telnet::client(width, height)))
+    }
+
+    // The server side of a connection a listener accepted.
+    pub fn accept(stream: TcpStream, width: u16, height: u16) -> io::Result<TelnetChannel<TcpStream>>{
+        match stream.set_nonblocking(true){
+            Ok(())  => (),
+            Err(e)  => return Err(e)
+        }
+        let mut channel = TelnetChannel::new(stream, Telnet::server(width, height));
+        let mut greeting = Vec::new();
+        channel.telnet.server_greeting(&mut greeting);
+        channel.write_raw(&greeting);
+        Ok(channel)
+    }
+}
+
+impl<S: Read + Write> TelnetChannel<S>{
+    pub fn new(stream: S, telnet: Telnet) -> TelnetChannel<S>{
+        TelnetChannel{
+            stream: stream,
+            telnet: telnet,
+            open:   true,
+            buf:    vec![0u8; 4096],
+            echo:   Vec::new(),
+            unsent: Vec::new()
+        }
+    }
+
+    pub fn telnet(&self) -> &Telnet{
+        &self.telnet
+    }
+
+    pub fn is_open(&self) -> bool{
+        self.open
+    }
+
+    pub fn close(&mut self){
+        self.open = false;
+    }
+
+    fn write_raw(&mut self, bytes: &[u8]){
+        if !self.open{
+            return;
+        }
+        // the stream is non-blocking; what it doesn't take now waits for
+        // the next call, or the next `receive`
+        self.unsent.extend_from_slice(bytes);
+        if !self.unsent.is_empty(){
+            self.open = write_pending(&mut self.stream, &mut self.unsent);
+        }
+    }
+}
+
+impl<S: Read + Write> Channel for TelnetChannel<S>{
+    fn send(&mut self, bytes: &[u8]){
+        if !self.telnet.is_server() && !self.telnet.remote_echo(){
+            for &b in bytes{
+                match b{
+                    b'\r' => self.echo.extend_from_slice(b"\r\n"),
+                    0x08  => self.echo.extend_from_slice(b"\x08 \x08"),
+                    _     => self.echo.push(b)
+                }
+            }
+        }
+        let mut out = Vec::with_capacity(bytes.len());
+        self.telnet.encode(bytes, &mut out);
+        self.write_raw(&out);
+    }
+
+    fn receive(&mut self, data: &mut Vec<u8>) -> bool{
+        data.extend(self.echo.drain(..));
+        let mut reply = Vec::new();
+        while self.open{
+            match self.stream.read(&mut self.buf){
+                Ok(0)  => self.open = false,
+                Ok(n)  => self.telnet.receive(&self.buf[..n], data, &mut reply),
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => (),
+                Err(_) => self.open = false
+            }
+        }
+        self.write_raw(&reply);
+        self.open
+    }
+
+    fn resize(&mut self, width: u32, height: u32){
+        let mut out = Vec::new();
+        self.telnet.resize(width as u16, height as u16, &mut out);
+        self.write_raw(&out);
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use std::net::TcpListener;
+    use std::thread;
+    use std::time::Duration;
+
+    // A stream that takes only `budget` more bytes before it would block.
+    struct Trickle{
+        taken:  Vec<u8>,
+        budget: usize
+    }
+
+    impl Read for Trickle{
+        fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize>{
+            Err(io::Error::new(io::ErrorKind::WouldBlock, "nothing yet"))
+        }
+    }
+
+    impl Write for Trickle{
+        fn write(&mut self, bytes: &[u8]) -> io::Result<usize>{
+            if self.budget == 0{
+                return Err(io::Error::new(io::ErrorKind::WouldBlock, "full"));
+            }
+            let n = ::std::cmp::min(self.budget, bytes.len());
+            self.taken.extend_from_slice(&bytes[..n]);
+            self.budget -= n;
+            Ok(n)
+        }
+
+        fn flush(&mut self) -> io::Result<()>{
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn negotiates_with_a_server_over_loopback(){
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let mut client = TelnetChannel::connect(&address, 100, 40).unwrap();
+        let mut server = TelnetChannel::accept(listener.accept().unwrap().0, 80, 25).unwrap();
+
+        let mut data = Vec::new();
+        for _ in 0..200{
+            assert!(client.receive(&mut data));
+            assert!(server.receive(&mut data));
+            if client.telnet().remote_echo() && server.telnet().terminal_type.is_some()
+               && server.telnet().window_size() == (100, 40){
+                break;
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+        assert!(client.telnet().remote_echo());
+        assert_eq!(server.telnet().window_size(), (100, 40));
+        assert_eq!(server.telnet().terminal_type, Some(String::from(TERMINAL_TYPE)));
+        assert!(data.is_empty());
+
+        // with the server echoing, the client doesn't echo locally
+        client.send(b"hi");
+        let mut received = Vec::new();
+        for _ in 0..200{
+            server.receive(&mut received);
+            if received.len() >= 2{
+                break;
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+        assert_eq!(received, b"hi");
+        let mut echoed = Vec::new();
+        client.receive(&mut echoed);
+        assert!(echoed.is_empty());
+    }
+
+    #[test]
+    fn keeps_what_a_slow_stream_wont_take(){
+        let stream = Trickle{ taken: Vec::new(), budget: 4 };
+        let mut channel = TelnetChannel::new(stream, Telnet::client(80, 25));
+        channel.send(b"hello world");
+        assert_eq!(channel.stream.taken, b"hell");
+        assert!(channel.is_open());
+
+        channel.stream.budget = 100;
+        let mut data = Vec::new();
+        assert!(channel.receive(&mut data));
+        assert_eq!(channel.stream.taken, b"hello world");
+        assert!(channel.unsent.is_empty());
+    }
+
+    #[test]
+    fn drops_a_subnegotiation_that_runs_on(){
+        let mut telnet = Telnet::server(80, 25);
+        let (mut data, mut reply) = (Vec::new(), Vec::new());
+        telnet.receive(&[IAC, SB, OPT_NAWS, 0, 100, 0, 40], &mut data, &mut reply);
+        telnet.receive(&vec![7; 10000], &mut data, &mut reply);
+        assert_eq!(telnet.sub.len(), MAX_SUB);
+        telnet.receive(&[IAC, SE, b'x'], &mut data, &mut reply);
+        assert_eq!(telnet.window_size(), (80, 25));
+        assert_eq!(data, b"x");
+
+        // and the next one is read as usual
+        telnet.receive(&[IAC, SB, OPT_NAWS, 0, 100, 0, 40, IAC, SE], &mut data, &mut reply);
+        assert_eq!(telnet.window_size(), (100, 40));
+        telnet.receive(&[IAC, SB, OPT_NAWS, 0, 0, 0, 40, IAC, SE], &mut data, &mut reply);
+        assert_eq!(telnet.window_size(), (100, 40));
+    }
+}
@@ -0,0 +1,340 @@
+// Interprets a stream of bytes from a host (C0 controls and the common ANSI
+// escape sequences) and applies it to a `Grid`.
+
+use cp437;
+use grid::{Grid, LineSize, TileMap, DEC_GRAPHICS, DEFAULT_ATTR, PAGE_REGULAR, PAGE_BOLD, PAGE_ITALIC, PAGE_BOLD_ITALIC, PAGE_GRAPHICS, on_page};
+use palette::ANSI_TO_ATTR;
+use unicode_width::UnicodeWidthChar;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum State{
+    Ground,
+    Escape,
+    Designate(usize), // ESC ( or ESC ), waiting for which set G0 or G1 becomes
+    LineSize,         // ESC #, waiting for the size of the cursor's row
+    Csi,
+    Utf8(u8) // continuation bytes still expected
+}
+
+// The 94 character sets G0 and G1 can hold; SI and SO choose between them.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum CharacterSet{
+    Ascii,
+    British,    // '#' is '£'
+    DecGraphics // '_' to '~' draw lines and symbols
+}
+
+// How bytes outside ASCII are read.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Charset{
+    Utf8,
+    // One byte per glyph, as in ANSI art. Control codes that ANSI.SYS
+    // ignores are drawn as glyphs, a line feed also returns the carriage and
+    // writing the last column wraps straight away.
+    Cp437
+}
+
+pub struct Terminal{
+    state:     State,
+    params:    Vec<u32>,
+    current:   Option<u32>,
+    private:   bool, // CSI ? ...
+    charset:   Charset,
+    tiles:     TileMap,
+    codepoint: u32, // the UTF-8 sequence decoded so far
+    saved:     (u32, u32),
+    sets:      [CharacterSet; 2], // G0 and G1
+    shifted:   bool,              // SO: G1 is in use
+    bold:      bool,
+    italic:    bool,
+    smooth:    bool, // DECSCLM: scrolling glides rather than jumps
+    rang:      bool  // BEL since the last `take_bell`
+}
+
+impl Terminal{
+    pub fn new() -> Terminal{
+        Terminal::with_charset(Charset::Utf8, TileMap::ascii())
+    }
+
+    pub fn with_charset(charset: Charset, tiles: TileMap) -> Terminal{
+        Terminal{
+            state:     State::Ground,
+            params:    Vec::new(),
+            current:   None,
+            private:   false,
+            charset:   charset,
+            tiles:     tiles,
+            codepoint: 0,
+            saved:     (0, 0),
+            sets:      [CharacterSet::Ascii; 2],
+            shifted:   false,
+            bold:      false,
+            italic:    false,
+            smooth:    false,
+            rang:      false
+        }
+    }
+
+    pub fn set_tiles(&mut self, tiles: TileMap){
+        self.tiles = tiles;
+    }
+
+    pub fn set_charset(&mut self, charset: Charset){
+        self.charset = charset;
+    }
+
+    // Whether the host has asked for smooth scrolling; see `Session::process`.
+    pub fn smooth_scroll(&self) -> bool{
+        self.smooth
+    }
+
+    pub fn set_smooth_scroll(&mut self, smooth: bool){
+        self.smooth = smooth;
+    }
+
+    // Whether the bell has rung since the last call.
+    pub fn take_bell(&mut self) -> bool{
+        let rang = self.rang;
+        self.rang = false;
+        rang
+    }
+
+    pub fn feed(&mut self, grid: &mut Grid, bytes: &[u8]){
+        for &b in bytes{
+            self.byte(grid, b);
+        }
+    }
+
+    fn byte(&mut self, grid: &mut Grid, b: u8){
+        match self.state{
+            State::Ground => match b{
+                0x07 => self.rang = true,
+                0x08 => { let (x, y) = grid.cursor(); grid.set_cursor(x.saturating_sub(1), y); },
+                0x09 => grid.tab(),
+                0x0a if self.charset == Charset::Cp437 => grid.newline(),
+                0x0a | 0x0b | 0x0c => grid.line_feed(),
+                0x0d => { let (_, y) = grid.cursor(); grid.set_cursor(0, y); },
+                0x0e if self.charset == Charset::Utf8 => self.shifted = true,  // SO
+                0x0f if self.charset == Charset::Utf8 => self.shifted = false, // SI
+                0x1b => self.state = State::Escape,
+                0x20..=0x7e => self.put(grid, b as char),
+                _ if self.charset == Charset::Cp437 => {
+                    if b != 0x00 && b != 0x1a{
+                        self.put(grid, cp437::to_char(b));
+                    }
+                },
+                0xc0..=0xdf => { self.codepoint = (b & 0x1f) as u32; self.state = State::Utf8(1); },
+                0xe0..=0xef => { self.codepoint = (b & 0x0f) as u32; self.state = State::Utf8(2); },
+                0xf0..=0xf7 => { self.codepoint = (b & 0x07) as u32; self.state = State::Utf8(3); },
+                _ => ()
+            },
+            State::Utf8(remaining) => {
+                if b & 0xc0 != 0x80{
+                    // malformed; start over with this byte
+                    self.state = State::Ground;
+                    return self.byte(grid, b);
+                }
+                self.codepoint = (self.codepoint << 6) | (b & 0x3f) as u32;
+                if remaining > 1{
+                    self.state = State::Utf8(remaining - 1);
+                }
+                else{
+                    self.state = State::Ground;
+                    let c = ::std::char::from_u32(self.codepoint).unwrap_or('?');
+                    self.put(grid, c);
+                }
+            },
+            State::Escape => {
+                self.state = State::Ground;
+                match b{
+                    b'[' => {
+                        self.params.clear();
+                        self.current = None;
+                        self.private = false;
+                        self.state   = State::Csi;
+                    },
+                    b'(' => self.state = State::Designate(0),
+                    b')' => self.state = State::Designate(1),
+                    b'#' => self.state = State::LineSize,
+                    b'7' => self.saved = grid.cursor(),
+                    b'8' => grid.set_cursor(self.saved.0, self.saved.1),
+                    b'D' => grid.line_feed(),
+                    b'E' => grid.newline(),
+                    b'M' => grid.reverse_line_feed(),
+                    b'c' => {
+                        grid.clear();
+                        self.sets    = [CharacterSet::Ascii; 2];
+                        self.shifted = false;
+                        self.bold    = false;
+                        self.italic  = false;
+                    },
+                    _    => ()
+                }
+            },
+            State::Designate(g) => {
+                self.state = State::Ground;
+                self.sets[g] = match b{
+                    b'0' => CharacterSet::DecGraphics,
+                    b'A' => CharacterSet::British,
+                    _    => CharacterSet::Ascii
+                };
+            },
+            State::LineSize => {
+                self.state = State::Ground;
+                match b{
+                    b'3' => grid.set_line_size(LineSize::DoubleTop),
+                    b'4' => grid.set_line_size(LineSize::DoubleBottom),
+                    b'5' => grid.set_line_size(LineSize::Single),
+                    b'6' => grid.set_line_size(LineSize::DoubleWidth),
+                    _    => ()
+                }
+            },
+            State::Csi => match b{
+                b'0'..=b'9' => {
+                    let digit = (b - b'0') as u32;
+                    self.current = Some(self.current.unwrap_or(0).saturating_mul(10).saturating_add(digit));
+                },
+                b';' => {
+                    let param = self.current.take().unwrap_or(0);
+                    self.params.push(param);
+                },
+                b'?' => self.private = true,
+                0x40..=0x7e => {
+                    if let Some(param) = self.current.take(){
+                        self.params.push(param);
+                    }
+                    self.state = State::Ground;
+                    self.csi(grid, b);
+                },
+                0x18 | 0x1a => self.state = State::Ground, // CAN, SUB
+                _ => ()
+            }
+        }
+    }
+
+    fn put(&self, grid: &mut Grid, c: char){
+        let set = self.sets[if self.shifted { 1 } else { 0 }];
+        // line drawing is kept by its place in the set, which is where the
+        // graphics page has it; see `TileMap::text_tile` for atlases without
+        let (c, tile) = match (set, c){
+            (CharacterSet::DecGraphics, '_'..='~') => {
+                let index = c as u16 - '_' as u16;
+                (DEC_GRAPHICS[index as usize].0, on_page(index, PAGE_GRAPHICS))
+            },
+            (CharacterSet::British, '#') => {
+                let pound = self.tiles.get('\u{a3}').unwrap_or(self.tiles.tile('#'));
+                ('\u{a3}', on_page(pound, self.page()))
+            },
+            _ => (c, on_page(self.tiles.tile(c), self.page()))
+        };
+        if self.charset == Charset::Cp437{
+            grid.put_tile(tile);
+            let (x, y) = grid.cursor();
+            if x >= grid.row_width(y){
+                grid.newline();
+            }
+            return;
+        }
+        // East Asian wide characters take two cells; combining marks take none
+        // and are dropped, as there's no way to draw them over another tile
+        match c.width(){
+            Some(2) => grid.put_wide_tile(tile),
+            Some(0) => (),
+            _       => grid.put_tile(tile)
+        }
+    }
+
+    // The page text is drawn from in the current rendition. In ANSI art bold
+    // only ever means bright.
+    fn page(&self) -> u16{
+        if self.charset == Charset::Cp437{
+            return PAGE_REGULAR;
+        }
+        match (self.bold, self.italic){
+            (false, false) => PAGE_REGULAR,
+            (true,  false) => PAGE_BOLD,
+            (false, true)  => PAGE_ITALIC,
+            (true,  true)  => PAGE_BOLD_ITALIC
+        }
+    }
+
+    // Parameter `i`, or `default` if it is missing or 0.
+    fn param(&self, i: usize, default: u32) -> u32{
+        match self.params.get(i){
+            Some(&p) if p != 0 => p,
+            _                  => default
+        }
+    }
+
+    fn csi(&mut self, grid: &mut Grid, command: u8){
+        if self.private{
+            // DEC modes; only DECSCLM (4) is supported
+            if (command == b'h' || command == b'l') && self.params.contains(&4){
+                self.smooth = command == b'h';
+            }
+            return;
+        }
+        let (x, y) = grid.cursor();
+        let n = self.param(0, 1);
+        // scrolling more than the whole region only blanks it
+        let (top, bottom) = grid.scroll_region();
+        let scrolls = n.min(bottom + 1 - top);
+        match command{
+            b'A'        => grid.set_cursor(x, y.saturating_sub(n)),
+            b'B'        => grid.set_cursor(x, y.saturating_add(n)),
+            b'C'        => grid.set_cursor(x.saturating_add(n), y),
+            b'D'        => grid.set_cursor(x.saturating_sub(n), y),
+            b'G'        => grid.set_cursor(n - 1, y),
+            b'H' | b'f' => grid.set_cursor(self.param(1, 1) - 1, n - 1),
+            b'J'        => grid.erase_in_display(self.params.get(0).cloned().unwrap_or(0)),
+            b'K'        => grid.erase_in_line(self.params.get(0).cloned().unwrap_or(0)),
+            b'L'        => grid.insert_lines(n),
+            b'M'        => grid.delete_lines(n),
+            b'S'        => for _ in 0..scrolls { grid.scroll_up() },
+            b'T'        => for _ in 0..scrolls { grid.scroll_down() },
+            b'm'        => self.sgr(grid),
+            b'r'        => grid.set_scroll_region(n - 1, self.param(1, grid.height()).saturating_sub(1)),
+            b's'        => self.saved = (x, y),
+            b'u'        => grid.set_cursor(self.saved.0, self.saved.1),
+            _           => ()
+        }
+    }
+
+    // Select Graphic Rendition, mapped onto the grid's 16-colour attributes:
+    // bold is the bright foreground and blink the attribute's top bit. Bold
+    // and italic also choose the atlas page (see `page`).
+    fn sgr(&mut self, grid: &mut Grid){
+        let mut pen = grid.pen();
+        if self.params.is_empty(){
+            pen = DEFAULT_ATTR;
+            self.bold   = false;
+            self.italic = false;
+        }
+        for &p in self.params.iter(){
+            match p{
+                0  => { self.bold = false; self.italic = false; },
+                1  => self.bold   = true,
+                3  => self.italic = true,
+                22 => self.bold   = false,
+                23 => self.italic = false,
+                _  => ()
+            }
+            pen = match p{
+                0            => DEFAULT_ATTR,
+                1            => pen | 0x08,
+                5 | 6        => pen | 0x80,
+                7            => (pen & 0x88) | (pen & 0x07) << 4 | (pen & 0x70) >> 4,
+                22           => pen & !0x08,
+                25           => pen & !0x80,
+                30..=37      => (pen & 0xf8) | ANSI_TO_ATTR[(p - 30) as usize],
+                39           => (pen & 0xf8) | (DEFAULT_ATTR & 0x07),
+                40..=47      => (pen & 0x8f) | ANSI_TO_ATTR[(p - 40) as usize] << 4,
+                49           => (pen & 0x8f) | (DEFAULT_ATTR & 0x70),
+                90..=97      => (pen & 0xf0) | ANSI_TO_ATTR[(p - 90) as usize] | 0x08,
+                100..=107    => (pen & 0x0f) | (ANSI_TO_ATTR[(p - 100) as usize] | 0x08) << 4,
+                _            => pen
+            };
+        }
+        grid.set_pen(pen);
+    }
+}
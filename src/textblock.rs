@@ -1,7 +1,12 @@
 use atlas;
 use glium;
+use std::error;
+use std::fmt;
 
 use atlas::Atlas;
+use grid;
+use grid::{LineSize, ScrollShift, DEFAULT_ATTR};
+use palette::Palette;
 use profiling_timers::ScopeTimer;
 
 #[derive(Copy, Clone)]
@@ -17,9 +22,18 @@ pub struct TextBlock{
     height: u32,
     block:  Vec<u16>,
     tiles:  glium::texture::UnsignedTexture1d,
+    attrs:  Vec<u8>,
+    colours: glium::texture::UnsignedTexture1d,
+    spans:  Vec<u8>, // see `grid::layout`
+    tile_spans: glium::texture::UnsignedTexture1d,
+    palette: glium::texture::Texture1d,
+    ice_colours: bool, // attribute bit 7 brightens the background instead of blinking
+    phosphor: bool, // cells in the default colours show the atlas's own
     vbo:    glium::VertexBuffer<Vertex>,
-    ibo:    glium::index::IndexBuffer<u16>,
+    ibo:    glium::index::IndexBuffer<u32>,
     scanline_y: f32,
+    shift:  Option<ScrollShift>, // rows drawn lower down during a smooth scroll
+    flash:  f32, // the visual bell, from 0 (off) to 1 (fully reversed)
 }
 
 #[derive(Debug)]
@@ -27,13 +41,28 @@ pub enum TextBlockErr{
     WrongSizeBlock,
     VBOCreation(glium::vertex::BufferCreationError),
     IBOCreation(glium::index::BufferCreationError),
-    TextureCreation(glium::texture::TextureCreationError)
+    TextureCreation(glium::texture::TextureCreationError),
+    Draw(glium::DrawError)
 }
 
+impl fmt::Display for TextBlockErr{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result{
+        match *self{
+            TextBlockErr::WrongSizeBlock     => write!(f, "the tiles don't fill the block"),
+            TextBlockErr::VBOCreation(ref e) => write!(f, "couldn't make the vertex buffer: {}", e),
+            TextBlockErr::IBOCreation(ref e) => write!(f, "couldn't make the index buffer: {:?}", e),
+            TextBlockErr::TextureCreation(ref e) => write!(f, "couldn't make a texture: {}", e),
+            TextBlockErr::Draw(ref e)        => write!(f, "couldn't draw: {}", e)
+        }
+    }
+}
+
+impl error::Error for TextBlockErr{}
+
 impl TextBlock{
     fn generate_tile_triangles(atlas: &atlas::AtlasDimensions,
                                position: [f32; 2]) -> [Vertex; 4]{
-        let atlas_columns:u32 = (atlas.atlas_w_u / atlas.tile_w_u) as u32;
+        let atlas_columns:u32 = atlas.columns();
 
         enum Corner{
             TL, TR, BL, BR
@@ -70,19 +99,54 @@ impl TextBlock{
         ]
     }
 
-    fn generate_tile_indices(tile_index: u32) -> [u16; 6]{
+    // 32 bit, as past 16384 tiles the vertex numbers don't fit in 16.
+    fn generate_tile_indices(tile_index: u32) -> [u32; 6]{
         let index_base = tile_index * 4;
 
-        [(index_base + 0u32) as u16, (index_base + 1) as u16, (index_base + 2) as u16,
-         (index_base + 2)    as u16, (index_base + 1) as u16, (index_base + 3) as u16]
+        [index_base + 0, index_base + 1, index_base + 2,
+         index_base + 2, index_base + 1, index_base + 3]
+    }
+
+    // The corners of every tile, whose texture coordinates depend on the
+    // atlas's tile size.
+    fn generate_vbo<F>(glium: &F, atlas: &atlas::AtlasDimensions,
+                       width: u32, height: u32)
+                       -> Result<glium::VertexBuffer<Vertex>, TextBlockErr>
+        where F: glium::backend::Facade{
+        let mut triangles:Vec<Vertex> =
+            Vec::with_capacity((height * width * 4) as usize); // 4 unique vertices per tile
+
+        for index in 0..(width * height) as u32{
+            triangles.extend_from_slice(
+                &TextBlock::generate_tile_triangles(
+                    atlas,
+                    [(index % width) as f32,
+                     (index / width) as f32]));
+        }
+
+        match glium::VertexBuffer::persistent(glium, &triangles){
+            Ok(v)  => Ok(v),
+            Err(e) => Err(TextBlockErr::VBOCreation(e))
+        }
     }
 
     #[allow(unused_variables)]
-    fn update<F>(&mut self, glium: &F) where F: glium::backend::Facade
+    fn update<F>(&mut self, glium: &F) -> Result<(), TextBlockErr> where F: glium::backend::Facade
     {
         let outer  = ScopeTimer::new("tile-based update");
-        self.tiles = glium::texture::UnsignedTexture1d::new(glium, self.block.clone())
-            .expect("Failed to make texture");
+        self.tiles = match glium::texture::UnsignedTexture1d::new(glium, self.block.clone()){
+            Ok(t)  => t,
+            Err(e) => return Err(TextBlockErr::TextureCreation(e))
+        };
+        self.colours = match glium::texture::UnsignedTexture1d::new(glium, self.attrs.clone()){
+            Ok(t)  => t,
+            Err(e) => return Err(TextBlockErr::TextureCreation(e))
+        };
+        self.tile_spans = match glium::texture::UnsignedTexture1d::new(glium, self.spans.clone()){
+            Ok(t)  => t,
+            Err(e) => return Err(TextBlockErr::TextureCreation(e))
+        };
+        Ok(())
     }
 
     pub fn new<F>(glium: &F, atlas: &Atlas,
@@ -101,30 +165,18 @@ impl TextBlock{
             None    => vec![0u16; (width * height) as usize]
         };
 
-        let mut triangles:Vec<Vertex> =
-            Vec::with_capacity((height * width * 4) as usize); // 4 unique vertices per tile
-
-        let mut indices:Vec<u16> =
-            Vec::<u16>::with_capacity((height * width * 6) as usize); // 6 indexed vertices per tile
+        let mut indices:Vec<u32> =
+            Vec::<u32>::with_capacity((height * width * 6) as usize); // 6 indexed vertices per tile
 
         for index in 0..(width * height) as u32{
-            triangles.extend_from_slice(
-                &TextBlock::generate_tile_triangles(
-                    &atlas.dimensions,
-                    [(index % width) as f32,
-                     (index / width) as f32]));
-
             indices.extend_from_slice(
                 &TextBlock::generate_tile_indices(
                     index as u32))
         }
 
-        let vbo =
-            match glium::VertexBuffer::persistent(glium,
-                                                  &triangles)
-        {
+        let vbo = match TextBlock::generate_vbo(glium, &atlas.sheet(), width, height){
             Ok(v)  => v,
-            Err(e) => return Err(TextBlockErr::VBOCreation(e))
+            Err(e) => return Err(e)
         };
 
         let ibo =
@@ -143,25 +195,155 @@ impl TextBlock{
             Err(e) => return Err(TextBlockErr::TextureCreation(e))
         };
 
+        let attrs = vec![DEFAULT_ATTR; (width * height) as usize];
+        let colours =
+            match glium::texture::UnsignedTexture1d::new(glium, attrs.clone())
+        {
+            Ok(t)  => t,
+            Err(e) => return Err(TextBlockErr::TextureCreation(e))
+        };
+
+        let spans = vec![0u8; (width * height) as usize];
+        let tile_spans =
+            match glium::texture::UnsignedTexture1d::new(glium, spans.clone())
+        {
+            Ok(t)  => t,
+            Err(e) => return Err(TextBlockErr::TextureCreation(e))
+        };
+
+        let palette =
+            match glium::texture::Texture1d::new(glium, Palette::vga().colours.to_vec())
+        {
+            Ok(t)  => t,
+            Err(e) => return Err(TextBlockErr::TextureCreation(e))
+        };
+
         let tb = TextBlock{
-            atlas:  atlas.dimensions.clone(),
+            atlas:  atlas.sheet(),
             width:  width,
             height: height,
             block:  final_block,
             tiles:  tiles,
+            attrs:  attrs,
+            colours: colours,
+            spans:  spans,
+            tile_spans: tile_spans,
+            palette: palette,
+            ice_colours: false,
+            phosphor: true,
             vbo:    vbo,
             ibo:    ibo,
-            scanline_y: 0.0f32
+            scanline_y: 0.0f32,
+            shift:  None,
+            flash:  0.0
         };
 
         Ok(tb)
     }
 
+    pub fn width(&self) -> u32{
+        self.width
+    }
+
+    pub fn height(&self) -> u32{
+        self.height
+    }
+
+    // Replaces the tile ids shown by this block. The new contents are
+    // uploaded on the next `draw`.
+    pub fn set_block(&mut self, block: &[u16]) -> Result<(), TextBlockErr>{
+        if block.len() as u32 != (self.width * self.height){
+            return Err(TextBlockErr::WrongSizeBlock);
+        }
+        self.block.clear();
+        self.block.extend_from_slice(block);
+        Ok(())
+    }
+
+    // Replaces the cell attributes, in the same way as `set_block`.
+    pub fn set_attrs(&mut self, attrs: &[u8]) -> Result<(), TextBlockErr>{
+        if attrs.len() as u32 != (self.width * self.height){
+            return Err(TextBlockErr::WrongSizeBlock);
+        }
+        self.attrs.clear();
+        self.attrs.extend_from_slice(attrs);
+        Ok(())
+    }
+
+    // Sets which part of its tile each cell shows, in the same way as
+    // `set_block`; see `grid::layout`.
+    pub fn set_spans(&mut self, spans: &[u8]) -> Result<(), TextBlockErr>{
+        if spans.len() as u32 != (self.width * self.height){
+            return Err(TextBlockErr::WrongSizeBlock);
+        }
+        self.spans.clear();
+        self.spans.extend_from_slice(spans);
+        Ok(())
+    }
+
+    // Draws the rows of `shift` lower down from the next `draw` on, or all
+    // rows in place for None.
+    pub fn set_shift(&mut self, shift: Option<ScrollShift>){
+        self.shift = shift;
+    }
+
+    // Reverses the colours by `flash`, from 0 to 1, for the visual bell.
+    pub fn set_flash(&mut self, flash: f32){
+        self.flash = flash;
+    }
+
+    pub fn set_palette<F>(&mut self, glium: &F, palette: &Palette) -> Result<(), TextBlockErr>
+        where F: glium::backend::Facade{
+        self.palette =
+            match glium::texture::Texture1d::new(glium, palette.colours.to_vec()){
+                Ok(t)  => t,
+                Err(e) => return Err(TextBlockErr::TextureCreation(e))
+            };
+        Ok(())
+    }
+
+    pub fn set_ice_colours(&mut self, ice: bool){
+        self.ice_colours = ice;
+    }
+
+    // Whether cells in `grid::DEFAULT_ATTR` are drawn in the atlas's own
+    // colours, the green of the screen, rather than from the palette. Art
+    // turns this off so its light grey is light grey.
+    pub fn set_phosphor(&mut self, phosphor: bool){
+        self.phosphor = phosphor;
+    }
+
+    // Switches to an atlas laid out differently from the one the block was
+    // made for, such as a reloaded or newly chosen font. `draw` does this
+    // itself when given such an atlas.
+    pub fn set_atlas<F>(&mut self, glium: &F, atlas: &Atlas) -> Result<(), TextBlockErr>
+        where F: glium::backend::Facade{
+        let sheet = atlas.sheet();
+        self.vbo = match TextBlock::generate_vbo(glium, &sheet, self.width, self.height){
+            Ok(v)  => v,
+            Err(e) => return Err(e)
+        };
+        self.atlas = sheet;
+        Ok(())
+    }
+
     pub fn draw<F>(&mut self,
                 display: &F,
                 program: &glium::Program,
                 target: &mut glium::Frame,
-                atlas: &Atlas) where F: glium::backend::Facade{
+                atlas: &Atlas) -> Result<(), TextBlockErr> where F: glium::backend::Facade{
+        self.draw_viewport(display, program, target, atlas, None)
+    }
+
+    // Like `draw`, but confines the block to `viewport` (in pixels, origin at
+    // the bottom left of the frame) instead of filling the whole frame.
+    pub fn draw_viewport<F>(&mut self,
+                display: &F,
+                program: &glium::Program,
+                target: &mut glium::Frame,
+                atlas: &Atlas,
+                viewport: Option<glium::Rect>) -> Result<(), TextBlockErr>
+        where F: glium::backend::Facade{
         use glium::Surface;
         use nalgebra::*;
 
@@ -179,24 +361,175 @@ impl TextBlock{
                          0.0f32, 1.0f32, 1.0f32,
                          0.0f32, 0.0f32, 1.0f32) * scaled_matrix;
 
-        self.update(display);
+        if atlas.sheet() != self.atlas{
+            match self.set_atlas(display, atlas){
+                Ok(())  => (),
+                Err(e)  => return Err(e)
+            }
+        }
+        match self.update(display){
+            Ok(())  => (),
+            Err(e)  => return Err(e)
+        }
 
-        let uniforms = uniform! {
+        let uniforms = |matrix: &Matrix3<f32>| uniform! {
             tex:           &atlas.texture,
             matrix:        *matrix.as_ref(),
             scanline_y:    self.scanline_y,
+            flash:         self.flash,
             tile_id:      &self.tiles,
+            tile_attr:    &self.colours,
+            tile_span:    &self.tile_spans,
+            palette:      &self.palette,
+            ice_colours:   self.ice_colours,
+            phosphor:      self.phosphor,
             tile_width:    self.atlas.tile_w_f / self.atlas.atlas_w_f,
             tile_height:   self.atlas.tile_h_f / self.atlas.atlas_h_f,
-            atlas_columns: (self.atlas.atlas_w_u / self.atlas.tile_w_u) as u32
+            atlas_columns: self.atlas.columns(),
+            page_height:   1.0 / atlas.pages.len() as f32, // see `Atlas::upload`
+            page_map:      atlas.page_map()
         };
 
-        target.draw(&self.vbo, &self.ibo, program, &uniforms,
-                    &Default::default()).expect("Failed to draw");
+        let params = glium::DrawParameters{
+            viewport: viewport,
+            .. Default::default()
+        };
+
+        if let Err(e) = target.draw(&self.vbo, &self.ibo, program, &uniforms(&matrix), &params){
+            return Err(TextBlockErr::Draw(e));
+        }
+
+        // part way through a smooth scroll the scrolling rows are drawn again
+        // over a blank, moved by the offset and clipped to their region
+        if let Some(shift) = self.shift{
+            if shift.offset != 0.0 && shift.top <= shift.bottom && shift.bottom < self.height{
+                let area = viewport.unwrap_or_else(|| {
+                    let (width, height) = target.get_dimensions();
+                    glium::Rect{ left: 0, bottom: 0, width: width, height: height }
+                });
+                let row_height = area.height as f32 / self.height as f32;
+                let region = glium::Rect{
+                    left:   area.left,
+                    bottom: area.bottom + ((self.height - 1 - shift.bottom) as f32 * row_height).round() as u32,
+                    width:  area.width,
+                    height: ((shift.bottom - shift.top + 1) as f32 * row_height).round() as u32
+                };
+                target.clear(Some(&region), Some((0.0, 0.0, 0.0, 1.0)), false, None, None);
+
+                let shifted: Matrix3<f32> =
+                    Matrix3::new(1.0f32, 0.0f32,-1.0f32,
+                                 0.0f32, 1.0f32, 1.0f32 - shift.offset * scale_y,
+                                 0.0f32, 0.0f32, 1.0f32) * scaled_matrix;
+                let params = glium::DrawParameters{
+                    viewport: viewport,
+                    scissor:  Some(region),
+                    .. Default::default()
+                };
+                if let Err(e) = target.draw(&self.vbo, &self.ibo, program, &uniforms(&shifted), &params){
+                    return Err(TextBlockErr::Draw(e));
+                }
+            }
+        }
 
         self.scanline_y += 1.0f32 / 5.0f32 + 0.02f32; // this is just some random number tbh. Should appear like multiple bars crawling up the screen.
         if self.scanline_y > 1.2{   // if greater than the size of the screen and a little bit
             self.scanline_y -= 1.4; // set the scanline to just before the screen by a little bit (-0.4)
         }
+        Ok(())
+    }
+}
+
+// Owns a `TextBlock` for showing a grid of tiles that may change size, making
+// a new block whenever the size changes.
+pub struct GridView{
+    block:       Option<TextBlock>,
+    palette:     Palette,
+    ice_colours: bool,
+    phosphor:    bool,
+    shift:       Option<ScrollShift>,
+    flash:       f32
+}
+
+impl GridView{
+    pub fn new() -> GridView{
+        GridView{
+            block:       None,
+            palette:     Palette::vga(),
+            ice_colours: false,
+            phosphor:    true,
+            shift:       None,
+            flash:       0.0
+        }
+    }
+
+    pub fn set_palette(&mut self, palette: Palette){
+        self.palette = palette;
+        self.block   = None;
+    }
+
+    pub fn set_ice_colours(&mut self, ice: bool){
+        self.ice_colours = ice;
+    }
+
+    // See `TextBlock::set_phosphor`.
+    pub fn set_phosphor(&mut self, phosphor: bool){
+        self.phosphor = phosphor;
+    }
+
+    // See `TextBlock::set_shift`.
+    pub fn set_shift(&mut self, shift: Option<ScrollShift>){
+        self.shift = shift;
+    }
+
+    // See `TextBlock::set_flash`.
+    pub fn set_flash(&mut self, flash: f32){
+        self.flash = flash;
+    }
+
+    pub fn draw<F>(&mut self,
+                   display:  &F,
+                   program:  &glium::Program,
+                   target:   &mut glium::Frame,
+                   atlas:    &Atlas,
+                   width:    u32,
+                   height:   u32,
+                   cells:    &[u16],
+                   attrs:    &[u8],
+                   lines:    &[LineSize],
+                   viewport: Option<glium::Rect>) -> Result<(), TextBlockErr>
+        where F: glium::backend::Facade{
+        let stale = match self.block{
+            Some(ref b) => b.width() != width || b.height() != height,
+            None        => true
+        };
+        if stale{
+            self.block = None;
+        }
+        // wide characters and double size rows spread over several cells
+        let (cells, attrs, spans) = grid::layout(width, cells, attrs, lines);
+        let cells: Vec<u16> = cells.iter().map(|&t| atlas.drawn_tile(t)).collect();
+        let block = match self.block{
+            Some(ref mut b) => b,
+            None => {
+                let mut block =
+                    match TextBlock::new(display, atlas, width, height, Some(&cells)){
+                        Ok(b)  => b,
+                        Err(e) => return Err(e)
+                    };
+                match block.set_palette(display, &self.palette){
+                    Ok(())  => (),
+                    Err(e)  => return Err(e)
+                }
+                self.block.get_or_insert(block)
+            }
+        };
+        block.set_ice_colours(self.ice_colours);
+        block.set_phosphor(self.phosphor);
+        block.set_shift(self.shift);
+        block.set_flash(self.flash);
+        match block.set_block(&cells).and_then(|_| block.set_attrs(&attrs)).and_then(|_| block.set_spans(&spans)){
+            Ok(())  => block.draw_viewport(display, program, target, atlas, viewport),
+            Err(e)  => Err(e)
+        }
     }
 }
@@ -0,0 +1,83 @@
+// Paces a channel's output at a modem's speed, optionally with line noise,
+// so screens paint in the way they did over a phone line.
+
+use rand;
+use rand::Rng;
+use std::collections::VecDeque;
+use time::PreciseTime;
+
+use session::Channel;
+
+pub const STANDARD_RATES: [u32; 7] = [300, 1200, 2400, 9600, 14400, 28800, 56000];
+
+// 8N1: a start bit, 8 data bits and a stop bit
+const BITS_PER_BYTE: f64 = 10.0;
+
+pub struct Throttle{
+    inner:     Box<dyn Channel>,
+    baud:      u32,
+    noise:     f32, // chance of a garbage byte before each real one
+    pending:   VecDeque<u8>,
+    last:      PreciseTime,
+    allowance: f64, // bytes that may be released but haven't been yet
+    open:      bool,
+    received:  Vec<u8>
+}
+
+impl Throttle{
+    pub fn new(inner: Box<dyn Channel>, baud: u32, noise: f32) -> Throttle{
+        Throttle{
+            inner:     inner,
+            baud:      baud,
+            noise:     noise,
+            pending:   VecDeque::new(),
+            last:      PreciseTime::now(),
+            allowance: 0.0,
+            open:      true,
+            received:  Vec::new()
+        }
+    }
+}
+
+impl Channel for Throttle{
+    fn send(&mut self, bytes: &[u8]){
+        self.inner.send(bytes);
+    }
+
+    fn receive(&mut self, buf: &mut Vec<u8>) -> bool{
+        if self.open{
+            self.received.clear();
+            self.open = self.inner.receive(&mut self.received);
+            self.pending.extend(self.received.iter());
+        }
+
+        let now = PreciseTime::now();
+        if self.pending.is_empty(){
+            // an idle line doesn't save up for a burst later
+            self.allowance = 0.0;
+            self.last = now;
+            return self.open;
+        }
+        let elapsed = match self.last.to(now).num_microseconds(){
+            Some(us) => us as f64 / 1_000_000.0,
+            None     => 0.0
+        };
+        self.last = now;
+        self.allowance += elapsed * self.baud as f64 / BITS_PER_BYTE;
+
+        let count = ::std::cmp::min(self.allowance as usize, self.pending.len());
+        self.allowance -= count as f64;
+        let mut rng = rand::thread_rng();
+        for b in self.pending.drain(..count){
+            if self.noise > 0.0 && rng.gen::<f32>() < self.noise{
+                buf.push(rng.gen_range(0x21u8, 0x7f));
+            }
+            buf.push(b);
+        }
+        self.open || !self.pending.is_empty()
+    }
+
+    fn resize(&mut self, width: u32, height: u32){
+        self.inner.resize(width, height);
+    }
+}
@@ -1,5 +1,7 @@
 use atlas;
 use glium;
+use std::error;
+use std::fmt;
 
 use atlas::Atlas;
 
@@ -23,9 +25,23 @@ pub struct TileBlock{
 pub enum TileBlockErr{
     WrongSizeBlock,
     VBOCreation(glium::vertex::BufferCreationError),
-    IBOCreation(glium::index::BufferCreationError)
+    IBOCreation(glium::index::BufferCreationError),
+    Draw(glium::DrawError)
 }
 
+impl fmt::Display for TileBlockErr{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result{
+        match *self{
+            TileBlockErr::WrongSizeBlock     => write!(f, "the tiles don't fill the block"),
+            TileBlockErr::VBOCreation(ref e) => write!(f, "couldn't make the vertex buffer: {}", e),
+            TileBlockErr::IBOCreation(ref e) => write!(f, "couldn't make the index buffer: {:?}", e),
+            TileBlockErr::Draw(ref e)        => write!(f, "couldn't draw: {}", e)
+        }
+    }
+}
+
+impl error::Error for TileBlockErr{}
+
 impl TileBlock{
     fn generate_tile_triangles(&self,
                                position: [f32; 2],
@@ -134,7 +150,7 @@ impl TileBlock{
         };
 
         let mut tb = TileBlock{
-            atlas:  atlas.dimensions.clone(),
+            atlas:  atlas.sheet(),
             width:  width,
             height: height,
             block:  final_block,
@@ -151,7 +167,7 @@ impl TileBlock{
                 program: &glium::Program,
                 target: &mut glium::Frame,
                 atlas: &Atlas,
-                offset: [f32; 2]){
+                offset: [f32; 2]) -> Result<(), TileBlockErr>{
         use glium::Surface;
         use nalgebra::*;
 
@@ -176,7 +192,9 @@ impl TileBlock{
             matrix: *matrix.as_ref(),
         };
 
-        target.draw(&self.vbo, &self.ibo, program, &uniforms,
-                    &Default::default()).expect("Failed to draw");
+        match target.draw(&self.vbo, &self.ibo, program, &uniforms, &Default::default()){
+            Ok(())  => Ok(()),
+            Err(e)  => Err(TileBlockErr::Draw(e))
+        }
     }
 }
@@ -0,0 +1,218 @@
+// Plain byte streams a session can talk over, and picking one from a URL.
+
+use std::io;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::path::Path;
+
+use pty::Pty;
+use serial::{Serial, SerialConfig};
+use session::Channel;
+use telnet::TelnetChannel;
+
+// A byte stream to whatever is at the other end of a session. Reads must not
+// block: `WouldBlock` means nothing has arrived yet and `Ok(0)` that the
+// stream is closed.
+pub trait Transport: Read + Write{
+    // Called when the session's grid changes size.
+    fn resize(&mut self, _width: u32, _height: u32){}
+}
+
+impl Transport for TcpStream{}
+
+fn invalid(message: &str) -> io::Error{
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+// Writes as much of `pending` as `writer` takes without blocking and removes
+// it, leaving the rest for next time. Returns false once the writer is closed.
+pub fn write_pending<W: Write>(writer: &mut W, pending: &mut Vec<u8>) -> bool{
+    let mut written = 0;
+    let mut open = true;
+    while written < pending.len(){
+        match writer.write(&pending[written..]){
+            Ok(0)  => { open = false; break; },
+            Ok(n)  => written += n,
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => (),
+            Err(_) => { open = false; break; }
+        }
+    }
+    pending.drain(..written);
+    open
+}
+
+pub fn connect_tcp(address: &str) -> io::Result<TcpStream>{
+    let stream = match TcpStream::connect(address){
+        Ok(s)  => s,
+        Err(e) => return Err(e)
+    };
+    match stream.set_nonblocking(true){
+        Ok(())  => (),
+        Err(e)  => return Err(e)
+    }
+    match stream.set_nodelay(true){
+        Ok(())  => Ok(stream),
+        Err(e)  => Err(e)
+    }
+}
+
+// Passes bytes through untouched, optionally echoing what is sent for
+// devices that don't echo themselves.
+pub struct RawChannel<T>{
+    transport:  T,
+    open:       bool,
+    buf:        Vec<u8>,
+    local_echo: bool,
+    echo:       Vec<u8>,
+    unsent:     Vec<u8>  // what the transport hasn't taken yet
+}
+
+impl<T: Transport> RawChannel<T>{
+    pub fn new(transport: T, local_echo: bool) -> RawChannel<T>{
+        RawChannel{
+            transport:  transport,
+            open:       true,
+            buf:        vec![0u8; 4096],
+            local_echo: local_echo,
+            echo:       Vec::new(),
+            unsent:     Vec::new()
+        }
+    }
+}
+
+impl<T: Transport> Channel for RawChannel<T>{
+    fn send(&mut self, bytes: &[u8]){
+        if self.local_echo{
+            self.echo.extend_from_slice(bytes);
+        }
+        if self.open{
+            self.unsent.extend_from_slice(bytes);
+            self.open = write_pending(&mut self.transport, &mut self.unsent);
+        }
+    }
+
+    fn receive(&mut self, data: &mut Vec<u8>) -> bool{
+        data.extend(self.echo.drain(..));
+        if self.open && !self.unsent.is_empty(){
+            self.open = write_pending(&mut self.transport, &mut self.unsent);
+        }
+        while self.open{
+            match self.transport.read(&mut self.buf){
+                Ok(0)  => self.open = false,
+                Ok(n)  => data.extend_from_slice(&self.buf[..n]),
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => (),
+                // a PTY reads EIO once the child has gone
+                Err(_) => self.open = false
+            }
+        }
+        self.open
+    }
+
+    fn resize(&mut self, width: u32, height: u32){
+        self.transport.resize(width, height);
+    }
+}
+
+// Opens a channel described by a URL:
+//
+//     telnet://host[:port]              telnet, the default without a scheme
+//     tcp://host:port                   raw TCP
+//     serial:/dev/ttyUSB0[,9600,8N1]    a serial port, see `SerialConfig::parse`
+//     shell:[command]                   a command (default $SHELL) on a PTY
+pub fn connect(url: &str, width: u32, height: u32) -> io::Result<Box<dyn Channel>>{
+    if url.starts_with("tcp://"){
+        let address = &url["tcp://".len()..];
+        if !address.contains(':'){
+            return Err(invalid("tcp:// needs a port"));
+        }
+        match connect_tcp(address){
+            Ok(s)  => Ok(Box::new(RawChannel::new(s, false))),
+            Err(e) => Err(e)
+        }
+    }
+    else if url.starts_with("serial:"){
+        let mut parts = url["serial:".len()..].splitn(2, ',');
+        let path = parts.next().unwrap_or("");
+        let config = match SerialConfig::parse(parts.next().unwrap_or("")){
+            Ok(c)  => c,
+            Err(e) => return Err(e)
+        };
+        match Serial::open(Path::new(path), &config){
+            Ok(s)  => Ok(Box::new(RawChannel::new(s, false))),
+            Err(e) => Err(e)
+        }
+    }
+    else if url.starts_with("shell:"){
+        let command = &url["shell:".len()..];
+        match Pty::spawn(if command.is_empty() { None } else { Some(command) }, width, height){
+            Ok(p)  => Ok(Box::new(RawChannel::new(p, false))),
+            Err(e) => Err(e)
+        }
+    }
+    else{
+        match TelnetChannel::connect(url, width as u16, height as u16){
+            Ok(c)  => Ok(Box::new(c)),
+            Err(e) => Err(e)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use std::os::unix::net::UnixStream;
+
+    impl Transport for UnixStream{}
+
+    #[test]
+    fn send_queues_what_a_full_stream_wont_take(){
+        let (near, mut far) = UnixStream::pair().unwrap();
+        near.set_nonblocking(true).unwrap();
+        far.set_nonblocking(true).unwrap();
+        let mut channel = RawChannel::new(near, false);
+
+        // far more than a socket buffer holds; this mustn't spin waiting
+        let sent: Vec<u8> = (0..4 * 1024 * 1024).map(|i| i as u8).collect();
+        channel.send(&sent);
+        assert!(!channel.unsent.is_empty());
+
+        let mut arrived = Vec::new();
+        let mut buf = vec![0u8; 65536];
+        let mut data = Vec::new();
+        while arrived.len() < sent.len(){
+            match far.read(&mut buf){
+                Ok(n)  => arrived.extend_from_slice(&buf[..n]),
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => assert!(channel.receive(&mut data)),
+                Err(e) => panic!("{}", e)
+            }
+        }
+        assert!(arrived == sent);
+        assert!(channel.unsent.is_empty());
+        assert!(data.is_empty());
+    }
+
+    #[test]
+    fn receives_and_echoes(){
+        let (near, mut far) = UnixStream::pair().unwrap();
+        near.set_nonblocking(true).unwrap();
+        let mut channel = RawChannel::new(near, true);
+        channel.send(b"AT\r");
+        far.write_all(b"OK\r\n").unwrap();
+        let mut data = Vec::new();
+        assert!(channel.receive(&mut data));
+        assert_eq!(data, b"AT\rOK\r\n");
+
+        drop(far);
+        assert!(!channel.receive(&mut data));
+    }
+
+    #[test]
+    fn write_pending_stops_at_a_closed_writer(){
+        let mut pending = b"lost".to_vec();
+        let mut closed: &mut [u8] = &mut [];
+        assert!(!write_pending(&mut closed, &mut pending));
+        assert_eq!(pending, b"lost");
+    }
+}
@@ -0,0 +1,66 @@
+// ttyrec, the format of most archived BBS and roguelike sessions: frames of
+// terminal output, each after a 12 byte header of the seconds and
+// microseconds it was written at and its length, all little endian. The
+// terminal's size isn't recorded.
+
+use std::error;
+use std::fmt;
+
+use player::{Frame, FrameData, Recording};
+
+const HEADER_LEN: usize = 12;
+
+// ttyrec files are almost always of 80x24 terminals.
+pub const DEFAULT_WIDTH:  u32 = 80;
+pub const DEFAULT_HEIGHT: u32 = 24;
+
+#[derive(Debug)]
+pub enum TtyrecErr{
+    NoFrames
+}
+
+impl fmt::Display for TtyrecErr{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result{
+        match *self{
+            TtyrecErr::NoFrames => write!(f, "no ttyrec frames")
+        }
+    }
+}
+
+impl error::Error for TtyrecErr{}
+
+fn le32(bytes: &[u8]) -> u32{
+    bytes[0] as u32 | (bytes[1] as u32) << 8 | (bytes[2] as u32) << 16 | (bytes[3] as u32) << 24
+}
+
+// Reads the frames, timed from the first. A last frame cut short, as they
+// often are in archives, keeps what there is of it.
+pub fn parse(data: &[u8], title: &str) -> Result<Recording, TtyrecErr>{
+    let mut frames = Vec::new();
+    let mut first = None;
+    let mut pos = 0;
+    while pos + HEADER_LEN <= data.len(){
+        let header = &data[pos..pos + HEADER_LEN];
+        let time = le32(&header[0..4]) as f64 + le32(&header[4..8]) as f64 / 1_000_000.0;
+        let len  = le32(&header[8..12]) as usize;
+        let body = pos + HEADER_LEN;
+        let end  = if len > data.len() - body { data.len() } else { body + len };
+        let start = *first.get_or_insert(time);
+        // clocks get set back mid-session; such frames play straight away
+        let last = frames.last().map(|f: &Frame| f.time).unwrap_or(0.0);
+        frames.push(Frame{
+            time: (time - start).max(last),
+            data: FrameData::Output(data[body..end].to_vec())
+        });
+        pos = end;
+    }
+    if frames.is_empty(){
+        return Err(TtyrecErr::NoFrames);
+    }
+    Ok(Recording{
+        width:  DEFAULT_WIDTH,
+        height: DEFAULT_HEIGHT,
+        title:  String::from(title),
+        frames: frames
+    })
+}
@@ -0,0 +1,83 @@
+// Asks the window manager to draw attention to the window, as the bell does
+// when it rings while the window isn't focused. Only X11 has a way to ask
+// (the urgency hint in WM_HINTS); elsewhere this does nothing.
+//
+// Xlib is loaded at run time, as the windowing library does, rather than
+// linked.
+
+use glium::backend::glutin_backend::GlutinFacade;
+use libc;
+use libc::{c_int, c_long, c_ulong, c_void};
+use std::env;
+use std::mem;
+
+const URGENCY_HINT: c_long = 1 << 8;
+
+#[repr(C)]
+struct WmHints{
+    flags:         c_long,
+    input:         c_int,
+    initial_state: c_int,
+    icon_pixmap:   c_ulong,
+    icon_window:   c_ulong,
+    icon_x:        c_int,
+    icon_y:        c_int,
+    icon_mask:     c_ulong,
+    window_group:  c_ulong
+}
+
+type GetWmHints   = unsafe extern "C" fn(*mut c_void, c_ulong) -> *mut WmHints;
+type AllocWmHints = unsafe extern "C" fn() -> *mut WmHints;
+type SetWmHints   = unsafe extern "C" fn(*mut c_void, c_ulong, *mut WmHints) -> c_int;
+type Free         = unsafe extern "C" fn(*mut c_void) -> c_int;
+type Flush        = unsafe extern "C" fn(*mut c_void) -> c_int;
+
+// Whether the window is an X11 one. Wayland is used instead whenever it
+// is there.
+fn on_x11() -> bool{
+    let set = |name: &str| env::var_os(name).map(|v| !v.is_empty()).unwrap_or(false);
+    !cfg!(target_os = "macos") && set("DISPLAY") && !set("WAYLAND_DISPLAY") &&
+        env::var("XDG_SESSION_TYPE").map(|t| t != "wayland").unwrap_or(true)
+}
+
+pub fn set_urgent(display: &GlutinFacade, urgent: bool){
+    if !on_x11(){
+        return;
+    }
+    let window = match display.get_window(){
+        Some(w) => w,
+        None    => return
+    };
+    unsafe{
+        let (xdisplay, xwindow) = (window.platform_display(), window.platform_window() as c_ulong);
+        if xdisplay.is_null(){
+            return;
+        }
+        let lib = libc::dlopen(b"libX11.so.6\0".as_ptr() as *const _, libc::RTLD_LAZY);
+        if lib.is_null(){
+            return;
+        }
+        let symbols = [b"XGetWMHints\0".as_ptr(), b"XAllocWMHints\0".as_ptr(),
+                       b"XSetWMHints\0".as_ptr(), b"XFree\0".as_ptr(), b"XFlush\0".as_ptr()];
+        let found: Vec<*mut c_void> = symbols.iter().map(|&s| libc::dlsym(lib, s as *const _)).collect();
+        if found.iter().all(|f| !f.is_null()){
+            let get_hints:   GetWmHints   = mem::transmute(found[0]);
+            let alloc_hints: AllocWmHints = mem::transmute(found[1]);
+            let set_hints:   SetWmHints   = mem::transmute(found[2]);
+            let free:        Free         = mem::transmute(found[3]);
+            let flush:       Flush        = mem::transmute(found[4]);
+
+            let mut hints = get_hints(xdisplay, xwindow);
+            if hints.is_null(){
+                hints = alloc_hints();
+            }
+            if !hints.is_null(){
+                if urgent { (*hints).flags |= URGENCY_HINT; } else { (*hints).flags &= !URGENCY_HINT; }
+                set_hints(xdisplay, xwindow, hints);
+                free(hints as *mut c_void);
+                flush(xdisplay);
+            }
+        }
+        libc::dlclose(lib);
+    }
+}
@@ -0,0 +1,100 @@
+// Shows a piece of art a window's height at a time, scrolling through art
+// that is taller than that.
+
+use glium;
+use glium::glutin::{ElementState, Event, MouseScrollDelta, VirtualKeyCode};
+
+use art::Art;
+use atlas::Atlas;
+use textblock::{GridView, TextBlockErr};
+
+// Mouse wheel notches scroll this many rows.
+const WHEEL_LINES: i32 = 3;
+
+// What the art window should do after an event.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Request{
+    Continue,
+    Close,
+    SwitchMode // between viewing and editing
+}
+
+pub struct Viewer{
+    art:  Art,
+    view: GridView,
+    top:  u32, // first row of art shown
+    rows: u32
+}
+
+impl Viewer{
+    // A viewer showing `rows` rows at a time, or the whole art if it is
+    // shorter than that.
+    pub fn new(art: Art, rows: u32) -> Viewer{
+        let mut view = GridView::new();
+        view.set_palette(art.palette);
+        view.set_ice_colours(art.ice_colours);
+        view.set_phosphor(false);
+        let rows = ::std::cmp::min(rows, art.height());
+        Viewer{
+            art:  art,
+            view: view,
+            top:  0,
+            rows: rows
+        }
+    }
+
+    pub fn art(&self) -> &Art{
+        &self.art
+    }
+
+    pub fn into_art(self) -> Art{
+        self.art
+    }
+
+    pub fn rows(&self) -> u32{
+        self.rows
+    }
+
+    pub fn scroll(&mut self, lines: i32){
+        let max = (self.art.height() - self.rows) as i32;
+        self.top = (self.top as i32 + lines).max(0).min(max) as u32;
+    }
+
+    // Scrolls for keys and the mouse wheel. Asks to close on Escape or Q,
+    // and to edit the art on E.
+    pub fn handle(&mut self, event: &Event) -> Request{
+        let page = self.rows as i32;
+        match *event{
+            Event::KeyboardInput(ElementState::Pressed, _, Some(key)) => match key{
+                VirtualKeyCode::Escape   | VirtualKeyCode::Q     => return Request::Close,
+                VirtualKeyCode::E                                => return Request::SwitchMode,
+                VirtualKeyCode::Up                               => self.scroll(-1),
+                VirtualKeyCode::Down                             => self.scroll(1),
+                VirtualKeyCode::PageUp                           => self.scroll(-page),
+                VirtualKeyCode::PageDown | VirtualKeyCode::Space => self.scroll(page),
+                VirtualKeyCode::Home                             => self.top = 0,
+                VirtualKeyCode::End                              => self.scroll(self.art.height() as i32),
+                _ => ()
+            },
+            Event::MouseWheel(MouseScrollDelta::LineDelta(_, y), _) =>
+                self.scroll(-(y * WHEEL_LINES as f32) as i32),
+            Event::Closed => return Request::Close,
+            _ => ()
+        }
+        Request::Continue
+    }
+
+    pub fn draw<F>(&mut self,
+                   display: &F,
+                   program: &glium::Program,
+                   target:  &mut glium::Frame,
+                   atlas:   &Atlas) -> Result<(), TextBlockErr>
+        where F: glium::backend::Facade{
+        let width = self.art.width();
+        let start = (self.top * width) as usize;
+        let end   = ((self.top + self.rows) * width) as usize;
+        let grid  = &self.art.grid;
+        self.view.draw(display, program, target, atlas, width, self.rows,
+                       &grid.cells()[start..end], &grid.attrs()[start..end], &[], None)
+    }
+}